@@ -1,69 +1,90 @@
 use anyhow::Result;
 use camino::Utf8Path;
-use chrono::Local;
-use clippy::database::{get_db, remove_duplicates, ClipEntry, Database};
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use chrono::{Duration, Local};
+use clippy_daemon::{
+    database::{
+        get_db, insert_clip, remove_duplicates, store_clip, ClipEntry, Database, Payload, Selection,
+    },
+    utils::{filter::Filters, mime::guess_mime_type},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use rand::{distributions::Alphanumeric, Rng};
 use shortcut_assert_fs::TmpFs;
 
+const SIZES: [i64; 2] = [10_000, 100_000];
+
 pub fn get_random_string() -> String {
     rand::thread_rng().sample_iter(&Alphanumeric).take(7).map(char::from).collect()
 }
 
-fn create_and_fill_db<F>(amount: i64, func: F) -> Result<()>
-where
-    F: FnOnce(&Database) -> Result<()>,
-{
-    let tf = TmpFs::new()?;
+/// A clip without looking up the active window, which would be timed along with the rest
+fn clip(payload: &[u8]) -> ClipEntry {
+    ClipEntry::with_application(
+        Payload::from([(guess_mime_type(payload), payload.to_vec())]),
+        Selection::Clipboard,
+        None,
+    )
+}
+
+/// A database of `amount` clips, every tenth a repeat of the same one
+fn create_and_fill_db(tf: &TmpFs, amount: i64) -> Result<Database<'static>> {
     let path = tf.path("test");
     let db = get_db(Utf8Path::new(path.as_str()))?;
+    let start = Local::now() - Duration::seconds(amount);
     let tx = db.rw_transaction()?;
 
-    {
-        for i in 0..amount {
-            let payload = match i % 10 {
-                0 => &get_random_string(),
-                _ => "asdf",
-            };
-            tx.insert(ClipEntry {
-                epoch: Local::now().into(),
-                payload: payload.as_bytes().to_vec(),
-                application: None,
-            })?;
-        }
+    for i in 0..amount {
+        let payload = match i % 10 {
+            0 => "asdf".to_string(),
+            _ => get_random_string(),
+        };
+        let mut entry = clip(payload.as_bytes());
+        entry.epoch = (start + Duration::seconds(i)).into();
+        insert_clip(&tx, entry)?;
     }
     tx.commit()?;
 
-    func(&db)
+    Ok(db)
 }
 
-#[allow(clippy::iter_skip_zero)]
-fn remove_dupes_old(c: &mut Criterion) {
-    let amount: i64 = black_box(100);
-    let dedupe_amount: i64 = black_box(10);
-    c.bench_function("dupes", |b| {
-        b.iter(|| {
-            create_and_fill_db(amount, |db| {
-                remove_duplicates(db, dedupe_amount)?;
-                Ok(())
+/// Storing a clip then scanning every clip for duplicates, as done before the hash index
+fn full_pass(c: &mut Criterion) {
+    let mut group = c.benchmark_group("full_pass");
+    group.sample_size(10);
+
+    for amount in SIZES {
+        let tf = TmpFs::new().unwrap();
+        let db = create_and_fill_db(&tf, amount).unwrap();
+        remove_duplicates(&db, 0).unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(amount), &db, |b, db| {
+            b.iter(|| {
+                let tx = db.rw_transaction().unwrap();
+                insert_clip(&tx, clip(b"asdf")).unwrap();
+                tx.commit().unwrap();
+                remove_duplicates(db, 0).unwrap();
             })
-        })
-    });
+        });
+    }
+    group.finish();
 }
 
-#[allow(clippy::iter_skip_zero)]
-fn remove_dupes_iter(c: &mut Criterion) {
-    let amount: i64 = black_box(100);
-    let dedupe_amount: i64 = black_box(10);
-    c.bench_function("dupes", |b| {
-        b.iter(|| {
-            create_and_fill_db(amount, |db| {
-                remove_duplicates(db, dedupe_amount)?;
-                Ok(())
-            })
-        })
-    });
+/// Storing a clip with its duplicates found through the hash index
+fn on_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("on_insert");
+    group.sample_size(10);
+
+    for amount in SIZES {
+        let tf = TmpFs::new().unwrap();
+        let db = create_and_fill_db(&tf, amount).unwrap();
+        remove_duplicates(&db, 0).unwrap();
+
+        group.bench_with_input(BenchmarkId::from_parameter(amount), &db, |b, db| {
+            b.iter(|| store_clip(db, clip(b"asdf"), &Filters::default(), 0, 0).unwrap())
+        });
+    }
+    group.finish();
 }
 
-criterion_group!(benches, remove_dupes_old, remove_dupes_iter);
+criterion_group!(benches, full_pass, on_insert);
 criterion_main!(benches);
@@ -1,7 +1,11 @@
 use camino::Utf8PathBuf;
 use clap::{Parser, Subcommand, ValueHint::AnyPath};
+use clippy_daemon::utils::logging::LogFormat;
 
-use crate::{commands, utils::get_cache_path};
+use crate::{
+    commands,
+    utils::{default_db_path, default_duplicates, default_keep},
+};
 
 #[derive(Subcommand, Debug, PartialEq)]
 pub enum Commands {
@@ -13,6 +17,21 @@ pub enum Commands {
     Wipe(commands::Wipe),
     Remove(commands::Remove),
     Version(commands::Version),
+    Watch(commands::Watch),
+    Daemon(commands::Daemon),
+    Status(commands::Status),
+    Pause(commands::Pause),
+    Resume(commands::Resume),
+    Filter(commands::Filter),
+    Pin(commands::Pin),
+    Unpin(commands::Unpin),
+    Snippet(commands::Snippet),
+    Pick(commands::Pick),
+    Export(commands::Export),
+    Import(commands::Import),
+    Encrypt(commands::Encrypt),
+    Prune(commands::Prune),
+    Preview(commands::Preview),
 }
 
 pub const APP_NAME: &str = "clippy";
@@ -26,26 +45,39 @@ pub struct ClippyCli {
 
     #[arg(
         long,
-        default_value = get_cache_path("clippy", "db").unwrap(),
+        default_value = default_db_path(),
         value_hint(AnyPath)
     )]
-    /// Path to the local database used to store previous clips
+    /// Path to the local database used to store previous clips. Defaults to `db_path` from the
+    /// config, the database the daemon records into
     pub db_path: Utf8PathBuf,
 
-    /// Number of most recent duplicates to keep. Negative values remove x oldest duplicates instead.
+    /// Amount of clips to remove duplicates among. Defaults to the `[clipboard]` config.
     ///
-    /// Positive values keep x amount of most recent duplicates.
-    /// Negative values remove x amount of duplicates from the end.
+    /// Negative values remove duplicates among x newest clips, keeping the newest copy.
+    /// Positive values remove duplicates among x oldest clips, keeping the oldest copy.
     /// 0 will retain only unique clips. Removing any duplicates.
-    #[arg(short, long, alias("dupes"), default_value = "0")]
+    #[arg(
+        short,
+        long,
+        alias("dupes"),
+        allow_negative_numbers(true),
+        default_value = default_duplicates()
+    )]
     pub duplicates: i64,
 
-    /// Amount of clips to keep in database
-    #[arg(short, long, default_value = "1000")]
+    /// Amount of clips to keep in database, the oldest are removed past it. 0 keeps every clip.
+    /// Defaults to `max_size` from the `[clipboard]` config
+    #[arg(short, long, default_value = default_keep())]
     pub keep: u64,
 
+    /// Log more, may be repeated. `CLIPPY_LOG` takes precedence, ie: `clippy=trace`
     #[arg(short, action = clap::ArgAction::Count)]
-    verbose: u8,
+    pub verbose: u8,
+
+    /// Format of the log written to `stderr`
+    #[arg(long, value_enum, default_value_t)]
+    pub log_format: LogFormat,
 }
 
 #[cfg(test)]
@@ -0,0 +1,280 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use clippy_daemon::{
+    database::Selection,
+    ipc::Client,
+    utils::{
+        logging::{log_dir, LogFormat},
+        pidfile::{pid_path, running_pid},
+    },
+};
+
+use super::ClippyCommand;
+use crate::cli::ClippyCli;
+
+/// How long `start` waits for the daemon to answer and `stop` for it to exit
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Parser, Debug, PartialEq)]
+/// Starts, stops and checks on the daemon watching the clipboard.
+///
+/// Only one daemon runs at a time, it holds a lock on `clippy.pid` in the runtime directory
+pub struct Daemon {
+    #[command(subcommand)]
+    command: DaemonCommands,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+enum DaemonCommands {
+    /// Starts the daemon in the background unless it's already running. It logs to `logs` in
+    /// the cache directory, as verbosely as `clippy` was asked to
+    Start(Boards),
+    /// Asks the daemon to stop and waits for it to exit
+    Stop,
+    /// Stops the daemon if it's running then starts it again
+    Restart(Boards),
+    /// Shows whether the daemon is running
+    Status,
+    /// Outputs a systemd user unit running the daemon along with the graphical session
+    Unit(Unit),
+}
+
+#[derive(Args, Debug, PartialEq)]
+struct Boards {
+    /// Selections to record. Defaults to `general.selections` from the config
+    #[arg(value_enum)]
+    board: Vec<Selection>,
+}
+
+#[derive(Args, Debug, PartialEq)]
+struct Unit {
+    #[command(flatten)]
+    boards: Boards,
+    /// Write the unit to `systemd/user/clippy.service` in the config directory instead. Enable it
+    /// with `systemctl --user enable --now clippy`
+    #[arg(long)]
+    install: bool,
+}
+
+impl ClippyCommand for Daemon {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        match &self.command {
+            DaemonCommands::Start(boards) => start(&daemon_args(&boards.board, args)),
+            DaemonCommands::Stop => stop(),
+            DaemonCommands::Restart(boards) => {
+                stop()?;
+                start(&daemon_args(&boards.board, args))
+            },
+            DaemonCommands::Status => status(),
+            DaemonCommands::Unit(unit) =>
+                install_unit(&daemon_args(&unit.boards.board, args), unit),
+        }
+    }
+}
+
+fn pid_file() -> Result<String> {
+    pid_path().ok_or_else(|| anyhow!("Unable to determine pid file path"))
+}
+
+fn logs() -> Result<String> {
+    log_dir().ok_or_else(|| anyhow!("Unable to determine log directory"))
+}
+
+/// Starts the daemon with `args` and waits until it answers requests
+pub(crate) fn start(args: &[String]) -> Result<()> {
+    if let Some(pid) = running_pid(&pid_file()?)? {
+        println!("Daemon is already running with pid {pid}");
+        return Ok(());
+    }
+
+    let log_path = logs()?;
+    let mut child = run_in_background(&daemon_path(), args)?;
+    let started = Instant::now();
+
+    while started.elapsed() < TIMEOUT {
+        if let Some(status) = child.try_wait()? {
+            return Err(anyhow!("Daemon exited with {status}, see {log_path}"));
+        }
+        if Client::connect().is_some() {
+            println!("Started daemon with pid {}", child.id());
+            return Ok(());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    println!(
+        "Daemon with pid {} is still starting, see {log_path}",
+        child.id()
+    );
+    Ok(())
+}
+
+fn stop() -> Result<()> {
+    let pid_file = pid_file()?;
+    let Some(pid) = running_pid(&pid_file)? else {
+        println!("Daemon is not running");
+        return Ok(());
+    };
+
+    Client::connect()
+        .ok_or_else(|| anyhow!("Daemon with pid {pid} isn't answering, stop it with `kill {pid}`"))?
+        .shutdown()?;
+
+    let stopping = Instant::now();
+    while running_pid(&pid_file)?.is_some() {
+        if stopping.elapsed() > TIMEOUT {
+            return Err(anyhow!(
+                "Daemon with pid {pid} didn't stop, stop it with `kill {pid}`"
+            ));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    println!("Stopped daemon with pid {pid}");
+    Ok(())
+}
+
+fn status() -> Result<()> {
+    match running_pid(&pid_file()?)? {
+        Some(pid) => {
+            println!("state: running");
+            println!("pid: {pid}");
+        },
+        None => println!("state: stopped"),
+    }
+    println!("logs: {}", logs()?);
+
+    Ok(())
+}
+
+fn install_unit(args: &[String], unit: &Unit) -> Result<()> {
+    let contents = unit_file(&daemon_path(), args);
+
+    if !unit.install {
+        print!("{contents}");
+        return Ok(());
+    }
+
+    let path = dirs::config_dir()
+        .ok_or_else(|| anyhow!("Unable to determine config directory"))?
+        .join("systemd/user/clippy.service");
+    fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+    fs::write(&path, contents)?;
+    println!("Wrote {}", path.display());
+    println!("Enable it with `systemctl --user enable --now clippy`");
+
+    Ok(())
+}
+
+/// Arguments to record `boards`, logging as verbosely and in the same format as `cli`
+pub(crate) fn daemon_args(boards: &[Selection], cli: &ClippyCli) -> Vec<String> {
+    let boards = boards.iter().flat_map(|board| ["--board".to_string(), board.to_string()]);
+    let verbosity = (cli.verbose > 0).then(|| format!("-{}", "v".repeat(cli.verbose.into())));
+    let format = (cli.log_format != LogFormat::default())
+        .then(|| cli.log_format.to_possible_value())
+        .flatten()
+        .map(|format| format!("--log-format={}", format.get_name()));
+
+    boards.chain(verbosity).chain(format).collect()
+}
+
+/// `clippy_daemon` installed next to this binary, otherwise the first one in `PATH`
+fn daemon_path() -> PathBuf {
+    let name = format!("clippy_daemon{}", env::consts::EXE_SUFFIX);
+    let beside = env::current_exe().ok().map(|exe| exe.with_file_name(&name));
+    let in_path = || {
+        env::split_paths(&env::var_os("PATH")?)
+            .map(|dir| dir.join(&name))
+            .find(|path| path.exists())
+    };
+
+    beside
+        .filter(|path| path.exists())
+        .or_else(in_path)
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+fn unit_file(daemon: &Path, args: &[String]) -> String {
+    let exec_start = [format!("\"{}\"", daemon.display())]
+        .into_iter()
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "[Unit]
+Description=Clipboard history for clippy
+Documentation=https://github.com/JustinBacher/clippy
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+ExecStart={exec_start}
+Restart=on-failure
+
+[Install]
+WantedBy=graphical-session.target
+"
+    )
+}
+
+/// Its output is dropped, the daemon writes its own log files
+#[cfg(target_os = "windows")]
+fn run_in_background(command: &Path, args: &[String]) -> Result<Child> {
+    use std::os::windows::process::CommandExt;
+
+    Ok(Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(
+            winapi::um::winbase::DETACHED_PROCESS | winapi::um::winbase::CREATE_NEW_PROCESS_GROUP,
+        )
+        .spawn()?)
+}
+
+/// Its output is dropped, the daemon writes its own log files
+#[cfg(target_family = "unix")]
+fn run_in_background(command: &Path, args: &[String]) -> Result<Child> {
+    Ok(Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::cli::mock_cli;
+
+    #[test]
+    fn it_writes_systemd_units() {
+        let cli = mock_cli(["-vv", "--log-format", "json", "daemon", "unit"].into_iter()).unwrap();
+        let args = daemon_args(&[Selection::Clipboard, Selection::Primary], &cli);
+        let unit = unit_file(Path::new("/usr/bin/clippy_daemon"), &args);
+        let exec_start = unit.lines().find(|line| line.starts_with("ExecStart="));
+
+        assert_eq!(
+            exec_start,
+            Some(
+                "ExecStart=\"/usr/bin/clippy_daemon\" --board clipboard --board primary -vv \
+                --log-format=json"
+            )
+        );
+        assert!(unit.contains("WantedBy=graphical-session.target"));
+    }
+}
@@ -0,0 +1,50 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use clippy_daemon::{
+    database::encryption::{decrypt, encrypt},
+    ipc::Client,
+};
+
+use super::ClippyCommand;
+use crate::{cli::ClippyCli, history::open_db, utils::load_config};
+
+#[derive(Parser, Debug, PartialEq)]
+/// Encrypts an existing database with the key from `[general.encryption]` in the config.
+///
+/// New databases are encrypted as soon as they're created, this is only needed for one that
+/// already has clips. Stop the daemon first
+pub struct Encrypt {
+    /// Writes an encrypted database back in plaintext instead, ie: before turning encryption off
+    #[arg(long)]
+    decrypt: bool,
+}
+
+impl ClippyCommand for Encrypt {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        if let Some(mut client) = Client::connect() {
+            if client.status().is_ok_and(|status| status.db_path == args.db_path.as_str()) {
+                return Err(anyhow!("Stop the daemon before changing its database"));
+            }
+        }
+
+        let encryption = load_config()?.encryption().ok_or_else(|| {
+            anyhow!("Set `[general.encryption]` in config.toml to choose where the key comes from")
+        })?;
+        let mut db = match self.decrypt {
+            true => {
+                let db = open_db(&args.db_path, Some(&encryption))?;
+                decrypt(&db)?;
+                db
+            },
+            false => {
+                let db = open_db(&args.db_path, None)?;
+                encrypt(&db, &encryption)?;
+                db
+            },
+        };
+        // Rewritten records leave their old copies in freed pages until they're compacted away
+        db.compact()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use std::{
+    fs::File,
+    io::{stdout, BufWriter, Write},
+};
+
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use clap::{Parser, ValueHint::FilePath};
+
+use super::ClippyCommand;
+use crate::{
+    cli::ClippyCli,
+    history::History,
+    utils::archive::{write, ArchiveFormat},
+};
+
+#[derive(Parser, Debug, PartialEq)]
+/// Writes every clip to an archive that `clippy import` can read back.
+///
+/// Each clip is an object with its `timestamp`, `application`, `selection`, `pinned` and a
+/// `payload` of base64 encoded bytes by MIME type. See the README for the full format
+pub struct Export {
+    /// File to write the archive to instead of `stdout`
+    #[arg(short, long, value_hint(FilePath))]
+    output: Option<Utf8PathBuf>,
+
+    #[arg(short, long, value_enum, default_value_t)]
+    format: ArchiveFormat,
+}
+
+impl ClippyCommand for Export {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        let mut history = History::open(&args.db_path)?;
+        let mut clips = history.list(None, false)?;
+        history.load(&mut clips, |_| true)?;
+        let mut out: Box<dyn Write> = match &self.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(stdout().lock()),
+        };
+
+        write(&mut out, &clips, self.format)
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use clippy_daemon::{
+    database::{ClipEntry, Payload, Selection},
+    utils::{
+        config::Config,
+        filter::{Decision, Filters},
+        mime::TEXT_MIME_TYPE,
+    },
+};
+
+use super::ClippyCommand;
+use crate::{cli::ClippyCli, utils::load_config};
+
+#[derive(Parser, Debug, PartialEq)]
+/// Works with the include and exclude rules from the config
+pub struct Filter {
+    #[command(subcommand)]
+    command: FilterCommands,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+enum FilterCommands {
+    Test(Test),
+}
+
+#[derive(Parser, Debug, PartialEq)]
+/// Shows whether the daemon would store a clip and which rule decided it
+struct Test {
+    /// Text of the clip
+    text: String,
+
+    /// Title of the window the clip was copied from
+    #[arg(short, long)]
+    app: Option<String>,
+
+    /// MIME type the clip is offered as, may be repeated
+    #[arg(short, long, default_value = TEXT_MIME_TYPE)]
+    mime_type: Vec<String>,
+}
+
+impl ClippyCommand for Filter {
+    fn execute(&self, _: &ClippyCli) -> Result<()> {
+        let FilterCommands::Test(test) = &self.command;
+        println!("{}", describe(&test.check(&load_config()?)?));
+
+        Ok(())
+    }
+}
+
+impl Test {
+    fn check(&self, config: &Config) -> Result<Decision> {
+        let payload = self
+            .mime_type
+            .iter()
+            .map(|mime_type| (mime_type.clone(), self.text.as_bytes().to_vec()))
+            .collect::<Payload>();
+        let entry = ClipEntry::with_application(payload, Selection::Clipboard, self.app.clone());
+
+        Ok(Filters::new(&config.clipboard())?.check(&entry))
+    }
+}
+
+fn describe(decision: &Decision) -> String {
+    match decision {
+        Decision::Store => "Stored, no rule matched".to_string(),
+        Decision::Included(rule) => format!("Stored by {rule}"),
+        Decision::Excluded(rule) => format!("Ignored by {rule}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::cli::{mock_cli, Commands};
+
+    #[test]
+    fn it_explains_decisions() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+            [clipboard.default.exclude.terminals]
+            applications = ["kitty"]
+            "#,
+        )?;
+        let Some(Commands::Filter(filter)) =
+            mock_cli(["filter", "test", "hunter2", "--app", "kitty"].into_iter())
+                .map(|cli| cli.command)
+        else {
+            panic!("Expected a filter command");
+        };
+        let FilterCommands::Test(test) = filter.command;
+
+        assert_eq!(
+            describe(&test.check(&config)?),
+            r#"Ignored by exclude.terminals.applications = "kitty""#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    fs::{self, File},
+    io::{stdin, BufReader},
+};
+
+use anyhow::{anyhow, Result};
+use camino::Utf8PathBuf;
+use chrono::{Duration, Local};
+use clap::{Parser, ValueEnum, ValueHint::FilePath};
+use clippy_daemon::{
+    database::{ClipEntry, Payload, Selection},
+    utils::{filter::Filters, limits::SizeLimits, mime::guess_mime_type},
+};
+
+use super::{store::check, ClippyCommand};
+use crate::{
+    cli::ClippyCli,
+    history::History,
+    utils::{archive::read, cliphist::read_history, load_config},
+};
+
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum Source {
+    /// An archive written by `clippy export`
+    #[default]
+    Clippy,
+    /// The database of cliphist, `~/.cache/cliphist/db` by default
+    Cliphist,
+}
+
+#[derive(Parser, Debug, PartialEq)]
+/// Adds the clips of an archive or of another clipboard manager to history.
+///
+/// Clips keep the time they were copied at and go through the same duplicate, size, include and
+/// exclude rules as newly copied ones. Clips already in history, by timestamp, are skipped
+pub struct Import {
+    /// Archive or database to import, read from `stdin` when left out for `clippy` archives
+    #[arg(value_hint(FilePath))]
+    path: Option<Utf8PathBuf>,
+
+    /// What wrote the file being imported
+    #[arg(long, value_enum, default_value_t)]
+    from: Source,
+}
+
+impl ClippyCommand for Import {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        let entries = match self.from {
+            Source::Clippy => match &self.path {
+                Some(path) => read(&mut BufReader::new(File::open(path)?))?,
+                None => read(&mut stdin().lock())?,
+            },
+            Source::Cliphist => {
+                let path = match &self.path {
+                    Some(path) => path.clone().into_std_path_buf(),
+                    None => dirs::cache_dir()
+                        .ok_or_else(|| anyhow!("Couldn't find the cache directory of cliphist"))?
+                        .join("cliphist/db"),
+                };
+                from_cliphist(read_history(&fs::read(path)?)?)
+            },
+        };
+
+        let total = entries.len();
+        let config = load_config()?;
+        let limits = SizeLimits::current(&config);
+        let entries = entries
+            .into_iter()
+            .filter_map(|mut entry| check(&mut entry, &limits).is_ok().then_some(entry))
+            .collect::<Vec<_>>();
+        let skipped = total - entries.len();
+        let filters = Filters::new(&config.clipboard())?;
+        let imported =
+            History::open(&args.db_path)?.import(entries, &filters, args.duplicates, args.keep)?;
+
+        println!("Imported {imported} of {total} clips");
+        if skipped > 0 {
+            println!("Skipped {skipped} empty or too large clips");
+        }
+        Ok(())
+    }
+}
+
+/// cliphist doesn't record when clips were copied, so they're spaced a microsecond apart up to
+/// now to keep their order
+fn from_cliphist(payloads: Vec<Vec<u8>>) -> Vec<ClipEntry> {
+    let now = Local::now();
+    let count = payloads.len() as i64;
+
+    payloads
+        .into_iter()
+        .zip(0..)
+        .map(|(payload, i)| {
+            let payload = Payload::from([(guess_mime_type(&payload), payload)]);
+            let mut entry = ClipEntry::with_application(payload, Selection::Clipboard, None);
+            entry.epoch = (now - Duration::microseconds(count - i)).into();
+            entry
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_orders_cliphist_clips() {
+        let entries = from_cliphist(vec![b"old".to_vec(), b"".to_vec(), b"new".to_vec()]);
+
+        assert!(entries.windows(2).all(|pair| pair[0].epoch.0 < pair[1].epoch.0));
+        let limits = SizeLimits::default();
+        let storable = entries.into_iter().filter_map(|mut entry| check(&mut entry, &limits).ok());
+        assert_eq!(storable.count(), 2);
+    }
+}
@@ -1,10 +1,16 @@
+use std::io::stdout;
+
 use anyhow::Result;
 use clap::{Parser, ValueEnum};
-use clippy_daemon::database::{get_db, ClipEntry, TableLen};
+use clippy_daemon::database::{ClipEntry, Selection};
 use serde::Serialize;
 
 use super::ClippyCommand;
-use crate::{cli::ClippyCli, utils::formatting::format_entry};
+use crate::{
+    cli::ClippyCli,
+    history::History,
+    utils::{output::Output, thumbnails::Thumbnails},
+};
 
 #[derive(ValueEnum, Parser, Clone, Default, PartialEq, Debug, Serialize)]
 #[serde(rename_all = "lowercase")]
@@ -16,48 +22,60 @@ pub enum ClipboardState {
     Sensitive,
 }
 
+#[derive(ValueEnum, Clone, Copy, PartialEq, Debug)]
+pub enum Pinned {
+    /// List pinned clips before the rest
+    First,
+    /// List pinned clips only
+    Only,
+}
+
 #[derive(Parser, Debug, PartialEq)]
 /// Lists all stored clips in clipboard
 pub struct List {
-    /// Includes dates clips were taken in the output
-    #[arg(short('d'), long, action, default_value = "false")]
-    include_dates: bool,
-
-    /// Max characters to show of clips in preview. Use 0 to retain original width.
-    ///
-    /// This does not affect what is put back into the clipboard
-    #[arg(short('w'), long, default_value = "100")]
-    preview_width: usize,
+    #[command(flatten)]
+    output: Output,
+
+    /// Only list clips copied into this selection
+    #[arg(short, long, value_enum)]
+    selection: Option<Selection>,
+    /// Also list sensitive clips the daemon only keeps in memory
+    #[arg(long)]
+    sensitive: bool,
+    /// Where pinned clips go in the listing. By default they're listed among the rest
+    #[arg(short, long, value_enum)]
+    pinned: Option<Pinned>,
 }
 
 impl ClippyCommand for List {
     fn execute(&self, args: &ClippyCli) -> Result<()> {
-        let db = get_db(&args.db_path)?;
-        let tx = db.r_transaction()?;
-
-        if tx.length()? == 0 {
-            println!("Clipboard is empty");
-            return Ok(());
-        }
-
-        tx.scan()
-            .primary::<ClipEntry>()?
-            .all()?
-            .flatten()
-            .enumerate()
-            .for_each(|(i, entry)| {
-                let preview = format_entry(&entry, self.preview_width, self.include_dates);
-                println!("{i} {}", preview);
-            });
-
-        Ok(())
+        let mut history = History::open(&args.db_path)?;
+        let mut clips = order_pinned(history.list(self.selection, self.sensitive)?, self.pinned);
+        let thumbnails = Thumbnails::of_db(&args.db_path)?;
+        history.load(&mut clips, |entry| self.output.needs_image(entry, &thumbnails))?;
+
+        self.output.write(&mut stdout(), &clips, "Clipboard is empty", &thumbnails)
+    }
+}
+
+fn order_pinned(clips: Vec<ClipEntry>, pinned: Option<Pinned>) -> Vec<ClipEntry> {
+    let Some(pinned) = pinned else {
+        return clips;
+    };
+    let (pinned_clips, rest): (Vec<_>, Vec<_>) = clips.into_iter().partition(|entry| entry.pinned);
+
+    match pinned {
+        Pinned::First => pinned_clips.into_iter().chain(rest).collect(),
+        Pinned::Only => pinned_clips,
     }
 }
 
 #[cfg(test)]
 mod test {
     use clippy_daemon::database::testing::{fill_db_and_test, get_db_contents, FillWith};
+    use pretty_assertions::assert_eq;
 
+    use super::*;
     use crate::cli::mock_cli;
 
     #[test]
@@ -67,8 +85,31 @@ mod test {
 
             let after = get_db_contents(db)?;
 
-            Ok(assert_eq!(after, before))
+            assert_eq!(after, before);
+            Ok(())
         })
         .unwrap();
     }
+
+    #[test]
+    fn it_orders_pinned_clips() {
+        let clips = (1..=4)
+            .map(|id| {
+                let mut entry = ClipEntry::new(id.to_string().as_bytes());
+                entry.id = Some(id);
+                entry.pinned = id % 2 == 0;
+                entry
+            })
+            .collect::<Vec<_>>();
+        let ids = |pinned| {
+            order_pinned(clips.clone(), pinned)
+                .iter()
+                .map(|entry| entry.id.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(None), [1, 2, 3, 4]);
+        assert_eq!(ids(Some(Pinned::First)), [2, 4, 1, 3]);
+        assert_eq!(ids(Some(Pinned::Only)), [2, 4]);
+    }
 }
@@ -1,8 +1,21 @@
 pub mod completions;
+pub mod daemon;
+pub mod encrypt;
+pub mod export;
+pub mod filter;
+pub mod import;
 pub mod list;
+pub mod pause;
+pub mod pick;
+pub mod pin;
+pub mod preview;
+pub mod prune;
 pub mod recall;
 pub mod remove;
+pub mod resume;
 pub mod search;
+pub mod snippet;
+pub mod status;
 pub mod store;
 pub mod version;
 pub mod watch;
@@ -12,11 +25,24 @@ use std::{ops::Sub, path::Path, str::FromStr};
 
 use anyhow::Result;
 pub use completions::GenCompletions;
+pub use daemon::Daemon;
 use derive_more::Display;
+pub use encrypt::Encrypt;
+pub use export::Export;
+pub use filter::Filter;
+pub use import::Import;
 pub use list::List;
+pub use pause::Pause;
+pub use pick::Pick;
+pub use pin::{Pin, Unpin};
+pub use preview::Preview;
+pub use prune::Prune;
 pub use recall::Recall;
 pub use remove::Remove;
+pub use resume::Resume;
 pub use search::Search;
+pub use snippet::Snippet;
+pub use status::Status;
 pub use store::Store;
 pub use version::Version;
 pub use watch::Watch;
@@ -25,13 +51,7 @@ pub use wipe::Wipe;
 use crate::{cli::ClippyCli, utils::get_config_path};
 
 pub trait ClippyCommand {
-    fn execute(&self, _: &ClippyCli) -> Result<()> {
-        panic!(
-            "Oops! you weren't supposed to be able to see this. \
-            Please submit an issue here: \
-            https://github.com/JustinBacher/clippy/issues"
-        )
-    }
+    fn execute(&self, args: &ClippyCli) -> Result<()>;
 }
 
 pub fn is_fresh_run() -> Result<bool> {
@@ -94,6 +114,12 @@ impl From<GreedyInt> for usize {
     }
 }
 
+impl From<GreedyInt> for u64 {
+    fn from(data: GreedyInt) -> Self {
+        data.0 as u64
+    }
+}
+
 impl Sub<usize> for &GreedyInt {
     type Output = usize;
 
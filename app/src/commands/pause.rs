@@ -0,0 +1,16 @@
+use anyhow::Result;
+use clap::Parser;
+use clippy_daemon::ipc::Client;
+
+use super::ClippyCommand;
+use crate::{cli::ClippyCli, error::ClippyError};
+
+#[derive(Parser, Debug, PartialEq)]
+/// Stops the daemon from recording new clips until `resume` is called
+pub struct Pause {}
+
+impl ClippyCommand for Pause {
+    fn execute(&self, _: &ClippyCli) -> Result<()> {
+        Client::connect().ok_or(ClippyError::DaemonUnavailable)?.pause()
+    }
+}
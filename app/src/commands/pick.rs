@@ -0,0 +1,446 @@
+use std::io::stdout;
+
+use anyhow::Result;
+use clap::Parser;
+use clippy_daemon::{
+    database::{
+        search::{IndexedClip, Matcher, Mode, Query},
+        ClipEntry, Selection,
+    },
+    platforms::set_clipboard,
+};
+use promkit::{
+    crossterm::{
+        event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        style::{Attribute, Attributes, Color, ContentStyle},
+    },
+    grapheme::StyledGraphemes,
+    listbox::{self, Listbox},
+    pane::Pane,
+    style::StyleBuilder,
+    text_editor, Finalizer, PaneFactory, Prompt, PromptSignal, Renderer,
+};
+
+use super::{recall::write_clip, ClippyCommand};
+use crate::{
+    cli::ClippyCli,
+    error::ClippyError,
+    history::History,
+    utils::formatting::{describe, truncate},
+};
+
+/// Rows given to the highlighted clip below the list
+const PREVIEW_LINES: usize = 10;
+const LIST_LINES: usize = 10;
+const HELP: &str =
+    "enter: select  ctrl+y: copy  ctrl+o: print  ctrl+t: pin/unpin  ctrl+d: delete  esc: quit";
+
+#[derive(Parser, Debug, PartialEq)]
+/// Opens a picker to filter clips as you type, then copies the chosen one into the clipboard.
+///
+/// The highlighted clip can also be printed, pinned or deleted, see the help line at the bottom
+pub struct Pick {
+    /// Only show clips copied into this selection
+    #[arg(short, long, value_enum)]
+    selection: Option<Selection>,
+    /// Also show sensitive clips the daemon only keeps in memory
+    #[arg(long)]
+    sensitive: bool,
+    /// How the query is matched against clips. Qualifiers like `app:firefox` work too
+    #[arg(short, long, value_enum, default_value_t = Mode::Fuzzy)]
+    mode: Mode,
+    /// Ignore case when matching
+    #[arg(short, long)]
+    ignore_case: bool,
+    /// Print the chosen clip to `stdout` on enter instead of copying it
+    #[arg(short, long)]
+    print: bool,
+}
+
+impl ClippyCommand for Pick {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        let mut history = History::open(&args.db_path)?;
+        let clips = history.list(self.selection, self.sensitive)?;
+
+        if clips.is_empty() {
+            return Err(ClippyError::EmptyHistory.into());
+        }
+
+        let picker = Picker::new(clips, self.mode, self.ignore_case);
+        // The prompt has to be gone before copying so the terminal is restored first
+        let outcome = Prompt {
+            renderer: PickerRenderer::new(history, picker, self.print),
+        }
+        .run()?;
+
+        match outcome {
+            Some(Outcome::Copy(clip)) => set_clipboard(&clip.payload, Selection::Clipboard),
+            Some(Outcome::Print(clip)) => write_clip(&mut stdout(), &clip, None),
+            None => Ok(()),
+        }
+    }
+}
+
+enum Outcome {
+    Copy(ClipEntry),
+    Print(ClipEntry),
+}
+
+/// Clips offered by the picker, newest first, and which of them match the query
+struct Picker {
+    clips: Vec<(ClipEntry, IndexedClip)>,
+    mode: Mode,
+    ignore_case: bool,
+    /// Indices into `clips` in the order they're shown
+    visible: Vec<usize>,
+}
+
+impl Picker {
+    fn new(mut clips: Vec<ClipEntry>, mode: Mode, ignore_case: bool) -> Self {
+        clips.sort_by_key(|entry| std::cmp::Reverse(entry.epoch.0));
+
+        Self {
+            visible: (0..clips.len()).collect(),
+            clips: clips
+                .into_iter()
+                .map(|entry| {
+                    let indexed = IndexedClip::from(&entry);
+                    (entry, indexed)
+                })
+                .collect(),
+            mode,
+            ignore_case,
+        }
+    }
+
+    /// Fuzzy matches are ranked best first, anything else stays newest first
+    fn filter(&mut self, query: &str) -> Result<()> {
+        let matcher = Matcher::new(&Query::parse(query, self.mode, self.ignore_case)?)?;
+        let mut scored = self
+            .clips
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (_, indexed))| Some((index, matcher.score(indexed)?)))
+            .collect::<Vec<_>>();
+
+        if self.mode == Mode::Fuzzy {
+            scored.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        }
+
+        self.visible = scored.into_iter().map(|(index, _)| index).collect();
+        Ok(())
+    }
+
+    fn get(&self, position: usize) -> Option<&ClipEntry> {
+        self.visible.get(position).map(|index| &self.clips[*index].0)
+    }
+
+    /// Swaps in a changed clip, ie: one that was just pinned
+    fn replace(&mut self, entry: ClipEntry) {
+        if let Some((clip, _)) = self.clips.iter_mut().find(|(clip, _)| clip.id == entry.id) {
+            *clip = entry;
+        }
+    }
+
+    fn remove(&mut self, id: Option<u64>) {
+        let Some(index) = self.clips.iter().position(|(clip, _)| clip.id == id) else {
+            return;
+        };
+
+        self.clips.remove(index);
+        self.visible.retain(|visible| *visible != index);
+        self.visible.iter_mut().filter(|visible| **visible > index).for_each(|visible| {
+            *visible -= 1;
+        });
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.visible
+            .iter()
+            .map(|index| {
+                let clip = &self.clips[*index].0;
+                let summary = describe(clip).lines().next().unwrap_or_default().to_string();
+                let pin = if clip.pinned { "* " } else { "" };
+
+                format!(
+                    "{} {pin}{}",
+                    clip.id.unwrap_or_default(),
+                    truncate(&summary, 100)
+                )
+            })
+            .collect()
+    }
+}
+
+struct PickerRenderer {
+    history: History,
+    picker: Picker,
+    query: text_editor::State,
+    listbox: listbox::State,
+    /// Result of the last action or why the query couldn't be used
+    message: String,
+    print: bool,
+    outcome: Option<Outcome>,
+}
+
+impl PickerRenderer {
+    fn new(history: History, picker: Picker, print: bool) -> Self {
+        Self {
+            query: text_editor::State {
+                texteditor: Default::default(),
+                history: None,
+                prefix: String::from("❯❯ "),
+                mask: None,
+                prefix_style: StyleBuilder::new().fgc(Color::DarkGreen).build(),
+                active_char_style: StyleBuilder::new().bgc(Color::DarkCyan).build(),
+                inactive_char_style: StyleBuilder::new().build(),
+                edit_mode: Default::default(),
+                word_break_chars: Default::default(),
+                lines: Some(1),
+            },
+            listbox: listbox::State {
+                listbox: Listbox::from_iter(picker.lines()),
+                cursor: String::from("❯ "),
+                active_item_style: Some(StyleBuilder::new().fgc(Color::DarkCyan).build()),
+                inactive_item_style: Some(StyleBuilder::new().build()),
+                lines: Some(LIST_LINES),
+            },
+            history,
+            picker,
+            message: String::new(),
+            print,
+            outcome: None,
+        }
+    }
+
+    fn selected(&self) -> Option<&ClipEntry> {
+        self.picker.get(self.listbox.listbox.position())
+    }
+
+    /// Rebuilds the list, keeping the cursor where it was when possible
+    fn refresh(&mut self, position: usize) {
+        self.listbox.listbox = Listbox::from_iter(self.picker.lines());
+        for _ in 0..position {
+            if !self.listbox.listbox.forward() {
+                break;
+            }
+        }
+    }
+
+    fn toggle_pin(&mut self) -> Result<()> {
+        let Some(clip) = self.selected() else {
+            return Ok(());
+        };
+        let (id, pinned) = (clip.id.unwrap_or_default(), !clip.pinned);
+
+        if let Some(updated) = self.history.pin(id, pinned)? {
+            self.picker.replace(updated);
+            self.message = format!("{} {id}", if pinned { "Pinned" } else { "Unpinned" });
+        }
+        self.refresh(self.listbox.listbox.position());
+        Ok(())
+    }
+
+    fn delete(&mut self) -> Result<()> {
+        let Some(id) = self.selected().and_then(|clip| clip.id) else {
+            return Ok(());
+        };
+
+        self.history.remove(id)?;
+        self.picker.remove(Some(id));
+        self.message = format!("Deleted {id}");
+        self.refresh(self.listbox.listbox.position());
+        Ok(())
+    }
+
+    /// Quits with the selected clip recalled whole, listed clips leave some of it in files
+    fn choose(&mut self, outcome: fn(ClipEntry) -> Outcome) -> PromptSignal {
+        let Some(mut clip) = self.selected().cloned() else {
+            return PromptSignal::Quit;
+        };
+
+        match self.history.load(std::slice::from_mut(&mut clip), |_| true) {
+            Ok(()) => {
+                self.outcome = Some(outcome(clip));
+                PromptSignal::Quit
+            },
+            Err(e) => {
+                self.message = e.to_string();
+                PromptSignal::Continue
+            },
+        }
+    }
+
+    fn filter(&mut self) {
+        let query = self.query.texteditor.text_without_cursor().to_string();
+
+        self.message = match self.picker.filter(&query) {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        };
+        self.refresh(0);
+    }
+
+    fn preview_pane(&self, width: u16) -> Pane {
+        let style = ContentStyle::default();
+        let rows = self
+            .selected()
+            .map(describe)
+            .unwrap_or_default()
+            .replace('\t', "    ")
+            .lines()
+            .flat_map(|line| {
+                // Blank lines would otherwise vanish
+                let line = if line.is_empty() { " " } else { line };
+                StyledGraphemes::from_str(line, style)
+                    .matrixify(width as usize, PREVIEW_LINES, 0)
+                    .0
+            })
+            .take(PREVIEW_LINES)
+            .collect::<Vec<_>>();
+
+        Pane::new(rows, 0)
+    }
+}
+
+impl Finalizer for PickerRenderer {
+    type Return = Option<Outcome>;
+
+    fn finalize(&mut self) -> Result<Self::Return> {
+        Ok(self.outcome.take())
+    }
+}
+
+impl Renderer for PickerRenderer {
+    fn create_panes(&self, width: u16, height: u16) -> Vec<Pane> {
+        let dim = StyleBuilder::new().attrs(Attributes::from(Attribute::Dim)).build();
+        let footer = match self.message.is_empty() {
+            true => HELP,
+            false => &self.message,
+        };
+
+        vec![
+            self.query.create_pane(width, height),
+            self.listbox.create_pane(width, height),
+            Pane::new(
+                vec![StyledGraphemes::from_str("─".repeat(width as usize), dim)],
+                0,
+            ),
+            self.preview_pane(width),
+            Pane::new(
+                StyledGraphemes::from_str(footer, dim).matrixify(width as usize, 2, 0).0,
+                0,
+            ),
+        ]
+    }
+
+    fn evaluate(&mut self, event: &Event) -> Result<PromptSignal> {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        else {
+            return Ok(PromptSignal::Continue);
+        };
+        let editor = &mut self.query.texteditor;
+        let before = editor.text_without_cursor().to_string();
+
+        match (code, *modifiers) {
+            (KeyCode::Esc, _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) =>
+                return Ok(PromptSignal::Quit),
+            (KeyCode::Enter, _) => {
+                let outcome = match self.print {
+                    true => Outcome::Print,
+                    false => Outcome::Copy,
+                };
+                return Ok(self.choose(outcome));
+            },
+            (KeyCode::Char('y'), KeyModifiers::CONTROL) => return Ok(self.choose(Outcome::Copy)),
+            (KeyCode::Char('o'), KeyModifiers::CONTROL) => return Ok(self.choose(Outcome::Print)),
+            (KeyCode::Char('t'), KeyModifiers::CONTROL) =>
+                if let Err(e) = self.toggle_pin() {
+                    self.message = e.to_string();
+                },
+            (KeyCode::Char('d'), KeyModifiers::CONTROL) =>
+                if let Err(e) = self.delete() {
+                    self.message = e.to_string();
+                },
+            (KeyCode::Up, _) | (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                self.listbox.listbox.backward();
+            },
+            (KeyCode::Down, _) | (KeyCode::Char('j'), KeyModifiers::CONTROL) => {
+                self.listbox.listbox.forward();
+            },
+            (KeyCode::Left, _) => {
+                editor.backward();
+            },
+            (KeyCode::Right, _) => {
+                editor.forward();
+            },
+            (KeyCode::Backspace, _) => editor.erase(),
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => editor.erase_all(),
+            (KeyCode::Char(ch), KeyModifiers::NONE | KeyModifiers::SHIFT) => editor.insert(*ch),
+            _ => (),
+        }
+
+        if self.query.texteditor.text_without_cursor().to_string() != before {
+            self.filter();
+        }
+
+        Ok(PromptSignal::Continue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn picker(mode: Mode) -> Picker {
+        let clips = ["cargo build", "git commit", "cargo test"]
+            .iter()
+            .zip(1..)
+            .map(|(text, id)| {
+                let mut entry = ClipEntry::new(text.as_bytes());
+                entry.id = Some(id);
+                entry.epoch.0 += chrono::Duration::seconds(id as i64);
+                entry
+            })
+            .collect();
+
+        Picker::new(clips, mode, false)
+    }
+
+    fn ids(picker: &Picker) -> Vec<u64> {
+        (0..picker.visible.len()).filter_map(|i| picker.get(i)?.id).collect()
+    }
+
+    #[test]
+    fn it_filters_newest_first() -> Result<()> {
+        let mut picker = picker(Mode::Substring);
+        assert_eq!(ids(&picker), [3, 2, 1]);
+
+        picker.filter("cargo")?;
+        assert_eq!(ids(&picker), [3, 1]);
+        assert_eq!(picker.lines(), ["3 cargo test", "1 cargo build"]);
+
+        picker.remove(Some(3));
+        assert_eq!(ids(&picker), [1]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_ranks_fuzzy_matches() -> Result<()> {
+        let mut picker = picker(Mode::Fuzzy);
+
+        picker.filter("cgbld")?;
+        assert_eq!(ids(&picker), [1]);
+
+        picker.filter("")?;
+        assert_eq!(ids(&picker), [3, 2, 1]);
+        Ok(())
+    }
+}
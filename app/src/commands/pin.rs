@@ -0,0 +1,40 @@
+use anyhow::Result;
+use clap::Parser;
+
+use super::{ClippyCommand, GreedyInt};
+use crate::{cli::ClippyCli, history::History};
+
+#[derive(Parser, Debug, PartialEq)]
+/// Pins a clip so it's never pruned from history
+pub struct Pin {
+    /// The id of the clip from the output of `list` command
+    id: GreedyInt,
+}
+
+#[derive(Parser, Debug, PartialEq)]
+/// Lets a pinned clip be pruned again
+pub struct Unpin {
+    /// The id of the clip from the output of `list` command
+    id: GreedyInt,
+}
+
+impl ClippyCommand for Pin {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        set_pinned(args, self.id, true)
+    }
+}
+
+impl ClippyCommand for Unpin {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        set_pinned(args, self.id, false)
+    }
+}
+
+fn set_pinned(args: &ClippyCli, id: GreedyInt, pinned: bool) -> Result<()> {
+    let mut history = History::open(&args.db_path)?;
+
+    match history.pin(id.into(), pinned)? {
+        Some(_) => Ok(()),
+        None => Err(history.missing_clip(id.into())?.into()),
+    }
+}
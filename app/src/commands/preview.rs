@@ -0,0 +1,217 @@
+use std::io::{stdout, Write};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use clippy_daemon::{
+    database::ClipEntry,
+    utils::mime::{is_image, is_text},
+};
+use serde_json::Value;
+use size::Size;
+
+use super::{ClippyCommand, GreedyInt};
+use crate::{
+    cli::ClippyCli,
+    history::History,
+    utils::{
+        formatting::detect_image,
+        graphics::{draw, fit, Images},
+        thumbnails::{decode, Thumbnails},
+    },
+};
+
+/// Pixels images are scaled down to fit in
+const IMAGE_SIZE: (u32, u32) = (800, 600);
+/// Bytes of binary clips shown in the hex dump
+const HEX_DUMP_LIMIT: usize = 512;
+
+#[derive(Parser, Debug, PartialEq)]
+/// Shows the whole of a clip, formatted for its type.
+///
+/// Images are drawn when the terminal can, JSON is pretty printed, URI lists get a URI per line
+/// and anything else that isn't text is shown as a hex dump
+pub struct Preview {
+    /// The id of the clip from the output of `list` command
+    id: GreedyInt,
+    /// Show this representation of the clip instead of the preferred one. ie: `text/html`
+    #[arg(short, long)]
+    mime_type: Option<String>,
+    /// How images are shown, whatever the terminal supports by default
+    #[arg(long, value_enum)]
+    images: Option<Images>,
+}
+
+impl ClippyCommand for Preview {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        let mut history = History::open(&args.db_path)?;
+        let Some(clip) = history.recall(self.id.into())? else {
+            return Err(history.missing_clip(self.id.into())?.into());
+        };
+        let images = self.images.unwrap_or_else(Images::detect);
+        let thumbnails = Thumbnails::of_db(&args.db_path)?;
+
+        render(
+            &mut stdout(),
+            &clip,
+            self.mime_type.as_deref(),
+            images,
+            &thumbnails,
+        )
+    }
+}
+
+/// A header describing `clip` followed by its `mime_type` representation
+fn render(
+    out: &mut impl Write,
+    clip: &ClipEntry,
+    mime_type: Option<&str>,
+    images: Images,
+    thumbnails: &Thumbnails,
+) -> Result<()> {
+    let mime_type = match mime_type {
+        Some(mime_type) if clip.get(mime_type).is_none() =>
+            return Err(anyhow!("Clip was not copied as {mime_type}")),
+        Some(mime_type) => mime_type,
+        None => clip.mime_type().unwrap_or_default(),
+    };
+    let bytes = clip.get(mime_type).unwrap_or_default();
+    let representations = clip
+        .payload
+        .iter()
+        .map(|(mime_type, bytes)| format!("{mime_type} ({})", Size::from_bytes(bytes.len())))
+        .collect::<Vec<_>>();
+
+    writeln!(
+        out,
+        "{} copied {} from {}{}",
+        clip.id.unwrap_or_default(),
+        clip.epoch.0.format("%c"),
+        clip.application.as_deref().unwrap_or("an unknown window"),
+        if clip.pinned { ", pinned" } else { "" }
+    )?;
+    writeln!(out, "{}\n", representations.join(", "))?;
+
+    match decode(bytes).filter(|_| is_image(mime_type)) {
+        Some(image) => {
+            if images.draws() {
+                let (width, height) = IMAGE_SIZE;
+                draw(out, &fit(&image, width, height), images)?;
+            }
+            writeln!(out, "{}", detect_image(bytes).unwrap_or_default())?;
+            if let Some(thumbnail) = thumbnails.path(clip).filter(|_| images == Images::Icon) {
+                writeln!(out, "{thumbnail}")?;
+            }
+        },
+        None => writeln!(out, "{}", format_text(mime_type, bytes))?,
+    }
+
+    Ok(out.flush()?)
+}
+
+/// `bytes` laid out the way their type reads best
+fn format_text(mime_type: &str, bytes: &[u8]) -> String {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return hex_dump(bytes);
+    };
+
+    match mime_type {
+        m if m.contains("json") || (is_text(m) && text.trim_start().starts_with(['{', '['])) =>
+            serde_json::from_str::<Value>(text)
+                .and_then(|json| serde_json::to_string_pretty(&json))
+                .unwrap_or_else(|_| text.to_string()),
+        // RFC 2483, one URI per CRLF terminated line with `#` starting comments
+        "text/uri-list" => text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => text.trim_end().to_string(),
+    }
+}
+
+/// `xxd` style, up to [`HEX_DUMP_LIMIT`] bytes
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut lines = bytes[..bytes.len().min(HEX_DUMP_LIMIT)]
+        .chunks(16)
+        .zip((0..).step_by(16))
+        .map(|(row, offset)| {
+            let hex = row.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>();
+            let ascii = row
+                .iter()
+                .map(|&byte| match byte {
+                    b' '..=b'~' => byte as char,
+                    _ => '.',
+                })
+                .collect::<String>();
+            format!("{offset:08x}: {:<47}  {ascii}", hex.join(" "))
+        })
+        .collect::<Vec<_>>();
+
+    if bytes.len() > HEX_DUMP_LIMIT {
+        lines.push(format!(
+            "… {} more",
+            Size::from_bytes(bytes.len() - HEX_DUMP_LIMIT)
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use camino::Utf8Path;
+    use clippy_daemon::database::{Payload, Selection};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_formats_by_type() -> Result<()> {
+        assert_eq!(
+            format_text("text/plain", br#"{"a": [1, 2]}"#),
+            "{\n  \"a\": [\n    1,\n    2\n  ]\n}"
+        );
+        assert_eq!(format_text("text/plain", b"{not json"), "{not json");
+        assert_eq!(
+            format_text("text/uri-list", b"# comment\r\nfile:///a\r\nfile:///b\r\n"),
+            "file:///a\nfile:///b"
+        );
+        assert_eq!(
+            format_text("application/octet-stream", b"\x00\xffbin"),
+            "00000000: 00 ff 62 69 6e                                   ..bin"
+        );
+        assert!(hex_dump(&[0; 600]).ends_with("… 88 bytes more"));
+
+        let mut clip = ClipEntry::with_payload(
+            Payload::from([
+                ("text/html".to_string(), b"<b>hi</b>".to_vec()),
+                ("text/plain".to_string(), b"hi".to_vec()),
+            ]),
+            Selection::Clipboard,
+        );
+        clip.id = Some(3);
+        clip.application = Some("firefox".to_string());
+        let thumbnails = Thumbnails::new(Utf8Path::new("db"), true);
+        let mut out = Vec::new();
+        render(
+            &mut out,
+            &clip,
+            Some("text/html"),
+            Images::Text,
+            &thumbnails,
+        )?;
+        let out = String::from_utf8(out)?;
+
+        assert!(out.contains(" from firefox\ntext/html (9 bytes), text/plain (2 bytes)\n\n"));
+        assert!(out.ends_with("\n<b>hi</b>\n"));
+        assert!(render(
+            &mut Vec::new(),
+            &clip,
+            Some("image/png"),
+            Images::Text,
+            &thumbnails
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use clippy_daemon::database::retention::Retention;
+use size::Size;
+
+use super::ClippyCommand;
+use crate::{
+    cli::ClippyCli,
+    history::History,
+    utils::{formatting::preview, load_config},
+};
+
+#[derive(Parser, Debug, PartialEq)]
+/// Removes clips past `max_age`, `max_age_by_type` and `max_total_size` from the config.
+///
+/// The daemon does this every minute on its own. Pinned clips are never removed
+pub struct Prune {
+    /// Only lists what would be removed
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+}
+
+impl ClippyCommand for Prune {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        let retention = Retention::new(&load_config()?.clipboard())?;
+        if retention.is_empty() {
+            return Err(anyhow!(
+                "Set `max_age`, `max_age_by_type` or `max_total_size` under [clipboard.default] in \
+                config.toml first"
+            ));
+        }
+
+        let mut history = History::open(&args.db_path)?;
+        let expired = history.prune(retention, self.dry_run)?;
+        if !self.dry_run && !expired.is_empty() {
+            history.forget_thumbnails(&args.db_path)?;
+        }
+        let freed = expired.iter().map(|expired| expired.entry.size()).sum::<u64>();
+
+        for expired in &expired {
+            println!(
+                "{} ({}) {}",
+                expired.entry.id.unwrap_or_default(),
+                expired.reason,
+                preview(&expired.entry, 60)
+            );
+        }
+        println!(
+            "{} {} clips, {}",
+            if self.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            expired.len(),
+            Size::from_bytes(freed)
+        );
+
+        Ok(())
+    }
+}
@@ -1,45 +1,96 @@
-use anyhow::Result;
+use std::io::{stdout, Write};
+
+use anyhow::{anyhow, Result};
 use clap::Parser;
-use clippy_daemon::database::{get_db, ClipEntry, TableLen};
+use clippy_daemon::{
+    database::{ClipEntry, Selection},
+    platforms::set_clipboard,
+};
 
 use super::{ClippyCommand, GreedyInt};
-use crate::cli::ClippyCli;
+use crate::{cli::ClippyCli, history::History};
 
 #[derive(Parser, Debug, PartialEq)]
 #[command(allow_missing_positional(true))]
-/// Outputs clip to `stdout`.
+/// Outputs clip to `stdout` exactly as it was copied.
 ///
-/// Meant for use with `wl-paste`
+/// Meant for use with `wl-copy`, or use `--copy` to put it straight back into the clipboard
 pub struct Recall {
     /// The id of the clip to use.
     ///
     /// From the output of `list` command
     id: GreedyInt,
+    /// Put the clip back into the clipboard with every MIME type it was copied in
+    #[arg(short, long)]
+    copy: bool,
+    /// Selection to copy the clip into with `--copy`
+    #[arg(short, long, value_enum, default_value_t, requires = "copy")]
+    selection: Selection,
+    /// Output this representation of the clip instead of the preferred one. ie: `text/html`
+    #[arg(short, long, conflicts_with = "copy")]
+    mime_type: Option<String>,
     #[arg(hide = true)] // This is just to make clap stop complaining
     other: Option<Vec<String>>,
 }
 
 impl ClippyCommand for Recall {
     fn execute(&self, args: &ClippyCli) -> Result<()> {
-        let error_text = "There is no clip with that id";
-        let db = get_db(&args.db_path)?;
-        let tx = db.r_transaction()?;
+        let mut history = History::open(&args.db_path)?;
+        let Some(clip) = history.recall(self.id.into())? else {
+            return Err(history.missing_clip(self.id.into())?.into());
+        };
 
-        if tx.length()? == 0 {
-            println!("{error_text}");
-            return Ok(());
+        match self.copy {
+            true => set_clipboard(&clip.payload, self.selection),
+            false => write_clip(&mut stdout(), &clip, self.mime_type.as_deref()),
         }
+    }
+}
+
+/// Writes the clip byte for byte, without so much as a trailing newline
+pub(crate) fn write_clip(
+    out: &mut impl Write,
+    clip: &ClipEntry,
+    mime_type: Option<&str>,
+) -> Result<()> {
+    let bytes = match mime_type {
+        Some(mime_type) => clip
+            .get(mime_type)
+            .ok_or_else(|| anyhow!("Clip was not copied as {mime_type}"))?,
+        None => clip.bytes(),
+    };
+
+    out.write_all(bytes)?;
+    Ok(out.flush()?)
+}
+
+#[cfg(test)]
+mod test {
+    use clippy_daemon::database::Payload;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_writes_raw_bytes() -> Result<()> {
+        let png = b"\x89PNG\r\n\x1a\n\0\0".to_vec();
+        let clip = ClipEntry::with_payload(
+            Payload::from([
+                ("image/png".to_string(), png.clone()),
+                ("text/html".to_string(), b"<img>\n".to_vec()),
+            ]),
+            Selection::Clipboard,
+        );
+        let mut out = Vec::new();
+
+        write_clip(&mut out, &clip, None)?;
+        assert_eq!(out, png);
 
-        let clip = tx
-            .scan()
-            .primary::<ClipEntry>()?
-            .all()?
-            .flatten()
-            .nth(&self.id - 1)
-            .expect(error_text)
-            .text()?;
-        println!("{clip}");
+        out.clear();
+        write_clip(&mut out, &clip, Some("text/html"))?;
+        assert_eq!(out, b"<img>\n");
 
+        assert!(write_clip(&mut out, &clip, Some("text/plain")).is_err());
         Ok(())
     }
 }
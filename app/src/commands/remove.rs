@@ -1,9 +1,8 @@
 use anyhow::Result;
 use clap::Parser;
-use clippy_daemon::database::{get_db, ClipEntry, TableLen};
 
 use super::{ClippyCommand, GreedyInt};
-use crate::cli::ClippyCli;
+use crate::{cli::ClippyCli, history::History};
 
 #[derive(Parser, Debug, PartialEq)]
 /// Removes a clip from the database
@@ -14,24 +13,11 @@ pub struct Remove {
 
 impl ClippyCommand for Remove {
     fn execute(&self, args: &ClippyCli) -> Result<()> {
-        let position: usize = self.id.into();
-        let db = get_db(&args.db_path)?;
-        let tx = db.rw_transaction()?;
+        let mut history = History::open(&args.db_path)?;
 
-        if tx.length()? == 0 {
-            println!("Clipboard empty. There is nothing to remove.");
-            return Ok(());
+        match history.remove(self.id.into())? {
+            Some(_) => history.forget_thumbnails(&args.db_path),
+            None => Err(history.missing_clip(self.id.into())?.into()),
         }
-
-        tx.remove(
-            tx.scan()
-                .primary::<ClipEntry>()?
-                .all()?
-                .flatten()
-                .nth(position - 1)
-                .expect("No clip found at that index"),
-        )?;
-
-        Ok(tx.commit()?)
     }
 }
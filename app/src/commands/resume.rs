@@ -0,0 +1,16 @@
+use anyhow::Result;
+use clap::Parser;
+use clippy_daemon::ipc::Client;
+
+use super::ClippyCommand;
+use crate::{cli::ClippyCli, error::ClippyError};
+
+#[derive(Parser, Debug, PartialEq)]
+/// Lets a paused daemon record clips again
+pub struct Resume {}
+
+impl ClippyCommand for Resume {
+    fn execute(&self, _: &ClippyCli) -> Result<()> {
+        Client::connect().ok_or(ClippyError::DaemonUnavailable)?.resume()
+    }
+}
@@ -1,58 +1,71 @@
-use std::io::{stdout, Write};
+use std::io::stdout;
 
 use anyhow::Result;
 use clap::Parser;
-use clippy_daemon::database::{get_db, ClipEntry, TableLen};
+use clippy_daemon::database::{
+    search::{Mode, Query},
+    Selection,
+};
 
 use super::ClippyCommand;
-use crate::{cli::ClippyCli, utils::formatting::format_entry};
+use crate::{
+    cli::ClippyCli,
+    history::History,
+    utils::{output::Output, thumbnails::Thumbnails},
+};
 
 #[derive(Parser, Debug, PartialEq)]
-/// Searches for a clip that contains `query`
+/// Searches for clips matching `query`
 pub struct Search {
     #[arg(short, long)]
-    /// The query to search for in clipboard history
+    /// The query to search for in clipboard history.
+    ///
+    /// May contain qualifiers: `app:<title>`, `after:<YYYY-MM-DD>`, `before:<YYYY-MM-DD>` and
+    /// `type:<mime type>`, ie: `app:firefox after:2026-01-01 type:image`
     query: Option<String>,
 
     #[arg(short, long, visible_alias("app"))]
     /// Filter search results to clips from a specific application.
     application: Option<String>,
 
-    #[arg(short('d'), long, action)]
-    /// Includes dates clips were taken in the output
-    include_dates: bool,
+    #[arg(short, long, value_enum, default_value_t)]
+    /// How the query is matched against clips
+    mode: Mode,
 
-    #[arg(short('w'), long, default_value = "100")]
-    /// Max characters to show of clips in preview. Use 0 to retain original width.
-    ///
-    ///
-    /// This does not affect what is put back into the clipboard
-    preview_width: usize,
+    #[arg(short, long)]
+    /// Ignore case when matching. Fuzzy searches are already case insensitive unless the query
+    /// has an uppercase letter
+    ignore_case: bool,
+
+    #[command(flatten)]
+    output: Output,
+
+    #[arg(short, long, value_enum)]
+    /// Only search clips copied into this selection
+    selection: Option<Selection>,
+
+    #[arg(long)]
+    /// Also search sensitive clips the daemon only keeps in memory
+    sensitive: bool,
 }
 
 impl ClippyCommand for Search {
     fn execute(&self, args: &ClippyCli) -> Result<()> {
-        let mut out = stdout();
-        let db = get_db(&args.db_path)?;
-        let tx = db.r_transaction()?;
-
-        if tx.length()? == 0 {
-            println!("Clipboard is empty");
-            return Ok(());
+        let mut query = Query::parse(
+            self.query.as_deref().unwrap_or_default(),
+            self.mode,
+            self.ignore_case,
+        )?;
+        if self.application.is_some() {
+            query.application.clone_from(&self.application);
         }
 
-        tx.scan()
-            .primary::<ClipEntry>()?
-            .all()?
-            .flatten()
-            .enumerate()
-            .filter(|(_, entry)| {
-                entry.contains(&self.query) & entry.was_copied_from_app(&self.application)
-            })
-            .for_each(|(i, entry)| {
-                let preview = format_entry(&entry, self.preview_width, self.include_dates);
-                writeln!(out, "{i} {}", preview,).unwrap();
-            });
-        Ok(())
+        let mut history = History::open(&args.db_path)?;
+        let mut clips = history.search(query, self.selection, self.sensitive)?;
+
+        let thumbnails = Thumbnails::of_db(&args.db_path)?;
+        history.load(&mut clips, |entry| self.output.needs_image(entry, &thumbnails))?;
+
+        self.output.write(&mut stdout(), &clips, "No clips found", &thumbnails)
     }
 }
@@ -0,0 +1,285 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{stdin, stdout, Read, Write},
+    process,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local,
+};
+use clap::{Parser, Subcommand};
+use clippy_daemon::{
+    database::{ClipEntry, Payload, Selection, Snippet as SnippetEntry},
+    platforms::set_clipboard,
+    utils::mime::guess_mime_type,
+};
+use promkit::preset::readline::Readline;
+
+use super::ClippyCommand;
+use crate::{
+    cli::ClippyCli,
+    error::ClippyError,
+    history::History,
+    utils::{formatting::truncate, template::fill},
+};
+
+#[derive(Parser, Debug, PartialEq)]
+/// Manages named snippets of text that can be expanded on demand.
+///
+/// A snippet body may contain placeholders: `{date}` and `{time}` (optionally with a format, ie:
+/// `{date:%d %B}`), `{clipboard}` for the newest clip, and any other `{name}` which is asked for
+/// when expanding. Use `{{` and `}}` for literal braces
+pub struct Snippet {
+    #[command(subcommand)]
+    command: SnippetCommands,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+enum SnippetCommands {
+    Add(Add),
+    List(List),
+    Edit(Edit),
+    Remove(Remove),
+    Expand(Expand),
+}
+
+#[derive(Parser, Debug, PartialEq)]
+/// Adds a new snippet
+struct Add {
+    name: String,
+    /// Text of the snippet, read from `stdin` when left out
+    body: Option<String>,
+}
+
+#[derive(Parser, Debug, PartialEq)]
+/// Lists every snippet with the start of its body
+struct List;
+
+#[derive(Parser, Debug, PartialEq)]
+/// Replaces the body of a snippet
+struct Edit {
+    name: String,
+    /// New text of the snippet, opens `$EDITOR` when left out
+    body: Option<String>,
+}
+
+#[derive(Parser, Debug, PartialEq)]
+/// Removes a snippet
+struct Remove {
+    name: String,
+}
+
+#[derive(Parser, Debug, PartialEq)]
+/// Fills in the placeholders of a snippet and outputs it to `stdout`
+struct Expand {
+    name: String,
+    /// Value of a `{name}` placeholder instead of asking for it, may be repeated. ie: `who=Sam`
+    #[arg(long = "set", value_parser = parse_variable)]
+    variables: Vec<(String, String)>,
+    /// Put the expanded snippet into the clipboard instead
+    #[arg(short, long)]
+    copy: bool,
+    /// Selection to copy the snippet into with `--copy`
+    #[arg(short, long, value_enum, default_value_t, requires = "copy")]
+    selection: Selection,
+}
+
+impl ClippyCommand for Snippet {
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        let mut history = History::open(&args.db_path)?;
+
+        match &self.command {
+            SnippetCommands::Add(add) => {
+                let body = match &add.body {
+                    Some(body) => body.clone(),
+                    None => {
+                        let mut body = String::new();
+                        stdin().read_to_string(&mut body)?;
+                        body
+                    },
+                };
+                history.save_snippet(SnippetEntry::new(&add.name, &body), false)
+            },
+            SnippetCommands::List(_) => {
+                for snippet in history.snippets()? {
+                    let preview = snippet.body.lines().next().unwrap_or_default();
+                    println!("{}: {}", snippet.name, truncate(preview, 100));
+                }
+                Ok(())
+            },
+            SnippetCommands::Edit(edit) => {
+                let snippet = find(&mut history, &edit.name)?;
+                let body = match &edit.body {
+                    Some(body) => body.clone(),
+                    None => open_in_editor(&snippet)?,
+                };
+                history.save_snippet(SnippetEntry::new(&edit.name, &body), true)
+            },
+            SnippetCommands::Remove(remove) =>
+                history.remove_snippet(&remove.name)?.map(|_| ()).ok_or_else(|| {
+                    ClippyError::SnippetNotFound {
+                        name: remove.name.clone(),
+                    }
+                    .into()
+                }),
+            SnippetCommands::Expand(expand) => expand.execute(&mut history),
+        }
+    }
+}
+
+impl Expand {
+    fn execute(&self, history: &mut History) -> Result<()> {
+        let snippet = find(history, &self.name)?;
+        let mut answers = self.variables.iter().cloned().collect::<HashMap<_, _>>();
+
+        let text = expand(&snippet.body, |placeholder| match placeholder {
+            Placeholder::Date(format) => now(format.unwrap_or("%Y-%m-%d")),
+            Placeholder::Time(format) => now(format.unwrap_or("%H:%M")),
+            Placeholder::Clipboard => {
+                let mut clips = history.list(Some(Selection::Clipboard), false)?;
+                let mut last = clips.split_off(clips.len().saturating_sub(1));
+                // Text that isn't the preferred representation may still be in a file
+                history.load(&mut last, |_| true)?;
+                last.first().map(ClipEntry::text).unwrap_or_else(|| Ok(String::new()))
+            },
+            Placeholder::Prompt(name) => {
+                if let Some(answer) = answers.get(*name) {
+                    return Ok(answer.clone());
+                }
+                let answer = Readline::default().title(*name).prompt()?.run()?;
+                answers.insert(name.to_string(), answer.clone());
+                Ok(answer)
+            },
+        })?;
+
+        match self.copy {
+            true => set_clipboard(
+                &Payload::from([(guess_mime_type(text.as_bytes()), text.into_bytes())]),
+                self.selection,
+            ),
+            false => {
+                let mut out = stdout();
+                out.write_all(text.as_bytes())?;
+                Ok(out.flush()?)
+            },
+        }
+    }
+}
+
+fn find(history: &mut History, name: &str) -> Result<SnippetEntry> {
+    history.snippet(name)?.ok_or_else(|| {
+        ClippyError::SnippetNotFound {
+            name: name.to_string(),
+        }
+        .into()
+    })
+}
+
+fn open_in_editor(snippet: &SnippetEntry) -> Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Unpredictably named and only readable by us, it's removed once dropped
+    let mut file = tempfile::Builder::new().prefix("clippy-snippet-").suffix(".txt").tempfile()?;
+    file.write_all(snippet.body.as_bytes())?;
+    file.flush()?;
+
+    let status = process::Command::new(&editor).arg(file.path()).status();
+    // Editors often replace the file rather than write to it so it's read again by path
+    let body = fs::read_to_string(file.path());
+
+    if !status?.success() {
+        return Err(anyhow!(
+            "{editor} exited with an error, snippet left unchanged"
+        ));
+    }
+
+    Ok(body?)
+}
+
+/// The current local time in the `strftime` `format`, which is checked first as chrono panics on
+/// a bad one
+fn now(format: &str) -> Result<String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(anyhow!("`{format}` is not a valid strftime format"));
+    }
+    Ok(Local::now().format(format).to_string())
+}
+
+fn parse_variable(variable: &str) -> Result<(String, String)> {
+    variable
+        .split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| anyhow!("Expected `name=value` but got `{variable}`"))
+}
+
+#[derive(Debug, PartialEq)]
+enum Placeholder<'a> {
+    /// Optionally with a `strftime` format
+    Date(Option<&'a str>),
+    Time(Option<&'a str>),
+    /// Text of the newest clip
+    Clipboard,
+    /// Anything else is asked for
+    Prompt(&'a str),
+}
+
+impl<'a> Placeholder<'a> {
+    fn parse(placeholder: &'a str) -> Self {
+        let (name, format) = match placeholder.split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (placeholder, None),
+        };
+
+        match name.trim() {
+            "date" => Self::Date(format),
+            "time" => Self::Time(format),
+            "clipboard" if format.is_none() => Self::Clipboard,
+            _ => Self::Prompt(placeholder.trim()),
+        }
+    }
+}
+
+/// Replaces every placeholder in `body` with what `resolve` gives for it
+fn expand(body: &str, mut resolve: impl FnMut(&Placeholder) -> Result<String>) -> Result<String> {
+    fill(body, |placeholder| {
+        resolve(&Placeholder::parse(placeholder))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_expands_placeholders() -> Result<()> {
+        let text = expand(
+            "Hi {who}, it's {date:%Y} {{really}} {clipboard}",
+            |placeholder| {
+                Ok(match placeholder {
+                    Placeholder::Date(Some(format)) => format!("<{format}>"),
+                    Placeholder::Clipboard => "pasted".to_string(),
+                    Placeholder::Prompt(name) => name.to_uppercase(),
+                    placeholder => panic!("Unexpected {placeholder:?}"),
+                })
+            },
+        )?;
+
+        assert_eq!(text, "Hi WHO, it's <%Y> {really} pasted");
+        assert!(expand("{unclosed", |_| Ok(String::new())).is_err());
+        assert!(expand("stray }", |_| Ok(String::new())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_bad_time_formats() -> Result<()> {
+        assert_eq!(now("%Y")?, Local::now().format("%Y").to_string());
+        assert!(now("%Q").is_err_and(|e| e.to_string().contains("`%Q`")));
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use anyhow::Result;
+use clap::Parser;
+use clippy_daemon::ipc::Client;
+
+use super::ClippyCommand;
+use crate::cli::ClippyCli;
+
+#[derive(Parser, Debug, PartialEq)]
+/// Shows whether the daemon is running and what it's doing
+pub struct Status {}
+
+impl ClippyCommand for Status {
+    fn execute(&self, _: &ClippyCli) -> Result<()> {
+        let Some(mut client) = Client::connect() else {
+            println!("Daemon is not running");
+            return Ok(());
+        };
+        let status = client.status()?;
+
+        println!("pid: {}", status.pid);
+        println!(
+            "state: {}",
+            if status.paused { "paused" } else { "watching" }
+        );
+        println!("clips: {}", status.clips);
+        println!("sensitive clips: {}", status.sensitive_clips);
+        println!("database: {}", status.db_path);
+
+        Ok(())
+    }
+}
@@ -1,17 +1,24 @@
-use std::{
-    io::{stdin, Read},
-    mem::size_of_val,
-};
+use std::io::{stdin, Read};
 
 use anyhow::Result;
 use clap::{ArgAction, Parser, ValueEnum};
-use clippy_daemon::database::{ensure_db_size, get_db, remove_duplicates, ClipEntry, Database};
+use clippy_daemon::{
+    database::{ClipEntry, Selection},
+    utils::{
+        config::Config,
+        filter::Filters,
+        limits::{Oversized, SizeLimits},
+    },
+};
 use serde::Serialize;
 
 use super::ClippyCommand;
-use crate::{cli::ClippyCli, utils::formatting::trim};
-
-const FIVE_MEGABYTES: usize = 5e6 as usize;
+use crate::{
+    cli::ClippyCli,
+    error::{ClippyError, Rejection},
+    history::History,
+    utils::{formatting::trim, load_config},
+};
 
 #[derive(ValueEnum, Parser, Clone, Default, PartialEq, Debug, Serialize)]
 #[serde(rename_all = "lowercase")]
@@ -29,20 +36,36 @@ pub enum State {
 pub struct Store {
     #[arg(env, action=ArgAction::Set, hide(true))]
     clipboard_state: State,
+
+    /// Selection the clip was copied into. Use `primary` with `wl-paste --primary --watch`
+    #[arg(short, long, value_enum, default_value_t)]
+    selection: Selection,
 }
 
 impl ClippyCommand for Store {
     fn execute(&self, args: &ClippyCli) -> Result<()> {
         match self.clipboard_state {
             State::Data => {
-                let db = get_db(&args.db_path)?;
+                let mut history = History::open(&args.db_path)?;
                 let mut payload = Vec::new();
                 stdin().read_to_end(&mut payload)?;
-                store(&db, payload.as_slice())?;
-                remove_duplicates(&db, args.duplicates)?;
-                ensure_db_size(&db, args.keep)?;
+                store(
+                    &mut history,
+                    payload.as_slice(),
+                    self.selection,
+                    &load_config()?,
+                    args,
+                )?;
+            },
+            State::Sensitive => {
+                let mut payload = Vec::new();
+                stdin().read_to_end(&mut payload)?;
+                let mut entry = ClipEntry::new(payload.as_slice());
+                entry.selection = self.selection;
+                check(&mut entry, &SizeLimits::current(&load_config()?))?;
+
+                History::open(&args.db_path)?.store_sensitive(entry)?;
             },
-            State::Sensitive => todo!("Use non-persistent storage for secrets"),
             State::Clear | State::Nil => (), // May want to implement these at some point
             State::Other => (),
         }
@@ -50,15 +73,69 @@ impl ClippyCommand for Store {
     }
 }
 
-pub fn store(db: &Database, payload: &[u8]) -> Result<()> {
-    if size_of_val(payload) > FIVE_MEGABYTES || trim(payload).is_empty() {
-        panic!("Data too large")
-    }
+/// Stores `payload` going by the size limits and the include and exclude rules of `config`
+pub fn store(
+    history: &mut History,
+    payload: &[u8],
+    selection: Selection,
+    config: &Config,
+    args: &ClippyCli,
+) -> Result<()> {
+    let mut entry = ClipEntry::new(payload);
+    entry.selection = selection;
+    check(&mut entry, &SizeLimits::current(config))?;
+    let filters = Filters::new(&config.clipboard())?;
 
-    let tx = db.rw_transaction()?;
-    {
-        tx.insert(ClipEntry::new(payload))?;
+    history.store(entry, &filters, args.duplicates, args.keep)
+}
+
+/// Leaves out the representations of `entry` over `limits`, like the daemon does with copied
+/// clips. Rejects it when nothing is left or it's blank
+pub(crate) fn check(entry: &mut ClipEntry, limits: &SizeLimits) -> Result<(), ClippyError> {
+    let rejection = match limits.enforce(entry).into_iter().next() {
+        Some(Oversized { size, limit, .. }) if entry.payload.is_empty() =>
+            Rejection::TooLarge { size, limit },
+        _ if trim(entry.bytes()).is_empty() => Rejection::Empty,
+        _ => return Ok(()),
+    };
+
+    Err(ClippyError::PayloadRejected(rejection))
+}
+
+#[cfg(test)]
+mod test {
+    use camino::Utf8Path;
+    use clippy_daemon::database::get_db;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+    use crate::cli::mock_cli;
+
+    #[test]
+    fn it_skips_excluded_clips() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let db = get_db(Utf8Path::new(tf.path("db").as_str()))?;
+        let mut history = History::Local(Box::new(db));
+        let args = mock_cli(std::iter::once("list")).unwrap();
+        let config: Config = toml::from_str(
+            r#"
+            [clipboard.default.exclude.secrets]
+            patterns = "^hunter2$"
+            "#,
+        )?;
+
+        store(
+            &mut history,
+            b"hunter2",
+            Selection::Clipboard,
+            &config,
+            &args,
+        )?;
+        store(&mut history, b"asdf", Selection::Clipboard, &config, &args)?;
+
+        let clips = history.list(None, false)?;
+        assert_eq!(clips.len(), 1);
+        assert_eq!(clips[0].bytes(), b"asdf");
+        Ok(())
     }
-    tx.commit()?;
-    Ok(())
 }
@@ -1,42 +1,23 @@
-use std::process::{Command, Stdio};
-
 use anyhow::Result;
 use clap::Parser;
+use clippy_daemon::database::Selection;
 
-use super::ClippyCommand;
+use super::{
+    daemon::{daemon_args, start},
+    ClippyCommand,
+};
 use crate::cli::ClippyCli;
 
-/// Starts daemon to watch for clipboard events
+/// Starts daemon to watch for clipboard events. Same as `clippy daemon start`
 #[derive(Parser, Debug, PartialEq)]
 pub struct Watch {
-    board: Option<String>,
+    /// Selections to record. Defaults to `general.selections` from the config
+    #[arg(value_enum)]
+    board: Vec<Selection>,
 }
 
 impl ClippyCommand for Watch {
-    fn execute(&self, _: &ClippyCli) -> Result<()> {
-        run_in_background("clippy_daemon", &[])?;
-        Ok(())
+    fn execute(&self, args: &ClippyCli) -> Result<()> {
+        start(&daemon_args(&self.board, args))
     }
 }
-
-#[cfg(target_os = "windows")]
-fn run_in_background(command: &str, args: &[&str]) -> Result<()> {
-    Command::new(command)
-        .args(args)
-        .creation_flags(
-            winapi::um::winbase::DETACHED_PROCESS | winapi::um::winbase::CREATE_NEW_PROCESS_GROUP,
-        )
-        .spawn()?;
-    Ok(())
-}
-
-#[cfg(target_family = "unix")]
-fn run_in_background(command: &str, args: &[&str]) -> Result<()> {
-    Command::new(command)
-        .args(args)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
-    Ok(())
-}
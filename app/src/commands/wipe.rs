@@ -1,9 +1,8 @@
 use anyhow::Result;
 use clap::Parser;
-use clippy_daemon::database::get_db;
 
 use super::ClippyCommand;
-use crate::cli::ClippyCli;
+use crate::{cli::ClippyCli, history::History};
 
 #[derive(Parser, Debug, PartialEq)]
 /// Wipes all clips from clipboard
@@ -11,11 +10,8 @@ pub struct Wipe {}
 
 impl ClippyCommand for Wipe {
     fn execute(&self, args: &ClippyCli) -> Result<()> {
-        let db = get_db(&args.db_path)?;
-        let tx = db.rw_transaction()?;
-        tx.drain();
-        tx.commit()?;
-
-        Ok(())
+        let mut history = History::open(&args.db_path)?;
+        history.wipe()?;
+        history.forget_thumbnails(&args.db_path)
     }
 }
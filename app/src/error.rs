@@ -0,0 +1,91 @@
+//! Failures scripts may want to react to, each exiting with a code of its own.
+//!
+//! Anything else exits with [`FAILURE`], and clap exits with 2 on invalid arguments.
+
+use derive_more::{Display, Error};
+use size::Size;
+
+pub const FAILURE: u8 = 1;
+pub const NOT_FOUND: u8 = 3;
+pub const EMPTY_HISTORY: u8 = 4;
+pub const PAYLOAD_REJECTED: u8 = 5;
+pub const DATABASE_LOCKED: u8 = 6;
+pub const DAEMON_UNAVAILABLE: u8 = 7;
+
+#[derive(Error, Display, Debug, PartialEq)]
+pub enum ClippyError {
+    #[display("There is no clip with id {id}")]
+    ClipNotFound { id: u64 },
+    #[display("There is no snippet named `{name}`")]
+    SnippetNotFound { name: String },
+    #[display("Clipboard history is empty")]
+    EmptyHistory,
+    #[display("Clip was not stored, {_0}")]
+    PayloadRejected(#[error(not(source))] Rejection),
+    #[display(
+        "Database `{path}` is in use by another process. Run `clippy daemon start` to share it \
+        between them"
+    )]
+    DatabaseLocked { path: String },
+    #[display("Daemon is not running, start it with `clippy daemon start`")]
+    DaemonUnavailable,
+}
+
+#[derive(Display, Debug, PartialEq)]
+pub enum Rejection {
+    #[display("it's empty")]
+    Empty,
+    #[display(
+        "it's {} which is over the limit of {}",
+        Size::from_bytes(*size),
+        Size::from_bytes(*limit)
+    )]
+    TooLarge { size: usize, limit: usize },
+}
+
+impl ClippyError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::ClipNotFound { .. } | Self::SnippetNotFound { .. } => NOT_FOUND,
+            Self::EmptyHistory => EMPTY_HISTORY,
+            Self::PayloadRejected(_) => PAYLOAD_REJECTED,
+            Self::DatabaseLocked { .. } => DATABASE_LOCKED,
+            Self::DaemonUnavailable => DAEMON_UNAVAILABLE,
+        }
+    }
+}
+
+/// Code of the first [`ClippyError`] behind `error`, otherwise [`FAILURE`]
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ClippyError>())
+        .map_or(FAILURE, ClippyError::exit_code)
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::anyhow;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_finds_exit_codes() {
+        let rejected = ClippyError::PayloadRejected(Rejection::TooLarge {
+            size: 6_000_000,
+            limit: 5_000_000,
+        });
+
+        assert_eq!(
+            rejected.to_string(),
+            "Clip was not stored, it's 5.72 MiB which is over the limit of 4.77 MiB"
+        );
+        assert_eq!(exit_code(&rejected.into()), PAYLOAD_REJECTED);
+        assert_eq!(
+            exit_code(&anyhow::Error::from(ClippyError::EmptyHistory).context("Recalling")),
+            EMPTY_HISTORY
+        );
+        assert_eq!(exit_code(&anyhow!("Something else")), FAILURE);
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::Result;
+use camino::Utf8Path;
+use clippy_daemon::{
+    database::{
+        get_clip, get_clips, import_clips, is_locked, remove_clip,
+        retention::{prune, Expired, Retention},
+        search::{search, Query, SearchIndex},
+        set_pinned,
+        snippets::{get_snippet, get_snippets, remove_snippet, save_snippet},
+        store_clip, wipe, ClipEntry, Database, Selection, Snippet,
+    },
+    ipc::Client,
+    utils::{config::Encryption, filter::Filters},
+};
+
+use crate::{
+    error::ClippyError,
+    utils::{load_config, thumbnails::Thumbnails},
+};
+
+/// Clipboard history as seen by the CLI.
+///
+/// Requests go through the daemon when it's running against the same database so the two never
+/// fight over the database lock. Otherwise the database is opened directly.
+pub enum History {
+    Daemon(Client),
+    Local(Box<Database<'static>>),
+}
+
+impl History {
+    pub fn open(db_path: &Utf8Path) -> Result<Self> {
+        if let Some(mut client) = Client::connect() {
+            if client.status().is_ok_and(|status| status.db_path == db_path.as_str()) {
+                return Ok(Self::Daemon(client));
+            }
+        }
+
+        Ok(Self::Local(Box::new(open_db(
+            db_path,
+            load_config()?.encryption().as_ref(),
+        )?)))
+    }
+
+    /// Clips recalled by id are only missing if there were clips to begin with
+    pub fn missing_clip(&mut self, id: u64) -> Result<ClippyError> {
+        Ok(match self.list(None, false)?.is_empty() {
+            true => ClippyError::EmptyHistory,
+            false => ClippyError::ClipNotFound { id },
+        })
+    }
+
+    /// `sensitive` also lists clips the daemon only keeps in memory
+    pub fn list(
+        &mut self,
+        selection: Option<Selection>,
+        sensitive: bool,
+    ) -> Result<Vec<ClipEntry>> {
+        match self {
+            Self::Daemon(client) => client.list(selection, sensitive),
+            Self::Local(db) => get_clips(db, &selection),
+        }
+    }
+
+    /// Without a daemon the index is built from scratch for every search
+    pub fn search(
+        &mut self,
+        query: Query,
+        selection: Option<Selection>,
+        sensitive: bool,
+    ) -> Result<Vec<ClipEntry>> {
+        match self {
+            Self::Daemon(client) => client.search(query, selection, sensitive),
+            Self::Local(db) => search(db, &mut SearchIndex::build(db)?, &query, &selection, vec![]),
+        }
+    }
+
+    pub fn recall(&mut self, id: u64) -> Result<Option<ClipEntry>> {
+        match self {
+            Self::Daemon(client) => client.recall(id),
+            Self::Local(db) => get_clip(db, id),
+        }
+    }
+
+    /// Swaps the listed `clips` that `needed` picks for recalled ones, with the representations
+    /// kept in files read. Recalled one at a time so no single response carries them all
+    pub fn load(
+        &mut self,
+        clips: &mut [ClipEntry],
+        needed: impl Fn(&ClipEntry) -> bool,
+    ) -> Result<()> {
+        for entry in clips.iter_mut().filter(|entry| !entry.blobs.is_empty() && needed(entry)) {
+            if let Some(recalled) = self.recall(entry.id.unwrap_or_default())? {
+                *entry = recalled;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clips `filters` exclude aren't stored. The daemon goes by the rules of its own config
+    pub fn store(
+        &mut self,
+        entry: ClipEntry,
+        filters: &Filters,
+        duplicates: i64,
+        keep: u64,
+    ) -> Result<()> {
+        match self {
+            Self::Daemon(client) => client.store(entry, duplicates, keep),
+            Self::Local(db) => store_clip(db, entry, filters, duplicates, keep),
+        }
+    }
+
+    /// Returns how many clips were new. Filtered like [`Self::store`]
+    pub fn import(
+        &mut self,
+        entries: Vec<ClipEntry>,
+        filters: &Filters,
+        duplicates: i64,
+        keep: u64,
+    ) -> Result<u64> {
+        match self {
+            Self::Daemon(client) => client.import(entries, duplicates, keep),
+            Self::Local(db) => import_clips(db, entries, filters, duplicates, keep),
+        }
+    }
+
+    /// With `dry_run` only says what would be removed
+    pub fn prune(&mut self, retention: Retention, dry_run: bool) -> Result<Vec<Expired>> {
+        match self {
+            Self::Daemon(client) => client.prune(retention, dry_run),
+            Self::Local(db) => prune(db, &retention, dry_run, true),
+        }
+    }
+
+    /// Sensitive clips are never written to disk so they can only be kept by a running daemon
+    pub fn store_sensitive(&mut self, entry: ClipEntry) -> Result<()> {
+        match self {
+            Self::Daemon(client) => client.store_sensitive(entry),
+            Self::Local(_) => Err(anyhow::Error::from(ClippyError::DaemonUnavailable)
+                .context("Sensitive clips are only kept in memory by the daemon")),
+        }
+    }
+
+    pub fn remove(&mut self, id: u64) -> Result<Option<ClipEntry>> {
+        match self {
+            Self::Daemon(client) => client.remove(id),
+            Self::Local(db) => remove_clip(db, id),
+        }
+    }
+
+    pub fn pin(&mut self, id: u64, pinned: bool) -> Result<Option<ClipEntry>> {
+        match self {
+            Self::Daemon(client) => client.pin(id, pinned),
+            Self::Local(db) => set_pinned(db, id, pinned),
+        }
+    }
+
+    /// Removes the thumbnails of clips no longer in history, once some were removed from it
+    pub fn forget_thumbnails(&mut self, db_path: &Utf8Path) -> Result<()> {
+        Thumbnails::of_db(db_path)?.retain(&self.list(None, false)?)
+    }
+
+    pub fn wipe(&mut self) -> Result<()> {
+        match self {
+            Self::Daemon(client) => client.wipe(),
+            Self::Local(db) => wipe(db),
+        }
+    }
+
+    pub fn snippets(&mut self) -> Result<Vec<Snippet>> {
+        match self {
+            Self::Daemon(client) => client.snippets(),
+            Self::Local(db) => get_snippets(db),
+        }
+    }
+
+    pub fn snippet(&mut self, name: &str) -> Result<Option<Snippet>> {
+        match self {
+            Self::Daemon(client) => client.snippet(name),
+            Self::Local(db) => get_snippet(db, name),
+        }
+    }
+
+    pub fn save_snippet(&mut self, snippet: Snippet, overwrite: bool) -> Result<()> {
+        match self {
+            Self::Daemon(client) => client.save_snippet(snippet, overwrite),
+            Self::Local(db) => save_snippet(db, snippet, overwrite),
+        }
+    }
+
+    pub fn remove_snippet(&mut self, name: &str) -> Result<Option<Snippet>> {
+        match self {
+            Self::Daemon(client) => client.remove_snippet(name),
+            Self::Local(db) => remove_snippet(db, name),
+        }
+    }
+}
+
+/// Opens the database directly, telling apart another process holding it
+pub fn open_db(db_path: &Utf8Path, encryption: Option<&Encryption>) -> Result<Database<'static>> {
+    clippy_daemon::database::open_db(db_path, encryption).map_err(|e| match is_locked(&e) {
+        true => ClippyError::DatabaseLocked {
+            path: db_path.to_string(),
+        }
+        .into(),
+        false => e,
+    })
+}
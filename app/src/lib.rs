@@ -1,7 +1,7 @@
-extern crate log;
-
 pub mod cli;
 pub mod commands;
+pub mod error;
+pub mod history;
 
 #[macro_use]
 pub mod utils;
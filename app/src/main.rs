@@ -1,28 +1,59 @@
+use std::process::ExitCode;
+
 use anyhow::Result;
 use clap::Parser;
 pub use clippy::{cli, commands};
 use clippy::{
     cli::{ClippyCli, Commands},
     commands::ClippyCommand,
+    error::exit_code,
 };
+use clippy_daemon::utils::logging;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let args = ClippyCli::parse();
 
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            ExitCode::from(exit_code(&e))
+        },
+    }
+}
+
+fn run(args: &ClippyCli) -> Result<()> {
+    logging::init(args.verbose, args.log_format, None)?;
+
     // I wanna know if there's a better way to do this than this huge blob
     //
     // But at least this way the commands are in there own files and it's
     //
     // easier to destinguish tests
     match &args.command {
-        Commands::GenCompletions(command) => command.execute(&args)?,
-        Commands::Store(command) => command.execute(&args)?,
-        Commands::List(command) => command.execute(&args)?,
-        Commands::Recall(command) => command.execute(&args)?,
-        Commands::Search(command) => command.execute(&args)?,
-        Commands::Wipe(command) => command.execute(&args)?,
-        Commands::Remove(command) => command.execute(&args)?,
-        Commands::Version(command) => command.execute(&args)?,
+        Commands::GenCompletions(command) => command.execute(args)?,
+        Commands::Store(command) => command.execute(args)?,
+        Commands::List(command) => command.execute(args)?,
+        Commands::Recall(command) => command.execute(args)?,
+        Commands::Search(command) => command.execute(args)?,
+        Commands::Wipe(command) => command.execute(args)?,
+        Commands::Remove(command) => command.execute(args)?,
+        Commands::Version(command) => command.execute(args)?,
+        Commands::Watch(command) => command.execute(args)?,
+        Commands::Daemon(command) => command.execute(args)?,
+        Commands::Status(command) => command.execute(args)?,
+        Commands::Pause(command) => command.execute(args)?,
+        Commands::Resume(command) => command.execute(args)?,
+        Commands::Filter(command) => command.execute(args)?,
+        Commands::Pin(command) => command.execute(args)?,
+        Commands::Unpin(command) => command.execute(args)?,
+        Commands::Snippet(command) => command.execute(args)?,
+        Commands::Pick(command) => command.execute(args)?,
+        Commands::Export(command) => command.execute(args)?,
+        Commands::Import(command) => command.execute(args)?,
+        Commands::Encrypt(command) => command.execute(args)?,
+        Commands::Prune(command) => command.execute(args)?,
+        Commands::Preview(command) => command.execute(args)?,
     }
 
     Ok(())
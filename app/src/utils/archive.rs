@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, FixedOffset, Local};
+use clap::ValueEnum;
+use clippy_daemon::database::{ClipEntry, Payload, Selection};
+use serde::{Deserialize, Serialize};
+
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    /// A single array of clips
+    Json,
+    /// One clip object per line
+    #[default]
+    Jsonl,
+}
+
+/// A clip as written to an archive. Ids aren't kept, imported clips are numbered like new ones
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ArchivedClip {
+    /// RFC 3339 with nanoseconds
+    pub timestamp: DateTime<FixedOffset>,
+    pub application: Option<String>,
+    #[serde(default)]
+    pub selection: Selection,
+    #[serde(default)]
+    pub pinned: bool,
+    /// Base64 encoded bytes of each representation, by MIME type
+    pub payload: BTreeMap<String, String>,
+}
+
+impl From<&ClipEntry> for ArchivedClip {
+    fn from(entry: &ClipEntry) -> Self {
+        Self {
+            timestamp: entry.epoch.0.fixed_offset(),
+            application: entry.application.clone(),
+            selection: entry.selection,
+            pinned: entry.pinned,
+            payload: entry
+                .payload
+                .iter()
+                .map(|(mime_type, bytes)| (mime_type.clone(), STANDARD.encode(bytes)))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<ArchivedClip> for ClipEntry {
+    type Error = anyhow::Error;
+
+    fn try_from(clip: ArchivedClip) -> Result<Self> {
+        let payload = clip
+            .payload
+            .into_iter()
+            .map(|(mime_type, bytes)| {
+                let bytes = STANDARD
+                    .decode(bytes)
+                    .with_context(|| format!("Payload of `{mime_type}` isn't valid base64"))?;
+                Ok((mime_type, bytes))
+            })
+            .collect::<Result<Payload>>()?;
+        let mut entry = ClipEntry::with_application(payload, clip.selection, clip.application);
+        entry.epoch = clip.timestamp.with_timezone(&Local).into();
+        entry.pinned = clip.pinned;
+
+        Ok(entry)
+    }
+}
+
+pub fn write(out: &mut impl Write, clips: &[ClipEntry], format: ArchiveFormat) -> Result<()> {
+    let clips = clips.iter().map(ArchivedClip::from);
+
+    match format {
+        ArchiveFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &clips.collect::<Vec<_>>())?;
+            writeln!(out)?;
+        },
+        ArchiveFormat::Jsonl =>
+            for clip in clips {
+                serde_json::to_writer(&mut *out, &clip)?;
+                writeln!(out)?;
+            },
+    }
+
+    Ok(out.flush()?)
+}
+
+/// Reads either format, telling them apart by whether the archive starts with an array
+pub fn read(input: &mut impl Read) -> Result<Vec<ClipEntry>> {
+    let mut archive = String::new();
+    input.read_to_string(&mut archive)?;
+
+    let clips = if archive.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<ArchivedClip>>(&archive)?
+    } else {
+        archive
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| serde_json::from_str(line).map_err(|e| anyhow!("Line {}: {e}", i + 1)))
+            .collect::<Result<_>>()?
+    };
+
+    clips.into_iter().map(ClipEntry::try_from).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use chrono::Utc;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_round_trips() -> Result<()> {
+        let mut image = ClipEntry::with_payload(
+            Payload::from([
+                (
+                    "image/png".to_string(),
+                    vec![0x89, b'P', b'N', b'G', 0, 0xff],
+                ),
+                ("text/uri-list".to_string(), b"file:///tmp/a.png".to_vec()),
+            ]),
+            Selection::Primary,
+        );
+        image.pinned = true;
+        image.application = Some("viewer".to_string());
+        let clips = vec![ClipEntry::new(b"hello"), image];
+
+        for format in [ArchiveFormat::Json, ArchiveFormat::Jsonl] {
+            let mut archive = Vec::new();
+            write(&mut archive, &clips, format)?;
+
+            assert_eq!(read(&mut Cursor::new(archive))?, clips);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_documented_archives() -> Result<()> {
+        let archive = r#"
+{"timestamp":"2024-05-01T12:00:00.5+02:00","application":null,"payload":{"text/plain":"aGk="}}
+
+{"timestamp":"2024-05-01T12:00:01Z","application":"kitty","selection":"primary","pinned":true,"payload":{"text/plain":"eW8="}}
+"#;
+        let clips = read(&mut Cursor::new(archive))?;
+
+        assert_eq!(clips.len(), 2);
+        assert_eq!(clips[0].text()?, "hi");
+        assert_eq!(
+            clips[0].epoch.0,
+            "2024-05-01T10:00:00.5Z".parse::<DateTime<Utc>>()?
+        );
+        assert_eq!(clips[1].selection, Selection::Primary);
+        assert!(clips[1].pinned);
+        assert!(read(&mut Cursor::new(r#"{"timestamp":"yesterday"}"#)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+//! Reads the history kept by [cliphist](https://github.com/sentriz/cliphist) so it can be imported.
+//!
+//! cliphist stores clips in a [bbolt](https://github.com/etcd-io/bbolt) database, inside a bucket
+//! named `b` keyed by big endian ids. Only the handful of page layouts needed to walk that bucket
+//! are understood here, and every read is bounds checked since the file isn't ours.
+
+use anyhow::{anyhow, Result};
+
+const MAGIC: u32 = 0xED0CDAED;
+const PAGE_HEADER_SIZE: usize = 16;
+const ELEMENT_SIZE: usize = 16;
+const BUCKET_HEADER_SIZE: usize = 16;
+const BRANCH_PAGE: u16 = 0x01;
+const LEAF_PAGE: u16 = 0x02;
+const BUCKET_LEAF: u32 = 0x01;
+/// Guards against cycles in a corrupt file
+const MAX_DEPTH: usize = 64;
+const BUCKET: &[u8] = b"b";
+
+/// `(flags, key, value)` of an entry in a leaf page
+type Element<'a> = (u32, &'a [u8], &'a [u8]);
+
+/// Payloads of every clip oldest first
+pub fn read_history(db: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let bolt = Bolt::open(db)?;
+    let mut buckets = Vec::new();
+    bolt.walk(bolt.page(bolt.root)?, 0, &mut buckets)?;
+
+    let bucket = buckets
+        .into_iter()
+        .find_map(|(flags, key, value)| {
+            (flags & BUCKET_LEAF != 0 && key == BUCKET).then_some(value)
+        })
+        .ok_or_else(|| anyhow!("Not a cliphist database, it has no `b` bucket"))?;
+    // Small buckets are stored inline, right after their header
+    let page = match u64_at(bucket, 0)? {
+        0 => bucket
+            .get(BUCKET_HEADER_SIZE..)
+            .ok_or_else(|| anyhow!("Database is truncated"))?,
+        pgid => bolt.page(pgid)?,
+    };
+
+    let mut clips = Vec::new();
+    bolt.walk(page, 0, &mut clips)?;
+    clips.sort_by_key(|(_, key, _)| *key);
+
+    Ok(clips
+        .into_iter()
+        .filter(|(flags, _, _)| flags & BUCKET_LEAF == 0)
+        .map(|(_, _, value)| value.to_vec())
+        .collect())
+}
+
+struct Bolt<'a> {
+    data: &'a [u8],
+    page_size: usize,
+    /// Page of the bucket holding every top level bucket
+    root: u64,
+}
+
+impl<'a> Bolt<'a> {
+    /// bbolt alternates between two meta pages, the one with the newest transaction is current
+    fn open(data: &'a [u8]) -> Result<Self> {
+        let first = Meta::read(data, 0)?;
+        let current = match Meta::read(data, first.page_size) {
+            Ok(second) if second.txid > first.txid => second,
+            _ => first,
+        };
+
+        Ok(Self {
+            data,
+            page_size: current.page_size,
+            root: current.root,
+        })
+    }
+
+    fn page(&self, pgid: u64) -> Result<&'a [u8]> {
+        let start = usize::try_from(pgid)?
+            .checked_mul(self.page_size)
+            .filter(|start| *start < self.data.len())
+            .ok_or_else(|| anyhow!("Page {pgid} is out of bounds"))?;
+
+        Ok(&self.data[start..])
+    }
+
+    /// Collects every leaf element under `page`
+    fn walk(&self, page: &'a [u8], depth: usize, out: &mut Vec<Element<'a>>) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(anyhow!("Database is nested too deep, it may be corrupt"));
+        }
+        let flags = u16_at(page, 8)?;
+        let count = u16_at(page, 10)? as usize;
+
+        for i in 0..count {
+            let element = PAGE_HEADER_SIZE + i * ELEMENT_SIZE;
+
+            match flags {
+                BRANCH_PAGE => {
+                    let pgid = u64_at(page, element + 8)?;
+                    self.walk(self.page(pgid)?, depth + 1, out)?;
+                },
+                LEAF_PAGE => {
+                    let element_flags = u32_at(page, element)?;
+                    let key_at = element + u32_at(page, element + 4)? as usize;
+                    let key_size = u32_at(page, element + 8)? as usize;
+                    let value_size = u32_at(page, element + 12)? as usize;
+                    let key = slice(page, key_at, key_size)?;
+                    let value = slice(page, key_at + key_size, value_size)?;
+                    out.push((element_flags, key, value));
+                },
+                _ => return Err(anyhow!("Unexpected page type {flags:#x}")),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Meta {
+    page_size: usize,
+    root: u64,
+    txid: u64,
+}
+
+impl Meta {
+    fn read(data: &[u8], page_at: usize) -> Result<Self> {
+        let meta = page_at + PAGE_HEADER_SIZE;
+        if u32_at(data, meta)? != MAGIC {
+            return Err(anyhow!("Not a bbolt database"));
+        }
+        let page_size = u32_at(data, meta + 8)? as usize;
+        if page_size < PAGE_HEADER_SIZE {
+            return Err(anyhow!("Invalid page size {page_size}"));
+        }
+
+        Ok(Self {
+            page_size,
+            root: u64_at(data, meta + 16)?,
+            txid: u64_at(data, meta + 48)?,
+        })
+    }
+}
+
+fn slice(data: &[u8], at: usize, len: usize) -> Result<&[u8]> {
+    at.checked_add(len)
+        .and_then(|end| data.get(at..end))
+        .ok_or_else(|| anyhow!("Database is truncated"))
+}
+
+fn u16_at(data: &[u8], at: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(slice(data, at, 2)?.try_into()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(slice(data, at, 4)?.try_into()?))
+}
+
+fn u64_at(data: &[u8], at: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(slice(data, at, 8)?.try_into()?))
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const PAGE_SIZE: usize = 512;
+
+    /// Lays out a page the way bbolt does: header, element table, then keys and values
+    fn page(pgid: u64, flags: u16, elements: &[(u32, &[u8], &[u8])]) -> Vec<u8> {
+        let mut page = Vec::new();
+        page.extend(pgid.to_le_bytes());
+        page.extend(flags.to_le_bytes());
+        page.extend((elements.len() as u16).to_le_bytes());
+        page.extend(0u32.to_le_bytes());
+
+        let mut data = Vec::<u8>::new();
+        for (i, (element_flags, key, value)) in elements.iter().enumerate() {
+            let pos = (elements.len() - i) * ELEMENT_SIZE + data.len();
+            if flags == BRANCH_PAGE {
+                page.extend((pos as u32).to_le_bytes());
+                page.extend((key.len() as u32).to_le_bytes());
+                page.extend(*value);
+                data.extend(*key);
+            } else {
+                page.extend(element_flags.to_le_bytes());
+                page.extend((pos as u32).to_le_bytes());
+                page.extend((key.len() as u32).to_le_bytes());
+                page.extend((value.len() as u32).to_le_bytes());
+                data.extend(*key);
+                data.extend(*value);
+            }
+        }
+        page.extend(data);
+        page
+    }
+
+    fn meta(pgid: u64, root: u64, txid: u64) -> Vec<u8> {
+        let mut page = page(pgid, 0x04, &[]);
+        page.extend(MAGIC.to_le_bytes());
+        page.extend(2u32.to_le_bytes());
+        page.extend((PAGE_SIZE as u32).to_le_bytes());
+        page.extend(0u32.to_le_bytes());
+        page.extend(root.to_le_bytes());
+        page.extend(0u64.to_le_bytes());
+        page.extend(2u64.to_le_bytes());
+        page.extend(8u64.to_le_bytes());
+        page.extend(txid.to_le_bytes());
+        page
+    }
+
+    fn database(pages: Vec<Vec<u8>>) -> Vec<u8> {
+        pages
+            .into_iter()
+            .flat_map(|mut page| {
+                page.resize(PAGE_SIZE, 0);
+                page
+            })
+            .collect()
+    }
+
+    fn bucket(root: u64, inline: &[u8]) -> Vec<u8> {
+        [&root.to_le_bytes()[..], &0u64.to_le_bytes(), inline].concat()
+    }
+
+    #[test]
+    fn it_reads_inline_buckets() -> Result<()> {
+        let clips = page(
+            0,
+            LEAF_PAGE,
+            &[(0, &1u64.to_be_bytes(), b"first"), (0, &2u64.to_be_bytes(), b"second")],
+        );
+        let db = database(vec![
+            meta(0, 2, 4),
+            // Older transaction pointing at garbage
+            meta(1, 9, 3),
+            page(2, LEAF_PAGE, &[(BUCKET_LEAF, b"b", &bucket(0, &clips))]),
+        ]);
+
+        assert_eq!(read_history(&db)?, [b"first".to_vec(), b"second".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_nested_pages() -> Result<()> {
+        let db = database(vec![
+            meta(0, 2, 4),
+            meta(1, 2, 5),
+            page(
+                2,
+                LEAF_PAGE,
+                &[(BUCKET_LEAF, b"a", &bucket(0, &[])), (BUCKET_LEAF, b"b", &bucket(3, &[]))],
+            ),
+            page(
+                3,
+                BRANCH_PAGE,
+                &[
+                    (0, &3u64.to_be_bytes(), &5u64.to_le_bytes()),
+                    (0, &1u64.to_be_bytes(), &4u64.to_le_bytes()),
+                ],
+            ),
+            page(
+                4,
+                LEAF_PAGE,
+                &[
+                    (0, &1u64.to_be_bytes(), b"one"),
+                    (0, &2u64.to_be_bytes(), &[0x89, b'P', b'N', b'G']),
+                ],
+            ),
+            page(5, LEAF_PAGE, &[(0, &3u64.to_be_bytes(), b"three")]),
+        ]);
+
+        assert_eq!(
+            read_history(&db)?,
+            [b"one".to_vec(), vec![0x89, b'P', b'N', b'G'], b"three".to_vec()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_other_files() {
+        assert!(read_history(b"").is_err());
+        assert!(read_history(&[0; PAGE_SIZE * 2]).is_err());
+
+        let mut truncated = database(vec![
+            meta(0, 2, 4),
+            meta(1, 2, 3),
+            page(2, LEAF_PAGE, &[(BUCKET_LEAF, b"b", &bucket(7, &[]))]),
+        ]);
+        assert!(read_history(&truncated).is_err());
+
+        truncated.truncate(PAGE_SIZE * 2 + PAGE_HEADER_SIZE + 4);
+        assert!(read_history(&truncated).is_err());
+    }
+}
@@ -22,7 +22,8 @@ pub fn truncate(s: &str, max_chars: usize) -> String {
     }
 }
 
-fn detect_image(payload: &[u8]) -> Option<String> {
+/// `[[ binary data 233 bytes image/png 32x32 ]]` when `payload` is an image
+pub(crate) fn detect_image(payload: &[u8]) -> Option<String> {
     let Ok(image_reader) = ImageReader::new(Cursor::new(&payload)).with_guessed_format() else {
         return None;
     };
@@ -37,14 +38,32 @@ fn detect_image(payload: &[u8]) -> Option<String> {
     Some(output)
 }
 
+/// Text of the clip, or a description of it when it isn't text
+pub fn describe(entry: &ClipEntry) -> String {
+    if let Some(image) = detect_image(entry.bytes()) {
+        return image;
+    }
+    let mime_type = entry.mime_type().unwrap_or_default();
+
+    entry.text().unwrap_or_else(|_| {
+        format!(
+            "[[ binary data {} {mime_type} ]]",
+            // Listed clips leave what isn't text in files
+            Size::from_bytes(entry.len_of(mime_type).unwrap_or_default())
+        )
+    })
+}
+
+/// [`describe`] cut down to `width` characters, 0 leaves it whole
+pub fn preview(entry: &ClipEntry, width: usize) -> String {
+    match width {
+        0 => describe(entry),
+        _ => truncate(&describe(entry), width),
+    }
+}
+
 pub fn format_entry(entry: &ClipEntry, width: usize, include_dates: bool) -> String {
-    let payload = match detect_image(&entry.payload) {
-        Some(image) => image,
-        None => match width {
-            0 => entry.text().unwrap(),
-            _ => truncate(&entry.text().unwrap(), width),
-        },
-    };
+    let payload = preview(entry, width);
 
     let date = entry.epoch.0.format("%c").to_string();
     match include_dates {
@@ -0,0 +1,190 @@
+//! Images drawn straight into the terminal, with the kitty graphics protocol or as sixels
+
+use std::{
+    collections::BTreeSet,
+    env,
+    io::{stdout, Cursor, IsTerminal, Write},
+};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::ValueEnum;
+use color_quant::NeuQuant;
+use image::{DynamicImage, ImageFormat};
+use itertools::Itertools;
+
+/// Most base64 the kitty protocol takes in a single escape sequence
+const KITTY_CHUNK: usize = 4096;
+const SIXEL_COLORS: usize = 256;
+
+/// How images are shown
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum Images {
+    /// Described in text, ie: `[[ binary data 233 bytes image/png 32x32 ]]`
+    #[default]
+    Text,
+    /// Drawn with the kitty graphics protocol, also understood by WezTerm and Ghostty
+    Kitty,
+    /// Drawn as sixels, for foot, mlterm, xterm and the like
+    Sixel,
+    /// Described in text along with the path of a cached thumbnail, the way rofi and fuzzel take
+    /// icons
+    Icon,
+}
+
+impl Images {
+    /// Whichever protocol the terminal on `stdout` says it speaks through its environment
+    pub fn detect() -> Self {
+        if !stdout().is_terminal() {
+            return Self::Text;
+        }
+        let var = |name| env::var(name).unwrap_or_default();
+        let term = var("TERM");
+
+        match var("TERM_PROGRAM").as_str() {
+            "WezTerm" | "ghostty" => Self::Kitty,
+            _ if env::var_os("KITTY_WINDOW_ID").is_some() || term.contains("kitty") => Self::Kitty,
+            _ if term.starts_with("foot")
+                || term.starts_with("mlterm")
+                || term.contains("sixel") =>
+                Self::Sixel,
+            _ => Self::Text,
+        }
+    }
+
+    /// Whether images are drawn in the terminal
+    pub fn draws(&self) -> bool {
+        matches!(self, Self::Kitty | Self::Sixel)
+    }
+}
+
+/// `image` scaled down to fit in `width` by `height` pixels, never up
+pub fn fit(image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+    match image.width() <= width && image.height() <= height {
+        true => image.clone(),
+        false => image.thumbnail(width, height),
+    }
+}
+
+/// Draws `image` at the cursor then moves to the line below it. Does nothing unless `images`
+/// [draws](Images::draws)
+pub fn draw(out: &mut impl Write, image: &DynamicImage, images: Images) -> Result<()> {
+    match images {
+        Images::Kitty => kitty(out, image),
+        Images::Sixel => sixel(out, image),
+        Images::Text | Images::Icon => Ok(()),
+    }
+}
+
+/// Sent as PNG, split over as many escape sequences as it takes
+fn kitty(out: &mut impl Write, image: &DynamicImage) -> Result<()> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    let encoded = STANDARD.encode(png);
+    let chunks = encoded.as_bytes().chunks(KITTY_CHUNK).collect::<Vec<_>>();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        // Transmitted and shown at once, without the terminal answering back
+        match i {
+            0 => write!(out, "\x1b_Ga=T,f=100,q=2,m={more};")?,
+            _ => write!(out, "\x1b_Gm={more};")?,
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Quantized to [`SIXEL_COLORS`], mostly transparent pixels are left out
+fn sixel(out: &mut impl Write, image: &DynamicImage) -> Result<()> {
+    let rgba = image.to_rgba8();
+    let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+    let quant = NeuQuant::new(10, SIXEL_COLORS, rgba.as_raw());
+    let colors = rgba
+        .pixels()
+        .map(|pixel| (pixel[3] >= 128).then(|| quant.index_of(&pixel.0)))
+        .collect::<Vec<_>>();
+
+    // Pixels left unset stay transparent
+    write!(out, "\x1bP0;1q\"1;1;{width};{height}")?;
+    for (i, rgb) in quant.color_map_rgb().chunks(3).enumerate() {
+        let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|c| u32::from(c) * 100 / 255);
+        write!(out, "#{i};2;{r};{g};{b}")?;
+    }
+
+    // Each band is six rows of pixels, drawn a color at a time over each other
+    for top in (0..height).step_by(6) {
+        let rows = top..(top + 6).min(height);
+        let band = rows.clone().flat_map(|y| &colors[y * width..(y + 1) * width]);
+        for color in band.flatten().collect::<BTreeSet<_>>() {
+            let sixels = (0..width).map(|x| {
+                let bits = rows
+                    .clone()
+                    .enumerate()
+                    .filter(|(_, y)| colors[y * width + x] == Some(*color))
+                    .fold(0, |bits, (bit, _)| bits | 1 << bit);
+                char::from(63 + bits)
+            });
+
+            write!(out, "#{color}")?;
+            for (run, sixel) in sixels.dedup_with_count() {
+                match run {
+                    1..=3 => write!(out, "{}", sixel.to_string().repeat(run))?,
+                    run => write!(out, "!{run}{sixel}")?,
+                }
+            }
+            write!(out, "$")?;
+        }
+        write!(out, "-")?;
+    }
+    write!(out, "\x1b\\")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn render(images: Images, image: RgbaImage) -> Result<String> {
+        let mut out = Vec::new();
+        draw(&mut out, &DynamicImage::ImageRgba8(image), images)?;
+
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn it_draws_images() -> Result<()> {
+        let mut image = RgbaImage::from_pixel(8, 7, Rgba([255, 0, 0, 255]));
+        image.put_pixel(0, 6, Rgba([0, 0, 0, 0]));
+
+        let sixel = render(Images::Sixel, image.clone())?;
+        assert!(sixel.starts_with("\x1bP0;1q\"1;1;8;7#0;2;"));
+        assert!(sixel.ends_with("$-\x1b\\"));
+        // A full band of red, then a row of it missing the transparent pixel
+        assert!(sixel.contains("!8~$-"));
+        assert!(sixel.contains("?!7@$-"));
+        assert_eq!(render(Images::Text, image.clone())?, "");
+
+        // Noise so the PNG doesn't compress into a single chunk
+        let large = RgbaImage::from_fn(64, 64, |_, _| Rgba(rand::random()));
+        let kitty = render(Images::Kitty, large)?;
+        let chunks = kitty.matches("\x1b_G").count();
+        assert!(chunks > 1);
+        assert!(kitty.starts_with("\x1b_Ga=T,f=100,q=2,m=1;"));
+        assert_eq!(kitty.matches("m=1;").count(), chunks - 1);
+        assert_eq!(kitty.matches("\x1b_Gm=0;").count(), 1);
+        assert!(kitty.ends_with("\x1b\\\n"));
+
+        let image = DynamicImage::ImageRgba8(image);
+        assert_eq!(fit(&image, 4, 100).width(), 4);
+        assert_eq!(fit(&image, 100, 100).width(), 8);
+        Ok(())
+    }
+}
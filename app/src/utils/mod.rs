@@ -1,6 +1,12 @@
 #[allow(clippy::module_inception)]
 pub mod utils;
 
+pub mod archive;
+pub mod cliphist;
 pub mod formatting;
+pub mod graphics;
+pub mod output;
+pub mod template;
+pub mod thumbnails;
 
 pub use utils::*;
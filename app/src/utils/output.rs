@@ -0,0 +1,324 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
+use clippy_daemon::database::{ClipEntry, Selection};
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{
+    formatting::{format_entry, preview},
+    graphics::{draw, fit, Images},
+    template::fill,
+    thumbnails::{image_in_file, image_of, Thumbnails},
+};
+
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum Format {
+    /// `<id> <preview>` lines meant for reading
+    #[default]
+    Plain,
+    /// A single array of clips
+    Json,
+    /// One clip object per line
+    Jsonl,
+    /// Tab separated id, timestamp, application, MIME type, size, selection, pinned and preview.
+    /// Tabs, newlines and backslashes in values are escaped
+    Tsv,
+    /// `<id> <preview>` entries ending in a NUL byte instead of a newline
+    #[value(name = "null-delimited")]
+    NullDelimited,
+}
+
+/// Columns of `--format tsv`, in order
+const FIELDS: [&str; 8] = [
+    "id",
+    "timestamp",
+    "application",
+    "mime_type",
+    "size",
+    "selection",
+    "pinned",
+    "preview",
+];
+
+/// Pixels images drawn among listed clips are scaled down to fit in, about 8 rows of text
+const LISTED_IMAGE_SIZE: (u32, u32) = (320, 160);
+
+/// How a clip is described in machine readable output
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Record<'a> {
+    pub id: u64,
+    /// RFC 3339
+    pub timestamp: String,
+    pub application: Option<&'a str>,
+    pub mime_type: Option<&'a str>,
+    /// Of the preferred representation
+    pub size: usize,
+    pub selection: Selection,
+    pub pinned: bool,
+    pub preview: String,
+    /// Path of a cached thumbnail, only with `--images icon`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(entry: &'a ClipEntry, width: usize) -> Self {
+        let mime_type = entry.mime_type();
+
+        Self {
+            id: entry.id.unwrap_or_default(),
+            timestamp: entry.epoch.0.to_rfc3339(),
+            application: entry.application.as_deref(),
+            mime_type,
+            size: mime_type.and_then(|mime_type| entry.len_of(mime_type)).unwrap_or_default()
+                as usize,
+            selection: entry.selection,
+            pinned: entry.pinned,
+            preview: preview(entry, width),
+            thumbnail: None,
+        }
+    }
+
+    /// Every field, `thumbnail` too when there's none
+    fn fields(&self) -> Result<serde_json::Map<String, Value>> {
+        match serde_json::to_value(self)? {
+            Value::Object(mut fields) => {
+                fields.entry("thumbnail").or_insert(Value::Null);
+                Ok(fields)
+            },
+            _ => unreachable!("Records serialize to objects"),
+        }
+    }
+}
+
+/// Options shared by the commands that print clips
+#[derive(Args, Debug, PartialEq)]
+pub struct Output {
+    /// Includes dates clips were taken in the output
+    #[arg(short('d'), long, action)]
+    include_dates: bool,
+
+    /// Max characters to show of clips in preview. Use 0 to retain original width.
+    ///
+    /// This does not affect what is put back into the clipboard
+    #[arg(short('w'), long, default_value = "100")]
+    preview_width: usize,
+
+    /// How clips are printed
+    #[arg(short, long, value_enum, default_value_t)]
+    format: Format,
+
+    /// Prints a line per clip filling in `{id}`, `{timestamp}`, `{application}`, `{mime_type}`,
+    /// `{size}`, `{selection}`, `{pinned}`, `{preview}` and `{thumbnail}`. ie: `{id}\t{preview}`
+    ///
+    /// `\t`, `\n` and `\0` are understood. Tabs and newlines in values are escaped like `--format
+    /// tsv` does
+    #[arg(short, long, conflicts_with = "format")]
+    template: Option<String>,
+
+    /// How image clips are shown.
+    ///
+    /// `icon` ends plain lines with `\0icon\x1f<thumbnail>` for rofi and fuzzel, and fills in
+    /// `thumbnail` in every other format. `kitty` and `sixel` draw images below plain lines
+    #[arg(long, value_enum, default_value_t)]
+    images: Images,
+}
+
+impl Output {
+    /// Whether `entry` has to be recalled for its image to be shown, ie: to draw it or to make its
+    /// thumbnail. Listed clips leave images in files
+    pub fn needs_image(&self, entry: &ClipEntry, thumbnails: &Thumbnails) -> bool {
+        image_in_file(entry)
+            && match self.images {
+                Images::Icon => !thumbnails.is_cached(entry),
+                images => images.draws(),
+            }
+    }
+
+    /// Only plain output says so when there's nothing to print, anything else stays parsable
+    pub fn write(
+        &self,
+        out: &mut impl Write,
+        clips: &[ClipEntry],
+        empty: &str,
+        thumbnails: &Thumbnails,
+    ) -> Result<()> {
+        let width = self.preview_width;
+        let thumbnail = |entry| match self.images {
+            Images::Icon => thumbnails.path(entry).map(String::from),
+            _ => None,
+        };
+        let record = |entry| Record {
+            thumbnail: thumbnail(entry),
+            ..Record::new(entry, width)
+        };
+
+        if let Some(template) = &self.template {
+            let template = unescape(template);
+            for entry in clips {
+                let fields = record(entry).fields()?;
+                let line = fill(&template, |name| {
+                    fields
+                        .get(name)
+                        .map(|value| escape(&field_text(value)))
+                        .ok_or_else(|| anyhow!("Unknown field `{{{name}}}` in template"))
+                })?;
+                writeln!(out, "{line}")?;
+            }
+            return Ok(out.flush()?);
+        }
+
+        match self.format {
+            Format::Plain if clips.is_empty() => writeln!(out, "{empty}")?,
+            Format::Plain | Format::NullDelimited => {
+                let end = if self.format == Format::Plain {
+                    '\n'
+                } else {
+                    '\0'
+                };
+                for entry in clips {
+                    let preview = format_entry(entry, width, self.include_dates);
+                    write!(out, "{} {preview}", entry.id.unwrap_or_default())?;
+                    if let Some(thumbnail) = thumbnail(entry) {
+                        write!(out, "\0icon\x1f{thumbnail}")?;
+                    }
+                    write!(out, "{end}")?;
+
+                    if let Some(image) = image_of(entry).filter(|_| self.images.draws()) {
+                        let (width, height) = LISTED_IMAGE_SIZE;
+                        draw(out, &fit(&image, width, height), self.images)?;
+                    }
+                }
+            },
+            Format::Json => {
+                let records = clips.iter().map(record).collect::<Vec<_>>();
+                serde_json::to_writer(&mut *out, &records)?;
+                writeln!(out)?;
+            },
+            Format::Jsonl =>
+                for entry in clips {
+                    serde_json::to_writer(&mut *out, &record(entry))?;
+                    writeln!(out)?;
+                },
+            Format::Tsv => {
+                // The thumbnail column is only there when asked for, so it comes last
+                let icon = (self.images == Images::Icon).then_some("thumbnail");
+                for entry in clips {
+                    let fields = record(entry).fields()?;
+                    let values = FIELDS
+                        .into_iter()
+                        .chain(icon)
+                        .map(|field| escape(&field_text(&fields[field])));
+                    writeln!(out, "{}", values.collect::<Vec<_>>().join("\t"))?;
+                }
+            },
+        }
+
+        Ok(out.flush()?)
+    }
+}
+
+fn field_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Keeps a value on a single line and within its column
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Escape sequences that are awkward to type in a shell
+fn unescape(template: &str) -> String {
+    let mut unescaped = String::with_capacity(template.len());
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('0') => unescaped.push('\0'),
+            Some(other) => unescaped.extend(['\\', other]),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use camino::Utf8Path;
+    use clap::Parser;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        output: Output,
+    }
+
+    fn render(args: &[&str]) -> Result<String> {
+        let mut entry = ClipEntry::new(b"two\tcolumns\nand lines");
+        entry.id = Some(7);
+        entry.application = Some("Firefox".to_string());
+        let output =
+            Cli::try_parse_from(std::iter::once("clippy").chain(args.iter().copied()))?.output;
+        let mut out = Vec::new();
+        let thumbnails = Thumbnails::new(Utf8Path::new("db"), true);
+        output.write(&mut out, &[entry], "Clipboard is empty", &thumbnails)?;
+
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn it_writes_formats() -> Result<()> {
+        assert_eq!(render(&[])?, "7 two\tcolumns\nand lines\n");
+        assert_eq!(
+            render(&["-f", "null-delimited"])?,
+            "7 two\tcolumns\nand lines\0"
+        );
+
+        let json: Value = serde_json::from_str(&render(&["-f", "jsonl"])?)?;
+        assert_eq!(json["id"], 7);
+        assert_eq!(json["preview"], "two\tcolumns\nand lines");
+        assert_eq!(json["mime_type"], "text/plain;charset=utf-8");
+
+        let tsv = render(&["-f", "tsv"])?;
+        assert_eq!(tsv.matches('\t').count(), 7);
+        assert!(tsv.ends_with("\ttwo\\tcolumns\\nand lines\n"));
+        // Text clips have no thumbnail, the column is left empty
+        let tsv = render(&["-f", "tsv", "--images", "icon"])?;
+        assert!(tsv.ends_with("\ttwo\\tcolumns\\nand lines\t\n"));
+        assert_eq!(
+            render(&["--images", "kitty"])?,
+            "7 two\tcolumns\nand lines\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_fills_templates() -> Result<()> {
+        assert_eq!(
+            render(&["-t", r"{id}\t{application}: {preview}"])?,
+            "7\tFirefox: two\\tcolumns\\nand lines\n"
+        );
+        assert!(render(&["-t", "{nope}"]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use anyhow::{anyhow, Result};
+
+/// Replaces every `{placeholder}` in `template` with what `resolve` gives for its name.
+///
+/// `{{` and `}}` stand for literal braces.
+pub fn fill(template: &str, mut resolve: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        text.push_str(&rest[..start]);
+        let (brace, after) = rest[start..].split_at(1);
+
+        if let Some(after) = after.strip_prefix(brace) {
+            text.push_str(brace);
+            rest = after;
+            continue;
+        }
+
+        if brace == "}" {
+            return Err(anyhow!("Unmatched `}}`, use `}}}}` for a literal brace"));
+        }
+
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed `{{`, use `{{{{` for a literal brace"))?;
+        text.push_str(&resolve(&after[..end])?);
+        rest = &after[end + 1..];
+    }
+
+    text.push_str(rest);
+    Ok(text)
+}
@@ -0,0 +1,176 @@
+//! Scaled down copies of image clips, cached next to the database for pickers to show as icons
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{Cursor, ErrorKind},
+};
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use clippy_daemon::{
+    database::{ClipEntry, ContentHash},
+    utils::mime::is_image,
+};
+use image::{DynamicImage, ImageFormat, ImageReader};
+use tracing::warn;
+
+use super::{graphics::fit, load_config};
+
+/// Largest side of a cached thumbnail, in pixels
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+fn image_mime_type(entry: &ClipEntry) -> Option<&str> {
+    entry.mime_types().find(|mime_type| is_image(mime_type))
+}
+
+/// Bytes of the first image representation of `entry`
+fn image_bytes(entry: &ClipEntry) -> Option<&[u8]> {
+    entry.get(image_mime_type(entry)?)
+}
+
+/// Whether the first image representation of `entry` is still in a file, ie: it was listed
+pub fn image_in_file(entry: &ClipEntry) -> bool {
+    image_mime_type(entry).is_some_and(|mime_type| entry.blobs.contains_key(mime_type))
+}
+
+/// The first image representation of `entry` decoded, if it has one that decodes
+pub fn image_of(entry: &ClipEntry) -> Option<DynamicImage> {
+    decode(image_bytes(entry)?)
+}
+
+/// `bytes` decoded as whichever image format they look like
+pub fn decode(bytes: &[u8]) -> Option<DynamicImage> {
+    ImageReader::new(Cursor::new(bytes)).with_guessed_format().ok()?.decode().ok()
+}
+
+pub struct Thumbnails {
+    dir: Option<Utf8PathBuf>,
+}
+
+impl Thumbnails {
+    /// Cached in `<db_path>.thumbnails`. Nothing is cached for encrypted databases, thumbnails
+    /// would give away what was copied
+    pub fn new(db_path: &Utf8Path, encrypted: bool) -> Self {
+        Self {
+            dir: (!encrypted).then(|| Utf8PathBuf::from(format!("{db_path}.thumbnails"))),
+        }
+    }
+
+    /// Thumbnails of the database at `db_path`, encrypted or not going by the config
+    pub fn of_db(db_path: &Utf8Path) -> Result<Self> {
+        Ok(Self::new(db_path, load_config()?.encryption().is_some()))
+    }
+
+    /// Files are named after the image they're made from, so identical clips share one. Images
+    /// kept in files are already hashed that way
+    fn file_name(entry: &ClipEntry) -> Option<String> {
+        let mime_type = image_mime_type(entry)?;
+        let hash = match entry.blobs.get(mime_type) {
+            Some(blob) => blob.hash,
+            None => ContentHash::of(entry.get(mime_type)?),
+        };
+
+        Some(format!("{}.png", hash.to_hex()))
+    }
+
+    /// Whether `entry` has a thumbnail already, so its image doesn't have to be read
+    pub fn is_cached(&self, entry: &ClipEntry) -> bool {
+        let path = || Some(self.dir.as_ref()?.join(Self::file_name(entry)?));
+        path().is_some_and(|path| path.exists())
+    }
+
+    /// Path of the thumbnail of `entry`, made the first time it's asked for. Clips without an
+    /// image and sensitive clips don't get one
+    pub fn path(&self, entry: &ClipEntry) -> Option<Utf8PathBuf> {
+        if entry.is_sensitive() {
+            return None;
+        }
+        let path = self.dir.as_ref()?.join(Self::file_name(entry)?);
+
+        if !path.exists() {
+            let image = image_of(entry)?;
+            if let Err(e) = self.write(&image, &path) {
+                warn!(id = entry.id, "Couldn't cache thumbnail: {e}");
+                return None;
+            }
+        }
+        Some(path)
+    }
+
+    fn write(&self, image: &DynamicImage, path: &Utf8Path) -> Result<()> {
+        fs::create_dir_all(path.parent().unwrap())?;
+        let tmp = path.with_extension("tmp");
+        fit(image, THUMBNAIL_SIZE, THUMBNAIL_SIZE).save_with_format(&tmp, ImageFormat::Png)?;
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Removes the thumbnails of every clip but `clips`, ie: of clips no longer in history
+    pub fn retain(&self, clips: &[ClipEntry]) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let files = match fs::read_dir(dir) {
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            files => files?,
+        };
+        let kept = clips.iter().filter_map(Self::file_name).collect::<HashSet<_>>();
+
+        for file in files {
+            let file = file?;
+            if !file.file_name().to_str().is_some_and(|name| kept.contains(name)) {
+                fs::remove_file(file.path())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clippy_daemon::database::{Payload, Selection};
+    use image::{Rgb, RgbImage};
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Vec::new();
+        RgbImage::from_pixel(width, height, Rgb([0, 128, 255]))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn it_caches_thumbnails() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let db_path = tf.path("db");
+        let db_path = Utf8Path::new(db_path.as_str());
+        let thumbnails = Thumbnails::new(db_path, false);
+        let screenshot = ClipEntry::with_payload(
+            Payload::from([
+                ("text/html".to_string(), b"<img>".to_vec()),
+                ("image/png".to_string(), png(1024, 512)),
+            ]),
+            Selection::Clipboard,
+        );
+        let text = ClipEntry::new(b"no image here");
+
+        let path = thumbnails.path(&screenshot).unwrap();
+        let thumbnail = decode(&fs::read(&path)?).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 128));
+        assert_eq!(thumbnails.path(&text), None);
+        assert_eq!(Thumbnails::new(db_path, true).path(&screenshot), None);
+
+        thumbnails.retain(&[screenshot.clone(), text])?;
+        assert!(path.exists());
+        thumbnails.retain(&[])?;
+        assert!(!path.exists());
+        Ok(())
+    }
+}
@@ -3,6 +3,8 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use anyhow::Result;
+use clippy_daemon::utils::config::Config;
 use dirs::{cache_dir, config_dir};
 
 fn get_path(base_path: Option<PathBuf>, path: &str, name: &str) -> Option<String> {
@@ -19,6 +21,37 @@ pub fn get_cache_path(path: &str, name: &str) -> Option<String> {
     get_path(cache_dir(), path, name)
 }
 
+/// The config the daemon would use, or the defaults when there's none
+pub fn load_config() -> Result<Config> {
+    let config_path = get_config_path("clippy", "config.toml").unwrap();
+    Config::load(Path::new(&config_path))
+}
+
+/// The database the daemon records into going by the config, so commands default to the same one
+pub fn default_db_path() -> String {
+    load_config()
+        .ok()
+        .and_then(|config| config.db_path())
+        .or_else(|| get_cache_path("clippy", "db"))
+        .unwrap()
+}
+
+/// `keep_duplicates`/`remove_duplicates` from the config, so stored clips are deduplicated like the
+/// daemon's
+pub fn default_duplicates() -> String {
+    load_config().unwrap_or_default().clipboard().duplicates().to_string()
+}
+
+/// `max_size` from the config, so stored clips are trimmed like the daemon's
+pub fn default_keep() -> String {
+    load_config()
+        .unwrap_or_default()
+        .clipboard()
+        .max_size
+        .unwrap_or_default()
+        .to_string()
+}
+
 #[cfg(test)]
 use rand::{distributions::Alphanumeric, Rng};
 
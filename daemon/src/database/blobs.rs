@@ -0,0 +1,275 @@
+//! Representations too large to keep inline, each in a file of its own next to the database.
+//!
+//! Files are named after the [`ContentHash`] of their bytes so identical screenshots share one,
+//! and are encrypted like records are. Clips recalled one at a time are handed out with their
+//! representations loaded back into the payload. Listed ones only get their preferred text back,
+//! see [`Blobs::load_preview`], the rest keep referencing files through [`ClipEntry::blobs`].
+//!
+//! A file goes once no row references it anymore, checked with [`Blobs::release`] after clips
+//! are removed. Whatever is left over by a crash is cleaned up by [`Blobs::sweep`], which the
+//! daemon runs when it opens a database and once clips are removed through it.
+
+use std::{collections::HashSet, fs, io::ErrorKind};
+
+use anyhow::{anyhow, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::{debug, warn};
+
+use super::{encryption::DatabaseKey, Blob, ClipEntry, ContentHash, Database, RwTransaction};
+use crate::utils::mime::is_text;
+
+/// Representations over this many bytes are kept in files
+pub const INLINE_LIMIT: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct Blobs {
+    dir: Utf8PathBuf,
+    /// The one of the database, files are sealed like its records
+    key: DatabaseKey,
+}
+
+impl Blobs {
+    /// Files of the database at `db_path`, kept in `<db_path>.blobs`
+    pub fn new(db_path: &Utf8Path, key: DatabaseKey) -> Self {
+        Self {
+            dir: Utf8PathBuf::from(format!("{db_path}.blobs")),
+            key,
+        }
+    }
+
+    fn path(&self, hash: &ContentHash) -> Utf8PathBuf {
+        self.dir.join(hash.to_hex())
+    }
+
+    /// Moves the representations of `entry` over [`INLINE_LIMIT`] to files. Has to happen inside
+    /// the transaction storing it so [`Self::release`] can't remove a file it's about to reference
+    pub fn spill(&self, entry: &mut ClipEntry) -> Result<()> {
+        let large = entry
+            .payload
+            .iter()
+            .filter(|(_, bytes)| bytes.len() > INLINE_LIMIT)
+            .map(|(mime_type, _)| mime_type.clone())
+            .collect::<Vec<_>>();
+
+        for mime_type in large {
+            let bytes = entry.payload.remove(&mime_type).unwrap();
+            let blob = Blob {
+                hash: ContentHash::of(&bytes),
+                size: bytes.len() as u64,
+            };
+            let path = self.path(&blob.hash);
+
+            if !path.exists() {
+                fs::create_dir_all(&self.dir)?;
+                let tmp = path.with_extension("tmp");
+                fs::write(&tmp, self.key.seal(bytes).map_err(|err| anyhow!(err))?)?;
+                fs::rename(tmp, &path)?;
+            }
+            entry.blobs.insert(mime_type, blob);
+        }
+
+        Ok(())
+    }
+
+    /// Moves every representation of `entry` kept in a file back into its payload
+    pub fn load(&self, entry: &mut ClipEntry) {
+        self.load_where(entry, |_| true);
+    }
+
+    /// Loads the preferred representation of `entry` when it's text, all a listing shows of a
+    /// clip. Images and anything else stay in files until the clip is recalled on its own
+    pub fn load_preview(&self, entry: &mut ClipEntry) {
+        let Some(preferred) = entry.mime_type().filter(|mime_type| is_text(mime_type)) else {
+            return;
+        };
+        let preferred = preferred.to_string();
+
+        self.load_where(entry, |mime_type| mime_type == preferred);
+    }
+
+    /// Like [`Self::load`] for the representations whose MIME type passes `filter`, the others
+    /// stay in files. Missing or unreadable files are left out
+    pub fn load_where(&self, entry: &mut ClipEntry, filter: impl Fn(&str) -> bool) {
+        let (loaded, kept) = std::mem::take(&mut entry.blobs)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(mime_type, _)| filter(mime_type));
+        entry.blobs.extend(kept);
+
+        for (mime_type, blob) in loaded {
+            let bytes = fs::read(self.path(&blob.hash))
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| self.key.unseal(bytes));
+            match bytes {
+                Ok(bytes) => {
+                    entry.payload.insert(mime_type, bytes);
+                },
+                Err(err) => {
+                    warn!(
+                        id = entry.id,
+                        mime_type, "Leaving out a representation: {err}"
+                    );
+                },
+            }
+        }
+    }
+
+    /// Removes the files of `removed` clips no other clip references, once they're committed
+    pub fn release(&self, db: &Database, removed: &[ClipEntry]) -> Result<()> {
+        let candidates = removed
+            .iter()
+            .flat_map(|entry| entry.blobs.values().map(|blob| blob.hash))
+            .collect::<HashSet<_>>();
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        // Never committed, it only keeps clips from being stored meanwhile
+        let tx = db.rw_transaction()?;
+        let Some(referenced) = referenced(&tx)? else {
+            return Ok(());
+        };
+        for hash in candidates.difference(&referenced) {
+            debug!("Removing a file no clip references");
+            remove(&self.path(hash))?;
+        }
+        tx.abort()?;
+
+        Ok(())
+    }
+
+    /// Removes every file no clip references, ie: left behind by a crash or a key change
+    pub fn sweep(&self, db: &Database) -> Result<()> {
+        let files = match fs::read_dir(&self.dir) {
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            files => files?,
+        };
+        let tx = db.rw_transaction()?;
+        let Some(referenced) = referenced(&tx)? else {
+            return Ok(());
+        };
+        let referenced = referenced.iter().map(|hash| self.path(hash)).collect::<HashSet<_>>();
+
+        for file in files {
+            let path = Utf8PathBuf::try_from(file?.path())?;
+            if !referenced.contains(&path) {
+                debug!(%path, "Removing a file no clip references");
+                remove(&path)?;
+            }
+        }
+        tx.abort()?;
+
+        Ok(())
+    }
+}
+
+/// Hashes of every file a clip references. `None` when some row can't be read, the files it
+/// references are unknown so none can go
+fn referenced(tx: &RwTransaction) -> Result<Option<HashSet<ContentHash>>> {
+    let mut hashes = HashSet::new();
+    let mut unreadable = 0;
+    for entry in tx.scan().primary::<ClipEntry>()?.all()? {
+        match entry {
+            Ok(entry) => hashes.extend(entry.blobs.into_values().map(|blob| blob.hash)),
+            Err(err) => {
+                debug!("Skipping a clip that can't be read: {err}");
+                unreadable += 1;
+            },
+        }
+    }
+    if unreadable > 0 {
+        warn!(unreadable, "Keeping every file, some clips can't be read");
+        return Ok(None);
+    }
+
+    Ok(Some(hashes))
+}
+
+/// Already gone is fine
+fn remove(path: &Utf8Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+    use crate::{
+        database::{
+            get_clip, get_clips, get_db, remove_clip, store_clip, Database, Payload, Selection,
+        },
+        utils::filter::Filters,
+    };
+
+    fn files(db: &Database) -> usize {
+        fs::read_dir(&db.blobs.dir).map_or(0, Iterator::count)
+    }
+
+    #[test]
+    fn it_keeps_large_clips_in_files() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("db");
+        let path = Utf8Path::new(path.as_str());
+        let large = vec![b'a'; INLINE_LIMIT + 1];
+
+        let db = get_db(path)?;
+        store_clip(&db, ClipEntry::new(b"small"), &Filters::default(), 0, 0)?;
+        store_clip(&db, ClipEntry::new(&large), &Filters::default(), 1, 0)?;
+        store_clip(&db, ClipEntry::new(&large), &Filters::default(), 1, 0)?;
+        assert_eq!(files(&db), 1);
+
+        let loaded = get_clip(&db, 2)?.unwrap();
+        let stored = db.r_transaction()?.get().primary::<ClipEntry>(loaded.epoch)?.unwrap();
+        assert!(stored.payload.is_empty());
+        assert_eq!(stored.size(), large.len() as u64);
+        assert_eq!(stored.content_hash(), loaded.content_hash());
+        assert_eq!(loaded.bytes(), large);
+        assert_eq!(remove_clip(&db, 3)?.unwrap().bytes(), large);
+        // Still referenced by the other copy
+        assert_eq!(files(&db), 1);
+
+        remove_clip(&db, 2)?;
+        assert_eq!(files(&db), 0);
+
+        fs::write(db.blobs.dir.join("leftover.tmp"), b"")?;
+        drop(db);
+        let db = get_db(path)?;
+        assert_eq!(files(&db), 1);
+        db.sweep()?;
+        assert_eq!(files(&db), 0);
+        assert_eq!(get_clip(&db, 1)?.unwrap().bytes(), b"small");
+        Ok(())
+    }
+
+    #[test]
+    fn it_lists_clips_without_reading_images() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let db = get_db(Utf8Path::new(tf.path("db").as_str()))?;
+        let text = vec![b'a'; INLINE_LIMIT + 1];
+        let image = vec![0; INLINE_LIMIT + 1];
+        let payload = Payload::from([
+            ("image/png".to_string(), image.clone()),
+            ("text/html".to_string(), b"<img>".to_vec()),
+        ]);
+        store_clip(&db, ClipEntry::new(&text), &Filters::default(), 0, 0)?;
+        store_clip(
+            &db,
+            ClipEntry::with_application(payload, Selection::Clipboard, None),
+            &Filters::default(),
+            0,
+            0,
+        )?;
+
+        let listed = get_clips(&db, &None)?;
+        assert_eq!(listed[0].bytes(), text);
+        assert_eq!(listed[1].get("image/png"), None);
+        assert_eq!(listed[1].len_of("image/png"), Some(image.len() as u64));
+        assert_eq!(listed[1].get("text/html"), Some(&b"<img>"[..]));
+        assert_eq!(get_clip(&db, 2)?.unwrap().bytes(), image);
+        Ok(())
+    }
+}
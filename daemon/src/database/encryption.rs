@@ -0,0 +1,444 @@
+//! Encryption at rest of everything written through the `Bincode` codec, turned on with
+//! `[general.encryption]`.
+//!
+//! Records are sealed with XChaCha20-Poly1305 under a key derived with Argon2 from the secret
+//! [`Encryption`] points at. The salt, along with a sealed marker telling a wrong key apart from a
+//! corrupt database, is kept unencrypted in the [`Sealing`] row. Primary and secondary keys, ie:
+//! timestamps and ids, stay readable. Content hashes are keyed so they can't be matched against
+//! known clips.
+//!
+//! native_db removes and updates rows by comparing their encoded bytes, so sealing has to be
+//! deterministic. Nonces are a keyed hash of the record instead of random, which only gives away
+//! that two records are identical. Every record holds its own timestamp so none are.
+//!
+//! Codecs can't carry state, so every [`Database`] holds a [`DatabaseKey`] its transactions make the one
+//! records are sealed with on their thread while they're around. It has to be set before anything
+//! is written, a row written under another key can't be removed. Records read outside of a
+//! transaction fail rather than going through the key of some other database.
+
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use super::{ClipEntry, Counter, Database, RwTransaction, Sealing, Snippet, ToInput};
+use crate::utils::config::Encryption;
+
+/// Starts every sealed record. No bincode value starts with `0xFF` so plaintext never looks sealed
+const MARKER: [u8; 4] = [0xFF, b'C', b'S', 1];
+const NONCE_SIZE: usize = 24;
+const SALT_SIZE: usize = 16;
+/// Sealed into [`Sealing::check`] to find out whether a key is the right one
+const CHECK: &[u8] = b"clippy";
+pub(crate) const SEALING_ID: u8 = 0;
+
+struct Keyring {
+    cipher: XChaCha20Poly1305,
+    nonce_key: Zeroizing<[u8; 32]>,
+    hash_key: Zeroizing<[u8; 32]>,
+    /// Unset while decrypting, records are still read with the key but written in plaintext
+    seal: bool,
+}
+
+/// The key of a single database, shared with everything reading it. Empty while it's in plaintext
+#[derive(Clone, Default)]
+pub struct DatabaseKey(Arc<RwLock<Option<Keyring>>>);
+
+thread_local! {
+    /// Keys of the databases with a transaction open on this thread, the last one is used
+    static ACTIVE: RefCell<Vec<DatabaseKey>> = const { RefCell::new(Vec::new()) };
+}
+
+/// See [`DatabaseKey::enter`]. Stays on the thread it was made on
+pub struct KeyScope {
+    key: DatabaseKey,
+    _thread: PhantomData<*const ()>,
+}
+
+/// Readies the key of `db` before anything is read from it.
+///
+/// Without `encryption` the database has to be in plaintext. With it, an empty database is
+/// encrypted right away while one with clips has to go through [`encrypt`] first.
+pub fn unlock(db: &Database, encryption: Option<&Encryption>) -> Result<()> {
+    let sealing = db.r_transaction()?.get().primary::<Sealing>(SEALING_ID)?;
+
+    match (sealing, encryption) {
+        (None, None) => Ok(()),
+        (Some(_), None) => Err(anyhow!(
+            "The database is encrypted but no key is configured. Set `[general.encryption]` in \
+            config.toml"
+        )),
+        (Some(sealing), Some(encryption)) => {
+            let keyring = Keyring::derive(&encryption.secret()?, &sealing.salt)?;
+            keyring
+                .unseal(&sealing.check)
+                .ok()
+                .filter(|check| check == CHECK)
+                .ok_or_else(|| anyhow!("The database key is wrong"))?;
+
+            db.key.set(Some(keyring));
+            Ok(())
+        },
+        (None, Some(encryption)) => {
+            let tx = db.r_transaction()?;
+            if tx.len().primary::<ClipEntry>()? > 0 || tx.len().primary::<Snippet>()? > 0 {
+                return Err(anyhow!(
+                    "The database isn't encrypted yet, run `clippy encrypt` first"
+                ));
+            }
+            encrypt(db, encryption)
+        },
+    }
+}
+
+/// Rewrites every record of a plaintext database sealed with a key from `encryption`
+pub fn encrypt(db: &Database, encryption: &Encryption) -> Result<()> {
+    if db.r_transaction()?.get().primary::<Sealing>(SEALING_ID)?.is_some() {
+        return Err(anyhow!("The database is already encrypted"));
+    }
+
+    let mut salt = vec![0; SALT_SIZE];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let keyring = Keyring::derive(&encryption.secret()?, &salt)?;
+    let check = keyring.seal(CHECK).map_err(|err| anyhow!(err))?;
+
+    rewrite(
+        db,
+        || db.key.set(Some(keyring)),
+        |tx| {
+            tx.insert(Sealing {
+                id: SEALING_ID,
+                salt,
+                check,
+            })?;
+            Ok(())
+        },
+    )
+}
+
+/// Rewrites every record of a database opened with [`unlock`] in plaintext
+pub fn decrypt(db: &Database) -> Result<()> {
+    let sealing = db
+        .r_transaction()?
+        .get()
+        .primary::<Sealing>(SEALING_ID)?
+        .ok_or_else(|| anyhow!("The database isn't encrypted"))?;
+
+    rewrite(
+        db,
+        || {
+            if let Some(keyring) = db.key.0.write().unwrap().as_mut() {
+                keyring.seal = false;
+            }
+        },
+        |tx| {
+            tx.remove(sealing)?;
+            Ok(())
+        },
+    )
+}
+
+/// Writes every record back in a single transaction, encoded the way they are after `switch`.
+///
+/// Rows are matched by their encoded bytes when removed, so they all go before anything changes.
+/// Files of large clips are written anew too, the old ones go once the rows are committed
+fn rewrite(
+    db: &Database,
+    switch: impl FnOnce(),
+    finish: impl FnOnce(&RwTransaction) -> Result<()>,
+) -> Result<()> {
+    let tx = db.rw_transaction()?;
+    let old = take_all::<ClipEntry>(&tx)?;
+    let counters = take_all::<Counter>(&tx)?;
+    let snippets = take_all::<Snippet>(&tx)?;
+    let mut clips = old.clone();
+    for entry in &mut clips {
+        db.blobs.load(entry);
+    }
+
+    switch();
+    for entry in &mut clips {
+        db.blobs.spill(entry)?;
+    }
+    put_all(&tx, clips)?;
+    put_all(&tx, counters)?;
+    put_all(&tx, snippets)?;
+    finish(&tx)?;
+    tx.commit()?;
+
+    db.release(&old)
+}
+
+fn take_all<T: ToInput + Clone>(tx: &RwTransaction) -> Result<Vec<T>> {
+    let rows = tx.scan().primary::<T>()?.all()?.collect::<Result<Vec<T>, _>>()?;
+
+    for row in &rows {
+        tx.remove(row.clone())?;
+    }
+
+    Ok(rows)
+}
+
+fn put_all<T: ToInput>(tx: &RwTransaction, rows: Vec<T>) -> Result<()> {
+    for row in rows {
+        tx.insert(row)?;
+    }
+
+    Ok(())
+}
+
+impl Keyring {
+    fn derive(secret: &[u8], salt: &[u8]) -> Result<Self> {
+        let mut keys = Zeroizing::new([0; 64]);
+        Argon2::default()
+            .hash_password_into(secret, salt, keys.as_mut())
+            .map_err(|err| anyhow!("Failed to derive the database key: {err}"))?;
+        let mut nonce_key = Zeroizing::new([0; 32]);
+        nonce_key.copy_from_slice(&keys[32..]);
+        let hash_key = Zeroizing::new(blake3::derive_key("clippy content hash", &keys[32..]));
+
+        Ok(Self {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(&keys[..32])),
+            nonce_key,
+            hash_key,
+            seal: true,
+        })
+    }
+
+    fn seal(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let hash = blake3::keyed_hash(&self.nonce_key, data);
+        let nonce = XNonce::from_slice(&hash.as_bytes()[..NONCE_SIZE]);
+        let sealed = self
+            .cipher
+            .encrypt(
+                nonce,
+                Payload {
+                    msg: data,
+                    aad: &MARKER,
+                },
+            )
+            .map_err(|_| "Failed to encrypt record".to_string())?;
+
+        Ok([&MARKER[..], nonce, &sealed].concat())
+    }
+
+    fn unseal(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let (nonce, sealed) = data
+            .get(MARKER.len()..)
+            .and_then(|data| data.split_at_checked(NONCE_SIZE))
+            .ok_or_else(|| "Encrypted record is truncated".to_string())?;
+
+        self.cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: &MARKER,
+                },
+            )
+            .map_err(|_| "Failed to decrypt record, the database may be corrupt".to_string())
+    }
+}
+
+impl DatabaseKey {
+    fn set(&self, keyring: Option<Keyring>) {
+        *self.0.write().unwrap() = keyring;
+    }
+
+    /// Makes this the key the codec goes by on this thread until the scope is dropped. Every
+    /// transaction of a [`Database`] holds one
+    pub(super) fn enter(&self) -> KeyScope {
+        ACTIVE.with_borrow_mut(|keys| keys.push(self.clone()));
+
+        KeyScope {
+            key: self.clone(),
+            _thread: PhantomData,
+        }
+    }
+
+    pub(super) fn seal(&self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        match self.0.read().unwrap().as_ref() {
+            Some(keyring) if keyring.seal => keyring.seal(&data),
+            _ => Ok(data),
+        }
+    }
+
+    /// Plaintext records are only passed through while the database isn't encrypted, or is being
+    /// decrypted, so none can be slipped into an encrypted one
+    pub(super) fn unseal(&self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let keyring = self.0.read().unwrap();
+
+        match (keyring.as_ref(), data.starts_with(&MARKER)) {
+            (Some(keyring), true) => keyring.unseal(&data),
+            (Some(keyring), false) if keyring.seal =>
+                Err("Record isn't encrypted but the database is".to_string()),
+            (_, false) => Ok(data),
+            (None, true) =>
+                Err("Record is encrypted but the database wasn't unlocked".to_string()),
+        }
+    }
+
+    /// Keyed whenever records are sealed
+    fn hasher(&self) -> blake3::Hasher {
+        match self.0.read().unwrap().as_ref() {
+            Some(keyring) if keyring.seal => blake3::Hasher::new_keyed(&keyring.hash_key),
+            _ => blake3::Hasher::new(),
+        }
+    }
+}
+
+/// Never shows the key itself
+impl std::fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatabaseKey").finish_non_exhaustive()
+    }
+}
+
+impl Drop for KeyScope {
+    fn drop(&mut self) {
+        ACTIVE.with_borrow_mut(|keys| {
+            if let Some(i) = keys.iter().rposition(|key| Arc::ptr_eq(&key.0, &self.key.0)) {
+                keys.remove(i);
+            }
+        });
+    }
+}
+
+fn active() -> Option<DatabaseKey> {
+    ACTIVE.with_borrow(|keys| keys.last().cloned())
+}
+
+/// Used by the codec on every record written
+pub(super) fn seal(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    active()
+        .ok_or_else(|| "Record written outside of a transaction".to_string())?
+        .seal(data)
+}
+
+/// Used by the codec on every record read. [`Sealing`] isn't read through here
+pub(super) fn unseal(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    active()
+        .ok_or_else(|| "Record read outside of a transaction".to_string())?
+        .unseal(data)
+}
+
+/// Used for [`ClipEntry::content_hash`], with the key of the transaction it's part of. Unkeyed
+/// outside of one, as for a plaintext database
+pub(super) fn content_hasher() -> blake3::Hasher {
+    active().map_or_else(blake3::Hasher::new, |key| key.hasher())
+}
+
+#[cfg(test)]
+mod test {
+    use camino::Utf8Path;
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+    use crate::{
+        database::{get_clips, open_db, remove_clip, store_clip},
+        utils::filter::Filters,
+    };
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|window| window == needle)
+    }
+
+    /// Contents of the files large clips of the database at `path` are kept in
+    fn blobs(path: &Utf8Path) -> Result<Vec<Vec<u8>>> {
+        std::fs::read_dir(format!("{path}.blobs"))?
+            .map(|file| Ok(std::fs::read(file?.path())?))
+            .collect()
+    }
+
+    fn store(db: &Database, clip: &[u8]) -> Result<()> {
+        store_clip(db, ClipEntry::new(clip), &Filters::default(), 0, 0)
+    }
+
+    #[test]
+    fn it_seals_records() -> Result<()> {
+        let keyring = Keyring::derive(b"hunter2", &[7; SALT_SIZE])?;
+        let other = Keyring::derive(b"hunter3", &[7; SALT_SIZE])?;
+        let sealed = keyring.seal(b"secret clip").map_err(|err| anyhow!(err))?;
+
+        assert!(sealed.starts_with(&MARKER));
+        assert!(!sealed.windows(6).any(|window| window == b"secret"));
+        assert_eq!(keyring.seal(b"secret clip"), Ok(sealed.clone()));
+        assert_ne!(keyring.seal(b"secret clip!"), Ok(sealed.clone()));
+        assert_eq!(keyring.unseal(&sealed), Ok(b"secret clip".to_vec()));
+        assert!(other.unseal(&sealed).is_err());
+        assert!(keyring.unseal(&sealed[..20]).is_err());
+        assert_eq!(DatabaseKey::default().unseal(b"plain".to_vec()), Ok(b"plain".to_vec()));
+
+        // Plaintext slipped into an encrypted database
+        let key = DatabaseKey::default();
+        key.set(Some(keyring));
+        assert!(key.unseal(b"plain".to_vec()).is_err());
+        assert_eq!(key.unseal(sealed), Ok(b"secret clip".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn it_encrypts_databases() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("db");
+        let path = Utf8Path::new(path.as_str());
+        std::fs::write(tf.path("key"), "correct horse\n")?;
+        let encryption = Encryption::Keyfile {
+            path: tf.path("key").to_string(),
+        };
+        let wrong = Encryption::Command {
+            command: "echo battery staple".to_string(),
+        };
+        let large = "secret clip ".repeat(10_000);
+
+        {
+            let mut db = open_db(path, None)?;
+            store(&db, b"secret clip")?;
+            store(&db, large.as_bytes())?;
+            assert!(unlock(&db, Some(&encryption)).is_err());
+
+            encrypt(&db, &encryption)?;
+            // Found through the keyed hash index and replaced
+            store(&db, b"another clip")?;
+            store(&db, b"another clip")?;
+            db.compact()?;
+        }
+        assert!(!contains(&std::fs::read(path)?, b"secret clip"));
+        // Written again under the key, the plaintext file is gone
+        let files = blobs(path)?;
+        assert_eq!(files.len(), 1);
+        assert!(!contains(&files[0], b"secret clip"));
+        assert!(open_db(path, None).is_err());
+        assert!(open_db(path, Some(&wrong)).is_err());
+
+        let plaintext = open_db(Utf8Path::new(tf.path("plaintext").as_str()), None)?;
+        store(&plaintext, b"plain clip")?;
+
+        {
+            let db = open_db(path, Some(&encryption))?;
+            // Doesn't go through the key of the other
+            assert_eq!(get_clips(&plaintext, &None)?.len(), 1);
+            let clips = get_clips(&db, &None)?;
+            let texts = clips.iter().map(ClipEntry::text).collect::<Result<Vec<_>>>()?;
+            assert_eq!(texts, ["secret clip", &large, "another clip"]);
+
+            // Rows written under the key can still be removed
+            remove_clip(&db, clips[2].id.unwrap())?;
+            decrypt(&db)?;
+        }
+        assert_eq!(get_clips(&open_db(path, None)?, &None)?.len(), 2);
+        assert!(blobs(path)?.iter().all(|file| contains(file, b"secret clip")));
+        Ok(())
+    }
+}
@@ -1,15 +1,40 @@
+pub mod blobs;
+pub mod encryption;
+pub mod retention;
 mod schema;
+pub mod search;
+pub mod sensitive;
+pub mod snippets;
 pub mod testing;
 
-use std::{cmp::Ordering::*, collections::HashSet};
+use std::{
+    cmp::Ordering::*,
+    collections::HashSet,
+    ops::{Deref, DerefMut},
+};
 
 use anyhow::Result;
 use camino::Utf8Path;
+use tracing::{debug, instrument, Span};
 
 pub use crate::database::schema::{
     transaction::{RTransaction, RwTransaction},
-    Builder, ClipEntry, Database, ToInput, MODELS,
+    Blob, Builder, ClipEntry, ContentHash, Counter, DateTime, Payload, Sealing, Selection, Snippet,
+    ToInput, MODELS,
+};
+use crate::{
+    database::{
+        blobs::Blobs,
+        encryption::{DatabaseKey, KeyScope},
+        schema::ClipEntryKey,
+    },
+    utils::{
+        config::Encryption,
+        filter::{Decision, Filters},
+    },
 };
+
+const CLIP_ID_COUNTER: &str = "clip_id";
 pub trait TableLen<'txn, T: ToInput> {
     fn length(&self) -> Result<u64>;
 }
@@ -25,59 +50,547 @@ impl<'txn> TableLen<'txn, ClipEntry> for RwTransaction<'txn> {
     }
 }
 
-pub fn get_db(path: &Utf8Path) -> Result<native_db::Database> {
-    let db = Builder::new().create(&MODELS, path)?;
+/// The native_db database along with the files of its large clips and its key
+pub struct Database<'a> {
+    db: native_db::Database<'a>,
+    pub blobs: Blobs,
+    key: DatabaseKey,
+}
+
+/// A transaction of a [`Database`], records are sealed with its key while this is around
+pub struct Transaction<T> {
+    tx: T,
+    _key: KeyScope,
+}
+
+impl<T> Deref for Transaction<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tx
+    }
+}
+
+impl Transaction<RwTransaction<'_>> {
+    pub fn commit(self) -> Result<()> {
+        Ok(self.tx.commit()?)
+    }
+
+    pub fn abort(self) -> Result<()> {
+        Ok(self.tx.abort()?)
+    }
+}
+
+impl<'a> Deref for Database<'a> {
+    type Target = native_db::Database<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.db
+    }
+}
+
+impl DerefMut for Database<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.db
+    }
+}
+
+impl Database<'_> {
+    /// Shadows the one of native_db, which would leave records without a key
+    pub fn r_transaction(&self) -> Result<Transaction<RTransaction<'_>>> {
+        let key = self.key.enter();
+
+        Ok(Transaction {
+            tx: self.db.r_transaction()?,
+            _key: key,
+        })
+    }
+
+    /// Shadows the one of native_db, which would leave records without a key
+    pub fn rw_transaction(&self) -> Result<Transaction<RwTransaction<'_>>> {
+        let key = self.key.enter();
+
+        Ok(Transaction {
+            tx: self.db.rw_transaction()?,
+            _key: key,
+        })
+    }
+
+    /// Removes the files only `removed` clips referenced, see [`Blobs::release`]
+    fn release(&self, removed: &[ClipEntry]) -> Result<()> {
+        self.blobs.release(self, removed)
+    }
+
+    /// Removes every file no clip references, see [`Blobs::sweep`]
+    pub fn sweep(&self) -> Result<()> {
+        self.blobs.sweep(self)
+    }
+}
+
+pub fn get_db(path: &Utf8Path) -> Result<Database<'static>> {
+    open_db(path, None)
+}
+
+/// Opens the database at `path`, unlocking it with `encryption` when given. See
+/// [`encryption::unlock`]
+pub fn open_db(path: &Utf8Path, encryption: Option<&Encryption>) -> Result<Database<'static>> {
+    let key = DatabaseKey::default();
+    let db = Database {
+        db: Builder::new().create(&MODELS, path)?,
+        blobs: Blobs::new(path, key.clone()),
+        key,
+    };
+    encryption::unlock(&db, encryption)?;
     let tx = db.rw_transaction()?;
     tx.migrate::<ClipEntry>()?;
+    assign_missing_ids(&tx)?;
     tx.commit()?;
 
     Ok(db)
 }
 
-pub fn remove_duplicates(db: &Database, duplicates: i64) -> Result<()> {
-    let rtx = db.r_transaction()?;
-    let wtx = db.rw_transaction()?;
-    let it = rtx.scan().primary::<ClipEntry>()?;
-    let cursor = it.all()?;
-    let mut seen = HashSet::<Vec<u8>>::new();
-
-    let filtered: Box<dyn Iterator<Item = ClipEntry>> = match duplicates.cmp(&0) {
-        Greater => Box::new(cursor.take(duplicates as usize).flatten()),
-        Less => Box::new(cursor.rev().take(duplicates.unsigned_abs() as usize).flatten()),
-        Equal => Box::new(cursor.rev().flatten()),
+/// Whether [`open_db`] failed because another process has the database open
+pub fn is_locked(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<redb::DatabaseError>(),
+            Some(redb::DatabaseError::DatabaseAlreadyOpen)
+        )
+    })
+}
+
+/// Hands out the next clip id. Ids are never reused, even once their clip is gone
+pub fn next_id(tx: &RwTransaction) -> Result<u64> {
+    let mut counter =
+        tx.get().primary::<Counter>(CLIP_ID_COUNTER.to_string())?.unwrap_or(Counter {
+            name: CLIP_ID_COUNTER.to_string(),
+            value: 0,
+        });
+    counter.value += 1;
+    let id = counter.value;
+    tx.upsert(counter)?;
+
+    Ok(id)
+}
+
+/// Numbers clips migrated from before ids existed, oldest first. Clips without one are left out
+/// of the id index so nothing is read unless the counts differ
+fn assign_missing_ids(tx: &RwTransaction) -> Result<()> {
+    if tx.len().secondary::<ClipEntry>(ClipEntryKey::id)? == tx.len().primary::<ClipEntry>()? {
+        return Ok(());
+    }
+    let missing = tx
+        .scan()
+        .primary::<ClipEntry>()?
+        .all()?
+        .flatten()
+        .filter(|entry| entry.id.is_none())
+        .collect::<Vec<_>>();
+
+    for entry in missing {
+        let mut updated = entry.clone();
+        updated.id = Some(next_id(tx)?);
+        tx.update(entry, updated)?;
+    }
+
+    Ok(())
+}
+
+/// Id for a clip that won't be stored in the database, ie: a sensitive one
+pub fn reserve_id(db: &Database) -> Result<u64> {
+    let tx = db.rw_transaction()?;
+    let id = next_id(&tx)?;
+    tx.commit()?;
+
+    Ok(id)
+}
+
+/// Inserts `entry` under a fresh id, returning the id
+pub fn insert_clip(tx: &RwTransaction, mut entry: ClipEntry) -> Result<u64> {
+    let id = next_id(tx)?;
+    entry.id = Some(id);
+    tx.insert(entry)?;
+
+    Ok(id)
+}
+
+/// Every clip oldest first, limited to `selection` when given. Representations kept in files
+/// aren't read but for preferred text, [`get_clip`] loads the rest
+pub fn get_clips(db: &Database, selection: &Option<Selection>) -> Result<Vec<ClipEntry>> {
+    Ok(db
+        .r_transaction()?
+        .scan()
+        .primary::<ClipEntry>()?
+        .all()?
+        .flatten()
+        .filter(|entry| entry.is_from(selection))
+        .map(|mut entry| {
+            db.blobs.load_preview(&mut entry);
+            entry
+        })
+        .collect())
+}
+
+/// With every representation, including the ones kept in files
+pub fn get_clip(db: &Database, id: u64) -> Result<Option<ClipEntry>> {
+    let mut entry = db.r_transaction()?.get().secondary(ClipEntryKey::id, Some(id))?;
+    if let Some(entry) = &mut entry {
+        db.blobs.load(entry);
+    }
+
+    Ok(entry)
+}
+
+/// Removes the clip with `id`, returning it as listed if there was one
+pub fn remove_clip(db: &Database, id: u64) -> Result<Option<ClipEntry>> {
+    let tx = db.rw_transaction()?;
+    let Some(entry) = tx.get().secondary::<ClipEntry>(ClipEntryKey::id, Some(id))? else {
+        return Ok(None);
     };
+    let removed = tx.remove(entry)?;
+    tx.commit()?;
+
+    // Read before the files go
+    let mut loaded = removed.clone();
+    db.blobs.load_preview(&mut loaded);
+    db.release(&[removed])?;
 
-    for entry in filtered {
-        if !seen.insert(entry.payload.to_vec()) {
-            wtx.remove(entry).ok();
+    Ok(Some(loaded))
+}
+
+/// Pins or unpins the clip with `id`, returning it as listed if there was one
+pub fn set_pinned(db: &Database, id: u64, pinned: bool) -> Result<Option<ClipEntry>> {
+    let tx = db.rw_transaction()?;
+    let Some(entry) = tx.get().secondary::<ClipEntry>(ClipEntryKey::id, Some(id))? else {
+        return Ok(None);
+    };
+    let mut updated = entry.clone();
+    updated.pinned = pinned;
+    tx.update(entry, updated.clone())?;
+    tx.commit()?;
+    db.blobs.load_preview(&mut updated);
+
+    Ok(Some(updated))
+}
+
+/// Inserts `entry` unless `filters` exclude it, then prunes its duplicates and anything beyond
+/// `keep` clips, all in a single transaction. Representations too large to keep inline go to
+/// files, see [`blobs`]
+#[instrument(skip(db, entry, filters), fields(id))]
+pub fn store_clip(
+    db: &Database,
+    mut entry: ClipEntry,
+    filters: &Filters,
+    duplicates: i64,
+    keep: u64,
+) -> Result<()> {
+    if let Decision::Excluded(rule) = filters.check(&entry) {
+        debug!("Ignoring clip matching {rule}");
+        return Ok(());
+    }
+    let tx = db.rw_transaction()?;
+    db.blobs.spill(&mut entry)?;
+    let hash = entry.content_hash();
+    let epoch = entry.epoch;
+    Span::current().record("id", insert_clip(&tx, entry)?);
+    let mut removed = remove_duplicates_of(&tx, hash, epoch, duplicates)?;
+    removed.extend(trim(&tx, keep)?);
+    tx.commit()?;
+
+    db.release(&removed)
+}
+
+/// Inserts clips from elsewhere, ie: a backup, keeping their timestamps, then prunes like
+/// [`store_clip`]. Clips whose timestamp is already taken or that `filters` exclude are skipped
+/// so importing the same backup twice adds nothing. Returns how many clips were added
+#[instrument(skip(db, entries, filters), fields(entries = entries.len()))]
+pub fn import_clips(
+    db: &Database,
+    entries: Vec<ClipEntry>,
+    filters: &Filters,
+    duplicates: i64,
+    keep: u64,
+) -> Result<u64> {
+    let tx = db.rw_transaction()?;
+    let mut imported = 0;
+
+    for mut entry in entries.into_iter().filter(|entry| filters.check(entry).is_stored()) {
+        if tx.get().primary::<ClipEntry>(entry.epoch)?.is_none() {
+            db.blobs.spill(&mut entry)?;
+            insert_clip(&tx, entry)?;
+            imported += 1;
         }
     }
+    // Imported clips land anywhere in history, so every clip is checked instead of the new ones
+    let mut removed = dedupe(&tx, duplicates)?;
+    removed.extend(trim(&tx, keep)?);
+    tx.commit()?;
+    db.release(&removed)?;
+
+    Ok(imported)
+}
+
+pub fn wipe(db: &Database) -> Result<()> {
+    let tx = db.rw_transaction()?;
+    let entries = tx.scan().primary::<ClipEntry>()?.all()?.flatten().collect::<Vec<_>>();
+
+    for entry in &entries {
+        tx.remove(entry.clone())?;
+    }
+    tx.commit()?;
 
-    Ok(wtx.commit()?)
+    db.release(&entries)
 }
 
+/// Removes duplicates among every clip. See [`dedupe`] for what `duplicates` means
+pub fn remove_duplicates(db: &Database, duplicates: i64) -> Result<()> {
+    let tx = db.rw_transaction()?;
+    let removed = dedupe(&tx, duplicates)?;
+    tx.commit()?;
+
+    db.release(&removed)
+}
+
+/// Positive `duplicates` only look at that many of the oldest clips and keep the oldest copy,
+/// negative ones at that many of the newest and keep the newest copy. 0 looks at every clip and
+/// keeps the newest copy. Pinned clips are never removed. Returns the removed clips
+fn dedupe(tx: &RwTransaction, duplicates: i64) -> Result<Vec<ClipEntry>> {
+    // The table can't be written to while it's scanned
+    let dupes = {
+        let scan = tx.scan().primary::<ClipEntry>()?;
+        let cursor = scan.all()?;
+        let window: Box<dyn Iterator<Item = ClipEntry>> = match duplicates.cmp(&0) {
+            Greater => Box::new(cursor.take(duplicates as usize).flatten()),
+            Less => Box::new(cursor.rev().take(duplicates.unsigned_abs() as usize).flatten()),
+            Equal => Box::new(cursor.rev().flatten()),
+        };
+        let mut seen = HashSet::<ContentHash>::new();
+        window
+            .filter(|entry| !seen.insert(entry.content_hash()) && !entry.pinned)
+            .collect::<Vec<_>>()
+    };
+
+    if !dupes.is_empty() {
+        debug!("Removing {} duplicates", dupes.len());
+    }
+    for entry in &dupes {
+        tx.remove(entry.clone())?;
+    }
+
+    Ok(dupes)
+}
+
+/// Does what [`dedupe`] would right after the clip at `epoch` was inserted, assuming every clip
+/// before it was already deduplicated. Only the clips sharing its hash are looked up
+fn remove_duplicates_of(
+    tx: &RwTransaction,
+    hash: ContentHash,
+    epoch: DateTime,
+    duplicates: i64,
+) -> Result<Vec<ClipEntry>> {
+    let copies = tx
+        .scan()
+        .secondary::<ClipEntry>(ClipEntryKey::content_hash)?
+        .range(hash..=hash)?
+        .collect::<Result<Vec<_>, _>>()?;
+    if copies.len() < 2 {
+        return Ok(Vec::new());
+    }
+
+    let dupes: Vec<_> = match duplicates.cmp(&0) {
+        // The new clip is the newest so it's only among the oldest while there are few enough,
+        // and then it's the one to go
+        Greater if TableLen::<ClipEntry>::length(tx)? <= duplicates as u64 =>
+            copies.into_iter().filter(|entry| entry.epoch == epoch).collect(),
+        Greater => Vec::new(),
+        Less => {
+            let oldest = tx
+                .scan()
+                .primary::<ClipEntry>()?
+                .all()?
+                .rev()
+                .nth(duplicates.unsigned_abs() as usize - 1)
+                .transpose()?
+                .map(|entry| entry.epoch.0);
+            copies
+                .into_iter()
+                .filter(|entry| oldest.is_none_or(|oldest| entry.epoch.0 >= oldest))
+                .filter(|entry| entry.epoch != epoch)
+                .collect()
+        },
+        Equal => copies.into_iter().filter(|entry| entry.epoch != epoch).collect(),
+    };
+
+    let dupes = dupes.into_iter().filter(|entry| !entry.pinned).collect::<Vec<_>>();
+    for entry in &dupes {
+        debug!(id = entry.id, "Removing duplicate");
+        tx.remove(entry.clone())?;
+    }
+
+    Ok(dupes)
+}
+
+/// Removes the oldest clips past the newest `limit`. Pinned clips count towards the limit but are
+/// never removed. A `limit` of 0 keeps every clip
 pub fn ensure_db_size(db: &Database, limit: u64) -> Result<()> {
     let tx = db.rw_transaction()?;
-    tx.scan()
+    let removed = trim(&tx, limit)?;
+    tx.commit()?;
+
+    db.release(&removed)
+}
+
+/// Returns the removed clips
+fn trim(tx: &RwTransaction, limit: u64) -> Result<Vec<ClipEntry>> {
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let overflow = TableLen::<ClipEntry>::length(tx)?.saturating_sub(limit);
+    if overflow == 0 {
+        return Ok(Vec::new());
+    }
+
+    let oldest = tx
+        .scan()
         .primary::<ClipEntry>()?
         .all()?
-        .take(limit as usize)
         .flatten()
-        .for_each(|entry| {
-            tx.remove(entry).unwrap();
-        });
-    tx.commit()?;
-    Ok(())
+        .filter(|entry| !entry.pinned)
+        .take(overflow as usize)
+        .collect::<Vec<_>>();
+
+    debug!("Removing {} clips past the newest {limit}", oldest.len());
+    for entry in &oldest {
+        tx.remove(entry.clone())?;
+    }
+
+    Ok(oldest)
 }
 
 #[cfg(test)]
 pub mod test {
 
     use pretty_assertions::assert_eq;
-    use testing::{fill_db_and_test, FillWith};
+    use proptest::prelude::*;
+    use shortcut_assert_fs::TmpFs;
+    use testing::{fill_db_and_test, get_db_contents, FillWith};
 
     use super::*;
-    use crate::database::schema::ClipEntry;
+    use crate::{database::schema::ClipEntry, utils::config::Config};
+
+    #[test]
+    fn it_never_reuses_ids() {
+        fill_db_and_test(FillWith::Random, 20, |db, before| {
+            assert_eq!(get_clip(db, 20)?.unwrap().bytes(), before[19]);
+            assert!(remove_clip(db, 20)?.is_some());
+            assert_eq!(get_clip(db, 20)?, None);
+
+            let tx = db.rw_transaction()?;
+            let id = insert_clip(&tx, ClipEntry::new(b"asdf"))?;
+            tx.commit()?;
+
+            assert_eq!(id, 21);
+            assert_eq!(get_clip(db, 21)?.unwrap().bytes(), b"asdf");
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn it_numbers_clips_without_ids() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("db");
+        let path = Utf8Path::new(path.as_str());
+        {
+            let db = open_db(path, None)?;
+            let tx = db.rw_transaction()?;
+            insert_clip(&tx, ClipEntry::new(b"numbered"))?;
+            tx.insert(ClipEntry::new(b"migrated"))?;
+            tx.commit()?;
+        }
+
+        let db = open_db(path, None)?;
+        assert_eq!(get_clip(&db, 1)?.unwrap().bytes(), b"numbered");
+        assert_eq!(get_clip(&db, 2)?.unwrap().bytes(), b"migrated");
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_locked_databases() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("db");
+        let path = Utf8Path::new(path.as_str());
+        let _db = open_db(path, None)?;
+
+        assert!(open_db(path, None).is_err_and(|e| is_locked(&e)));
+        assert!(!is_locked(&anyhow::anyhow!("Database already open")));
+        Ok(())
+    }
+
+    #[test]
+    fn it_lists_every_representation() {
+        fill_db_and_test(FillWith::Random, 2, |db, _| {
+            let payload = Payload::from([
+                ("text/html".to_string(), b"<b>hi</b>".to_vec()),
+                ("text/plain".to_string(), b"hi".to_vec()),
+            ]);
+            store_clip(
+                db,
+                ClipEntry::with_application(payload.clone(), Selection::Clipboard, None),
+                &Filters::default(),
+                0,
+                0,
+            )?;
+
+            let clips = get_clips(db, &None)?;
+            assert_eq!(clips.len(), 3);
+            assert_eq!(clips[2].payload, payload);
+            assert_eq!(clips[2].bytes(), b"hi");
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn it_skips_imported_timestamps() {
+        fill_db_and_test(FillWith::Random, 5, |db, before| {
+            let mut entries = get_clips(db, &None)?;
+            let mut new = ClipEntry::new(b"asdf");
+            new.epoch = DateTime(entries[0].epoch.0 - chrono::Duration::days(1));
+            entries.push(new);
+
+            assert_eq!(import_clips(db, entries, &Filters::default(), 1, 0)?, 1);
+
+            let clips = get_clips(db, &None)?;
+            assert_eq!(clips.len(), before.len() + 1);
+            assert_eq!(clips[0].bytes(), b"asdf");
+            assert_eq!(clips[0].id, Some(before.len() as u64 + 1));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn it_keeps_pinned_clips() {
+        let dupe = "asdf";
+        fill_db_and_test(FillWith::Dupes(dupe), 20, |db, _| {
+            set_pinned(db, 5, true)?;
+            remove_duplicates(db, 0)?;
+
+            let remaining = get_clips(db, &None)?;
+
+            assert_eq!(remaining.len(), 2);
+            assert_eq!(remaining[0].id, Some(5));
+            assert!(remaining[0].pinned);
+
+            ensure_db_size(db, 1)?;
+
+            assert!(get_clip(db, 5)?.is_some_and(|entry| entry.pinned));
+            Ok(())
+        })
+        .unwrap();
+    }
 
     #[test]
     fn it_removes_dupes_oldest() {
@@ -105,6 +618,28 @@ pub mod test {
         .unwrap();
     }
 
+    #[test]
+    fn it_dedupes_past_the_window_with_the_default_config() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let db = get_db(Utf8Path::new(tf.path("db").as_str()))?;
+        let duplicates = Config::default().clipboard().duplicates();
+        let store = |bytes: &[u8]| {
+            store_clip(&db, ClipEntry::new(bytes), &Filters::default(), duplicates, 0)
+        };
+
+        for i in 0..duplicates.unsigned_abs() + 5 {
+            store(format!("clip {i}").as_bytes())?;
+        }
+        let before = get_db_contents(&db)?.len();
+        store(b"clip 12")?;
+
+        let contents = get_db_contents(&db)?;
+        assert_eq!(contents.len(), before);
+        assert_eq!(contents.last().unwrap(), b"clip 12");
+        assert_eq!(contents.iter().filter(|bytes| *bytes == b"clip 12").count(), 1);
+        Ok(())
+    }
+
     #[test]
     fn it_removes_all_dupes() {
         let dupe = "asdf";
@@ -114,9 +649,9 @@ pub mod test {
             let it = tx.scan().primary::<ClipEntry>()?;
             let mut cursor = it.all()?;
 
-            let a_first = cursor.next().unwrap()?.payload;
-            let a_second = cursor.next().unwrap()?.payload;
-            let a_last = cursor.next().unwrap()?.payload;
+            let a_first = cursor.next().unwrap()?.bytes().to_vec();
+            let a_second = cursor.next().unwrap()?.bytes().to_vec();
+            let a_last = cursor.next().unwrap()?.bytes().to_vec();
 
             let b_first = before.get(1).unwrap();
             let b_last = before.get(18).unwrap();
@@ -129,4 +664,90 @@ pub mod test {
         })
         .unwrap();
     }
+
+    /// Clip counts with limits from nothing up to twice the count, so below, at and above it
+    fn amount_and_limit() -> impl Strategy<Value = (i64, u64)> {
+        (1..25i64).prop_flat_map(|amount| (Just(amount), 0..=2 * amount as u64))
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn it_dedupes_on_insert_like_a_full_pass(
+            clips in prop::collection::vec(0..4u8, 1..20),
+            duplicates in -5..=5i64,
+        ) {
+            let run = || -> Result<()> {
+                let tf = TmpFs::new()?;
+                let stored = get_db(Utf8Path::new(tf.path("stored").as_str()))?;
+                let passed = get_db(Utf8Path::new(tf.path("passed").as_str()))?;
+
+                for clip in clips {
+                    let entry = ClipEntry::new(&[b'a' + clip]);
+                    store_clip(&stored, entry.clone(), &Filters::default(), duplicates, 0)?;
+
+                    let tx = passed.rw_transaction()?;
+                    insert_clip(&tx, entry)?;
+                    tx.commit()?;
+                    remove_duplicates(&passed, duplicates)?;
+                }
+
+                assert_eq!(get_db_contents(&stored)?, get_db_contents(&passed)?);
+                Ok(())
+            };
+            run().unwrap();
+        }
+
+        #[test]
+        fn it_keeps_the_newest_clips((amount, limit) in amount_and_limit()) {
+            fill_db_and_test(FillWith::Random, amount, |db, before| {
+                ensure_db_size(db, limit)?;
+
+                let kept = match limit {
+                    0 => before.len(),
+                    limit => before.len().min(limit as usize),
+                };
+                assert_eq!(get_db_contents(db)?, before[before.len() - kept..]);
+                Ok(())
+            })
+            .unwrap();
+        }
+
+        #[test]
+        fn it_never_trims_pinned_clips(
+            (amount, limit) in amount_and_limit(),
+            pinned in prop::collection::hash_set(1..25u64, 0..8),
+        ) {
+            fill_db_and_test(FillWith::Random, amount, |db, _| {
+                let pinned = pinned
+                    .into_iter()
+                    .filter(|id| *id <= amount as u64)
+                    .collect::<HashSet<_>>();
+                for id in &pinned {
+                    set_pinned(db, *id, true)?;
+                }
+
+                ensure_db_size(db, limit)?;
+
+                let overflow = match limit {
+                    0 => 0,
+                    limit => (amount as u64).saturating_sub(limit) as usize,
+                };
+                let removed = (1..=amount as u64)
+                    .filter(|id| !pinned.contains(id))
+                    .take(overflow)
+                    .collect::<HashSet<_>>();
+                let expected = (1..=amount as u64)
+                    .filter(|id| !removed.contains(id))
+                    .map(Some)
+                    .collect::<Vec<_>>();
+                let ids = get_clips(db, &None)?.iter().map(|entry| entry.id).collect::<Vec<_>>();
+
+                assert_eq!(ids, expected);
+                Ok(())
+            })
+            .unwrap();
+        }
+    }
 }
@@ -0,0 +1,324 @@
+use std::{fmt, time::Duration};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use super::{ClipEntry, Database};
+use crate::utils::{config::Clipboard, mime::matches_type};
+
+/// How long clips are kept and how much room they may take, on top of the amount kept by
+/// [`ensure_db_size`](super::ensure_db_size). Pinned clips are never removed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    /// By MIME type pattern, exact types first so they win over `type/*`
+    pub max_age_by_type: Vec<(String, Duration)>,
+    /// Bytes of every representation of every clip together
+    pub max_total_size: Option<u64>,
+}
+
+/// Why a clip is past [`Retention`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Reason {
+    Age(Duration),
+    TypeAge { pattern: String, max_age: Duration },
+    TotalSize(u64),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Age(max_age) => write!(f, "older than {}", humantime::format_duration(*max_age)),
+            Self::TypeAge { pattern, max_age } => write!(
+                f,
+                "{pattern} older than {}",
+                humantime::format_duration(*max_age)
+            ),
+            Self::TotalSize(max_total_size) => write!(f, "past the {max_total_size} byte budget"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Expired {
+    pub entry: ClipEntry,
+    pub reason: Reason,
+}
+
+impl Retention {
+    /// Parses the policies of a `[clipboard.<name>]` section
+    pub fn new(clipboard: &Clipboard) -> Result<Self> {
+        let parse_age = |age: &str| {
+            humantime::parse_duration(age).map_err(|err| anyhow!("Invalid age `{age}`: {err}"))
+        };
+        let mut max_age_by_type = clipboard
+            .max_age_by_type
+            .iter()
+            .flatten()
+            .map(|(pattern, age)| Ok((pattern.clone(), parse_age(age)?)))
+            .collect::<Result<Vec<_>>>()?;
+        max_age_by_type.sort_by_key(|(pattern, _)| (pattern.ends_with("/*"), pattern.clone()));
+
+        Ok(Self {
+            max_age: clipboard.max_age.as_deref().map(parse_age).transpose()?,
+            max_age_by_type,
+            max_total_size: clipboard
+                .max_total_size
+                .as_deref()
+                .map(|size| {
+                    parse_size::parse_size(size)
+                        .map_err(|err| anyhow!("Invalid size `{size}`: {err}"))
+                })
+                .transpose()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_age.is_none() && self.max_age_by_type.is_empty() && self.max_total_size.is_none()
+    }
+
+    /// Whether `entry` is too old to keep at `now`. Its preferred MIME type decides which age
+    /// applies
+    fn check_age(&self, entry: &ClipEntry, now: DateTime<Local>) -> Option<Reason> {
+        let mime_type = entry.mime_type().unwrap_or_default();
+        let (max_age, reason) = match self
+            .max_age_by_type
+            .iter()
+            .find(|(pattern, _)| matches_type(pattern, mime_type))
+        {
+            Some((pattern, max_age)) => (
+                *max_age,
+                Reason::TypeAge {
+                    pattern: pattern.clone(),
+                    max_age: *max_age,
+                },
+            ),
+            None => {
+                let max_age = self.max_age?;
+                (max_age, Reason::Age(max_age))
+            },
+        };
+        let age = (now - entry.epoch.0).to_std().unwrap_or_default();
+
+        (age > max_age).then_some(reason)
+    }
+}
+
+/// Clips past `retention` at `now`, oldest first. They're rows as stored, their representations
+/// in files aren't loaded
+pub fn find_expired(
+    db: &Database,
+    retention: &Retention,
+    now: DateTime<Local>,
+) -> Result<Vec<Expired>> {
+    let clips = db
+        .r_transaction()?
+        .scan()
+        .primary::<ClipEntry>()?
+        .all()?
+        .flatten()
+        .collect::<Vec<_>>();
+    // Pinned clips stay no matter what, so they're the first to take up the budget
+    let mut total_size =
+        clips.iter().filter(|entry| entry.pinned).map(ClipEntry::size).sum::<u64>();
+    let mut expired = Vec::new();
+
+    // Newest first so the rest of the budget goes to the clips most likely to be recalled
+    for entry in clips.into_iter().rev().filter(|entry| !entry.pinned) {
+        let reason = retention.check_age(&entry, now).or_else(|| {
+            let size = entry.size();
+            match retention.max_total_size {
+                Some(max_total_size) if total_size + size > max_total_size =>
+                    Some(Reason::TotalSize(max_total_size)),
+                _ => {
+                    total_size += size;
+                    None
+                },
+            }
+        });
+
+        if let Some(reason) = reason {
+            expired.push(Expired { entry, reason });
+        }
+    }
+    expired.reverse();
+
+    Ok(expired)
+}
+
+/// Removes every clip past `retention` in a single transaction, returning them. Nothing is
+/// removed when `dry_run` is set.
+///
+/// Representations kept in files are left unread unless `previews` is set, and then only the
+/// preferred one of each clip is read. [`ClipEntry::size`] counts them either way
+#[instrument(skip_all, fields(dry_run))]
+pub fn prune(
+    db: &Database,
+    retention: &Retention,
+    dry_run: bool,
+    previews: bool,
+) -> Result<Vec<Expired>> {
+    let mut expired = find_expired(db, retention, Local::now())?;
+    for Expired { entry, reason } in &expired {
+        debug!(id = entry.id, "Clip is {reason}");
+    }
+    let removed = match dry_run {
+        true => Vec::new(),
+        false => expired.iter().map(|expired| expired.entry.clone()).collect::<Vec<_>>(),
+    };
+
+    if !removed.is_empty() {
+        let tx = db.rw_transaction()?;
+        for entry in &removed {
+            tx.remove(entry.clone())?;
+        }
+        tx.commit()?;
+    }
+    // Files are only let go of once released
+    if previews {
+        for Expired { entry, .. } in &mut expired {
+            let preferred = entry.mime_type().unwrap_or_default().to_string();
+            db.blobs.load_where(entry, |mime_type| mime_type == preferred);
+        }
+    }
+    db.blobs.release(db, &removed)?;
+
+    Ok(expired)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use camino::Utf8Path;
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+    use crate::{
+        database::{
+            blobs::INLINE_LIMIT, get_clips, get_db, insert_clip, store_clip, Payload, Selection,
+        },
+        utils::filter::Filters,
+    };
+
+    #[test]
+    fn it_parses_policies() -> Result<()> {
+        let clipboard = Clipboard {
+            max_age: Some("30d".to_string()),
+            max_age_by_type: Some(HashMap::from([
+                ("image/*".to_string(), "1day".to_string()),
+                ("image/png".to_string(), "2h".to_string()),
+            ])),
+            max_total_size: Some("1 KiB".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Retention::new(&clipboard)?,
+            Retention {
+                max_age: Some(Duration::from_secs(30 * 86400)),
+                max_age_by_type: vec![
+                    ("image/png".to_string(), Duration::from_secs(2 * 3600)),
+                    ("image/*".to_string(), Duration::from_secs(86400)),
+                ],
+                max_total_size: Some(1024),
+            }
+        );
+        assert!(Retention::new(&Clipboard::default())?.is_empty());
+        assert!(Retention::new(&Clipboard {
+            max_age: Some("a while".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_finds_expired_clips() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("db");
+        let db = get_db(Utf8Path::new(path.as_str()))?;
+        let now = Local::now();
+        // Seconds apart so clips of the same day don't share a timestamp
+        let clip = |days: i64, seconds: i64, mime_type: &str, size: usize, pinned: bool| {
+            let mut entry = ClipEntry::with_payload(
+                Payload::from([(mime_type.to_string(), vec![b'a'; size])]),
+                Selection::Clipboard,
+            );
+            entry.epoch =
+                (now - chrono::Duration::days(days) + chrono::Duration::seconds(seconds)).into();
+            entry.pinned = pinned;
+            entry
+        };
+
+        let tx = db.rw_transaction()?;
+        for entry in [
+            clip(40, 0, "text/plain", 10, false),
+            clip(40, 1, "text/plain", 10, true),
+            clip(3, 0, "image/png", 10, false),
+            clip(3, 1, "text/plain", 60, false),
+            clip(2, 0, "text/plain", 30, false),
+            clip(1, 0, "text/plain", 30, false),
+        ] {
+            insert_clip(&tx, entry)?;
+        }
+        tx.commit()?;
+
+        let retention = Retention {
+            max_age: Some(Duration::from_secs(30 * 86400)),
+            max_age_by_type: vec![("image/*".to_string(), Duration::from_secs(86400))],
+            max_total_size: Some(75),
+        };
+        let expired = find_expired(&db, &retention, now)?
+            .into_iter()
+            .map(|expired| (expired.entry.id.unwrap(), expired.reason))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            expired,
+            [
+                (1, Reason::Age(Duration::from_secs(30 * 86400))),
+                (
+                    3,
+                    Reason::TypeAge {
+                        pattern: "image/*".to_string(),
+                        max_age: Duration::from_secs(86400)
+                    }
+                ),
+                (4, Reason::TotalSize(75)),
+            ]
+        );
+
+        prune(&db, &retention, true, true)?;
+        assert_eq!(get_clips(&db, &None)?.len(), 6);
+        prune(&db, &retention, false, true)?;
+        assert_eq!(get_clips(&db, &None)?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn it_prunes_without_reading_files() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("db");
+        let db = get_db(Utf8Path::new(path.as_str()))?;
+        let large = vec![b'a'; INLINE_LIMIT + 1];
+        store_clip(&db, ClipEntry::new(&large), &Filters::default(), 0, 0)?;
+        let retention = Retention {
+            max_total_size: Some(1),
+            ..Default::default()
+        };
+
+        let expired = prune(&db, &retention, true, false)?;
+        assert!(expired[0].entry.payload.is_empty());
+        assert_eq!(expired[0].entry.size(), large.len() as u64);
+
+        let expired = prune(&db, &retention, false, true)?;
+        assert_eq!(expired[0].entry.bytes(), large);
+        assert!(get_clips(&db, &None)?.is_empty());
+        assert!(std::fs::read_dir(format!("{path}.blobs"))?.next().is_none());
+        Ok(())
+    }
+}
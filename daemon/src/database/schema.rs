@@ -1,35 +1,77 @@
 use bincode;
 pub use native_db::*;
 use once_cell::sync::Lazy;
-pub use schemas::ClipEntry;
+pub(crate) use schemas::ClipEntryKey;
+pub use schemas::{
+    Blob, ClipEntry, ContentHash, Counter, DateTime, Payload, Sealing, Selection, Snippet,
+};
 use serde::{Deserialize, Serialize};
 
+use super::encryption::{content_hasher, seal, unseal};
+
+/// Encrypts records once the database is unlocked with a key, see [`super::encryption`]
 struct Bincode;
 
 impl<T: Serialize> native_model::Encode<T> for Bincode {
     type Error = bincode::error::EncodeError;
 
     fn encode(obj: &T) -> Result<Vec<u8>, bincode::error::EncodeError> {
-        bincode::serde::encode_to_vec(obj, bincode::config::standard())
+        seal(<Plain as native_model::Encode<T>>::encode(obj)?)
+            .map_err(bincode::error::EncodeError::OtherString)
     }
 }
 
 impl<T: for<'a> Deserialize<'a>> native_model::Decode<T> for Bincode {
     type Error = bincode::error::DecodeError;
 
+    fn decode(data: Vec<u8>) -> Result<T, bincode::error::DecodeError> {
+        <Plain as native_model::Decode<T>>::decode(
+            unseal(data).map_err(bincode::error::DecodeError::OtherString)?,
+        )
+    }
+}
+
+/// Never encrypted, for what's needed before the key is known
+struct Plain;
+
+impl<T: Serialize> native_model::Encode<T> for Plain {
+    type Error = bincode::error::EncodeError;
+
+    fn encode(obj: &T) -> Result<Vec<u8>, bincode::error::EncodeError> {
+        bincode::serde::encode_to_vec(obj, bincode::config::standard())
+    }
+}
+
+impl<T: for<'a> Deserialize<'a>> native_model::Decode<T> for Plain {
+    type Error = bincode::error::DecodeError;
+
     fn decode(data: Vec<u8>) -> Result<T, bincode::error::DecodeError> {
         Ok(bincode::serde::decode_from_slice(&data, bincode::config::standard())?.0)
     }
 }
 
 pub mod schemas {
-    use anyhow::Result;
+    use std::collections::BTreeMap;
+
+    use anyhow::{anyhow, Result};
+    use clap::ValueEnum;
+    use derive_more::Display;
+    use itertools::Itertools;
     use native_model::{native_model, Model};
 
     use super::*;
-    use crate::platforms::get_active_window;
+    use crate::{
+        platforms::get_active_window,
+        utils::mime::{guess_mime_type, is_image, PASSWORD_MANAGER_HINT, TEXT_MIME_TYPES},
+    };
 
-    pub type ClipEntry = crate::database::schema::schemas::v1::ClipEntryV1;
+    pub type ClipEntry = crate::database::schema::schemas::v7::ClipEntryV7;
+    pub use v1::DateTime;
+    pub use v2::Payload;
+    pub use v3::Selection;
+    pub use v6::ContentHash;
+    pub use v7::Blob;
+    pub(crate) use v7::ClipEntryV7Key as ClipEntryKey;
 
     pub(super) mod v1 {
         use super::*;
@@ -74,22 +116,454 @@ pub mod schemas {
             pub payload: Vec<u8>,
             pub application: Option<String>,
         }
+    }
+
+    pub(super) mod v2 {
+        use super::*;
+
+        /// Every representation of a clip keyed by the MIME type it was offered as
+        pub type Payload = BTreeMap<String, Vec<u8>>;
+
+        /// The representation shown and recalled by default out of `mime_types`.
+        ///
+        /// Text is preferred over images which are preferred over anything else.
+        pub fn preferred_mime_type<'a>(
+            mut mime_types: impl Iterator<Item = &'a str> + Clone,
+        ) -> Option<&'a str> {
+            TEXT_MIME_TYPES
+                .iter()
+                .find_map(|text| mime_types.clone().find(|mime_type| mime_type == text))
+                .or_else(|| mime_types.clone().find(|mime_type| is_image(mime_type)))
+                .or_else(|| mime_types.next())
+        }
+
+        #[native_db]
+        #[native_model(id = 1, version = 2, with = Bincode, from = v1::ClipEntryV1)]
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone)]
+        pub struct ClipEntryV2 {
+            #[primary_key]
+            pub epoch: DateTime,
+            pub payload: Payload,
+            pub application: Option<String>,
+        }
+
+        impl From<v1::ClipEntryV1> for ClipEntryV2 {
+            fn from(entry: v1::ClipEntryV1) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    payload: Payload::from([(guess_mime_type(&entry.payload), entry.payload)]),
+                    application: entry.application,
+                }
+            }
+        }
+
+        impl From<ClipEntryV2> for v1::ClipEntryV1 {
+            fn from(entry: ClipEntryV2) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    payload: preferred_mime_type(entry.payload.keys().map(String::as_str))
+                        .and_then(|mime_type| entry.payload.get(mime_type).cloned())
+                        .unwrap_or_default(),
+                    application: entry.application,
+                }
+            }
+        }
+    }
+
+    pub(super) mod v3 {
+        use super::*;
+
+        /// The X11/Wayland selection a clip was copied into
+        #[derive(
+            Serialize,
+            Deserialize,
+            ValueEnum,
+            Display,
+            Default,
+            PartialEq,
+            Eq,
+            Debug,
+            Hash,
+            Clone,
+            Copy,
+        )]
+        #[serde(rename_all = "lowercase")]
+        pub enum Selection {
+            /// The regular clipboard filled by explicitly copying
+            #[default]
+            #[display("clipboard")]
+            Clipboard,
+            /// Whatever text is currently highlighted, pasted with middle click
+            #[display("primary")]
+            Primary,
+        }
+
+        #[native_db]
+        #[native_model(id = 1, version = 3, with = Bincode, from = v2::ClipEntryV2)]
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone)]
+        pub struct ClipEntryV3 {
+            #[primary_key]
+            pub epoch: DateTime,
+            pub payload: Payload,
+            pub application: Option<String>,
+            pub selection: Selection,
+        }
+
+        impl From<v2::ClipEntryV2> for ClipEntryV3 {
+            fn from(entry: v2::ClipEntryV2) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: Selection::Clipboard,
+                }
+            }
+        }
+
+        impl From<ClipEntryV3> for v2::ClipEntryV2 {
+            fn from(entry: ClipEntryV3) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    payload: entry.payload,
+                    application: entry.application,
+                }
+            }
+        }
+    }
+
+    pub(super) mod v4 {
+        use super::*;
+
+        #[native_db]
+        #[native_model(id = 1, version = 4, with = Bincode, from = v3::ClipEntryV3)]
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone)]
+        pub struct ClipEntryV4 {
+            #[primary_key]
+            pub epoch: DateTime,
+            /// Stable id shown to users. Never reused, even after the clip is removed
+            #[secondary_key(unique, optional)]
+            pub id: Option<u64>,
+            pub payload: Payload,
+            pub application: Option<String>,
+            pub selection: Selection,
+        }
+
+        /// Clips migrated from older versions get their id right after migrating
+        impl From<v3::ClipEntryV3> for ClipEntryV4 {
+            fn from(entry: v3::ClipEntryV3) -> Self {
+                Self {
+                    id: None,
+                    epoch: entry.epoch,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: entry.selection,
+                }
+            }
+        }
+
+        impl From<ClipEntryV4> for v3::ClipEntryV3 {
+            fn from(entry: ClipEntryV4) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: entry.selection,
+                }
+            }
+        }
+    }
+
+    pub(super) mod v5 {
+        use super::*;
+
+        #[native_db]
+        #[native_model(id = 1, version = 5, with = Bincode, from = v4::ClipEntryV4)]
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone)]
+        pub struct ClipEntryV5 {
+            #[primary_key]
+            pub epoch: DateTime,
+            /// Stable id shown to users. Never reused, even after the clip is removed
+            #[secondary_key(unique, optional)]
+            pub id: Option<u64>,
+            pub payload: Payload,
+            pub application: Option<String>,
+            pub selection: Selection,
+            /// Pinned clips are never pruned
+            pub pinned: bool,
+        }
+
+        impl From<v4::ClipEntryV4> for ClipEntryV5 {
+            fn from(entry: v4::ClipEntryV4) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    id: entry.id,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: entry.selection,
+                    pinned: false,
+                }
+            }
+        }
+
+        impl From<ClipEntryV5> for v4::ClipEntryV4 {
+            fn from(entry: ClipEntryV5) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    id: entry.id,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: entry.selection,
+                }
+            }
+        }
+    }
 
-        impl ClipEntryV1 {
+    pub(super) mod v6 {
+        use super::*;
+
+        /// BLAKE3 of every representation of a clip, see [`ClipEntryV6::content_hash`]
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone, Copy)]
+        pub struct ContentHash(pub [u8; 32]);
+
+        impl ToKey for ContentHash {
+            fn to_key(&self) -> Key {
+                Key::new(self.0.to_vec())
+            }
+
+            fn key_names() -> Vec<String> {
+                vec!["ContentHash".to_string()]
+            }
+        }
+
+        /// Same fields as [`v5::ClipEntryV5`], bumped so the hash index is built for existing clips
+        #[native_db(secondary_key(content_hash -> ContentHash))]
+        #[native_model(id = 1, version = 6, with = Bincode, from = v5::ClipEntryV5)]
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone)]
+        pub struct ClipEntryV6 {
+            #[primary_key]
+            pub epoch: DateTime,
+            /// Stable id shown to users. Never reused, even after the clip is removed
+            #[secondary_key(unique, optional)]
+            pub id: Option<u64>,
+            pub payload: Payload,
+            pub application: Option<String>,
+            pub selection: Selection,
+            /// Pinned clips are never pruned
+            pub pinned: bool,
+        }
+
+        impl From<v5::ClipEntryV5> for ClipEntryV6 {
+            fn from(entry: v5::ClipEntryV5) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    id: entry.id,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: entry.selection,
+                    pinned: entry.pinned,
+                }
+            }
+        }
+
+        impl From<ClipEntryV6> for v5::ClipEntryV5 {
+            fn from(entry: ClipEntryV6) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    id: entry.id,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: entry.selection,
+                    pinned: entry.pinned,
+                }
+            }
+        }
+
+        impl ClipEntryV6 {
+            pub fn content_hash(&self) -> ContentHash {
+                let mut hasher = content_hasher();
+                for (mime_type, bytes) in &self.payload {
+                    hasher.update(&(mime_type.len() as u64).to_le_bytes());
+                    hasher.update(mime_type.as_bytes());
+                    hasher.update(&(bytes.len() as u64).to_le_bytes());
+                    hasher.update(bytes);
+                }
+
+                ContentHash(*hasher.finalize().as_bytes())
+            }
+        }
+    }
+
+    pub(super) mod v7 {
+        use super::*;
+
+        /// A representation kept in a file of its own, see [`crate::database::blobs`]
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone, Copy)]
+        pub struct Blob {
+            /// Also names the file
+            pub hash: ContentHash,
+            pub size: u64,
+        }
+
+        impl ContentHash {
+            /// Keyed like [`ClipEntryV7::content_hash`]
+            pub fn of(bytes: &[u8]) -> Self {
+                Self(*content_hasher().update(bytes).finalize().as_bytes())
+            }
+
+            /// For naming files after
+            pub fn to_hex(&self) -> String {
+                blake3::Hash::from_bytes(self.0).to_hex().to_string()
+            }
+        }
+
+        #[native_db(secondary_key(content_hash -> ContentHash))]
+        #[native_model(id = 1, version = 7, with = Bincode, from = v6::ClipEntryV6)]
+        #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone)]
+        pub struct ClipEntryV7 {
+            #[primary_key]
+            pub epoch: DateTime,
+            /// Stable id shown to users. Never reused, even after the clip is removed
+            #[secondary_key(unique, optional)]
+            pub id: Option<u64>,
+            pub payload: Payload,
+            pub application: Option<String>,
+            pub selection: Selection,
+            /// Pinned clips are never pruned
+            pub pinned: bool,
+            /// Representations too large for the database, by MIME type. Clips recalled from the
+            /// database have these moved back into `payload`, listed ones mostly don't
+            pub blobs: BTreeMap<String, Blob>,
+        }
+
+        impl From<v6::ClipEntryV6> for ClipEntryV7 {
+            fn from(entry: v6::ClipEntryV6) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    id: entry.id,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: entry.selection,
+                    pinned: entry.pinned,
+                    blobs: BTreeMap::new(),
+                }
+            }
+        }
+
+        /// Representations kept in files are left out, v6 has nowhere to put them
+        impl From<ClipEntryV7> for v6::ClipEntryV6 {
+            fn from(entry: ClipEntryV7) -> Self {
+                Self {
+                    epoch: entry.epoch,
+                    id: entry.id,
+                    payload: entry.payload,
+                    application: entry.application,
+                    selection: entry.selection,
+                    pinned: entry.pinned,
+                }
+            }
+        }
+
+        impl ClipEntryV7 {
             pub fn new(payload: &[u8]) -> Self {
+                Self::with_payload(
+                    Payload::from([(guess_mime_type(payload), payload.to_vec())]),
+                    Selection::Clipboard,
+                )
+            }
+
+            /// The id is only assigned once the clip is stored
+            pub fn with_payload(payload: Payload, selection: Selection) -> Self {
+                Self::with_application(payload, selection, get_active_window())
+            }
+
+            /// For clips that weren't just copied, ie: imported ones, where asking for the active
+            /// window would be wrong as well as slow
+            pub fn with_application(
+                payload: Payload,
+                selection: Selection,
+                application: Option<String>,
+            ) -> Self {
                 Self {
+                    id: None,
+                    pinned: false,
                     epoch: v1::DateTime::now(),
-                    payload: payload.to_vec(),
-                    application: get_active_window(),
+                    payload,
+                    application,
+                    selection,
+                    blobs: BTreeMap::new(),
+                }
+            }
+
+            /// Identical payloads hash the same whatever else differs, so duplicates are found
+            /// through the index instead of comparing payloads. Keyed once the database is
+            /// encrypted so the index doesn't give away what was copied.
+            ///
+            /// Each representation goes in by its own hash so moving it to a file changes nothing
+            pub fn content_hash(&self) -> ContentHash {
+                let inline = self
+                    .payload
+                    .iter()
+                    .map(|(mime_type, bytes)| (mime_type, ContentHash::of(bytes)));
+                let blobs = self.blobs.iter().map(|(mime_type, blob)| (mime_type, blob.hash));
+                let mut hasher = content_hasher();
+
+                for (mime_type, hash) in inline.chain(blobs).sorted_by(|a, b| a.0.cmp(b.0)) {
+                    hasher.update(&(mime_type.len() as u64).to_le_bytes());
+                    hasher.update(mime_type.as_bytes());
+                    hasher.update(&hash.0);
                 }
+
+                ContentHash(*hasher.finalize().as_bytes())
+            }
+
+            /// Including the ones kept in files
+            pub fn mime_types(&self) -> impl Iterator<Item = &str> + Clone {
+                self.payload.keys().chain(self.blobs.keys()).map(String::as_str)
+            }
+
+            /// See [`v2::preferred_mime_type`]
+            pub fn mime_type(&self) -> Option<&str> {
+                v2::preferred_mime_type(self.mime_types())
+            }
+
+            /// Bytes of every representation together, including the ones kept in files
+            pub fn size(&self) -> u64 {
+                let inline = self.payload.values().map(|bytes| bytes.len() as u64);
+                inline.chain(self.blobs.values().map(|blob| blob.size)).sum()
+            }
+
+            /// Bytes in the `mime_type` representation, including one kept in a file
+            pub fn len_of(&self, mime_type: &str) -> Option<u64> {
+                self.get(mime_type)
+                    .map(|bytes| bytes.len() as u64)
+                    .or_else(|| self.blobs.get(mime_type).map(|blob| blob.size))
+            }
+
+            pub fn get(&self, mime_type: &str) -> Option<&[u8]> {
+                self.payload.get(mime_type).map(Vec::as_slice)
+            }
+
+            /// Bytes of the preferred representation. See [`Self::mime_type`]
+            pub fn bytes(&self) -> &[u8] {
+                self.mime_type().and_then(|mime_type| self.get(mime_type)).unwrap_or_default()
             }
 
             pub fn text(&self) -> Result<String> {
-                let str_ified = std::str::from_utf8(&self.payload)?;
+                let text = TEXT_MIME_TYPES
+                    .iter()
+                    .find_map(|mime_type| self.get(mime_type))
+                    .ok_or_else(|| anyhow!("Clip has no text representation"))?;
+                let str_ified = std::str::from_utf8(text)?;
 
                 Ok(str_ified.to_string())
             }
 
+            /// Whether the app that copied this asked for it to stay out of clipboard history
+            pub fn is_sensitive(&self) -> bool {
+                self.get(PASSWORD_MANAGER_HINT)
+                    .is_some_and(|hint| hint.trim_ascii() == b"secret")
+            }
+
             pub fn contains(&self, maybe_query: &Option<String>) -> bool {
                 if let Some(query) = maybe_query {
                     if self.text().is_ok_and(|text| text.contains(query)) {
@@ -109,12 +583,116 @@ pub mod schemas {
 
                 false
             }
+
+            pub fn is_from(&self, maybe_selection: &Option<Selection>) -> bool {
+                maybe_selection.is_none_or(|selection| self.selection == selection)
+            }
+        }
+    }
+
+    /// Named counters that have to survive removing the rows they count
+    #[native_db]
+    #[native_model(id = 2, version = 1, with = Bincode)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub struct Counter {
+        #[primary_key]
+        pub name: String,
+        pub value: u64,
+    }
+
+    /// Text written by the user to be expanded on demand, ie: boilerplate or signatures.
+    ///
+    /// The body may contain `{placeholder}` variables that are filled in when expanded.
+    #[native_db]
+    #[native_model(id = 3, version = 1, with = Bincode)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub struct Snippet {
+        #[primary_key]
+        pub name: String,
+        pub body: String,
+        pub modified: DateTime,
+    }
+
+    /// Only present in encrypted databases. Holds what's needed to derive and verify the key
+    #[native_db]
+    #[native_model(id = 4, version = 1, with = Plain)]
+    #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+    pub struct Sealing {
+        #[primary_key]
+        pub id: u8,
+        pub salt: Vec<u8>,
+        /// A known value sealed with the key
+        pub check: Vec<u8>,
+    }
+
+    impl Snippet {
+        pub fn new(name: &str, body: &str) -> Self {
+            Self {
+                name: name.to_string(),
+                body: body.to_string(),
+                modified: DateTime::now(),
+            }
         }
     }
 }
 
 pub static MODELS: Lazy<Models> = Lazy::new(|| {
     let mut models = Models::new();
+    models.define::<schemas::v1::ClipEntryV1>().unwrap();
+    models.define::<schemas::v2::ClipEntryV2>().unwrap();
+    models.define::<schemas::v3::ClipEntryV3>().unwrap();
+    models.define::<schemas::v4::ClipEntryV4>().unwrap();
+    models.define::<schemas::v5::ClipEntryV5>().unwrap();
+    models.define::<schemas::v6::ClipEntryV6>().unwrap();
     models.define::<crate::database::ClipEntry>().unwrap();
+    models.define::<schemas::Counter>().unwrap();
+    models.define::<schemas::Snippet>().unwrap();
+    models.define::<schemas::Sealing>().unwrap();
     models
 });
+
+#[cfg(test)]
+mod test {
+    use camino::Utf8Path;
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::{schemas::v1::ClipEntryV1, *};
+    use crate::{
+        database::{encryption::DatabaseKey, get_db},
+        utils::mime::TEXT_MIME_TYPE,
+    };
+
+    #[test]
+    fn it_migrates_v1_payloads() -> anyhow::Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("test");
+        let entry = ClipEntryV1 {
+            epoch: schemas::DateTime::now(),
+            payload: b"asdf".to_vec(),
+            application: Some("terminal".to_string()),
+        };
+
+        {
+            let mut models = Models::new();
+            models.define::<ClipEntryV1>()?;
+            let db = Builder::new().create(&models, path.as_std_path())?;
+            // Written like an old plaintext database would have been
+            let _key = DatabaseKey::default().enter();
+            let tx = db.rw_transaction()?;
+            tx.insert(entry.clone())?;
+            tx.commit()?;
+        }
+
+        let db = get_db(Utf8Path::new(path.as_str()))?;
+        let migrated: ClipEntry = db.r_transaction()?.get().primary(entry.epoch)?.unwrap();
+
+        assert_eq!(migrated.epoch, entry.epoch);
+        assert_eq!(migrated.id, Some(1));
+        assert_eq!(migrated.application, entry.application);
+        assert_eq!(migrated.mime_type(), Some(TEXT_MIME_TYPE));
+        assert_eq!(migrated.bytes(), b"asdf");
+        assert_eq!(migrated.selection, Selection::Clipboard);
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use std::{collections::BTreeMap, sync::mpsc::Receiver};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDate};
+use clap::ValueEnum;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use native_db::watch::Event;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{blobs::Blobs, encryption::DatabaseKey, ClipEntry, ClipEntryKey, Database, Selection};
+use crate::utils::mime::is_text;
+
+#[derive(ValueEnum, Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Clips containing the query as is
+    #[default]
+    Substring,
+    Regex,
+    /// fzf style matching, best matches first
+    Fuzzy,
+}
+
+/// A search such as `app:firefox after:2026-01-01 type:image invoice`.
+///
+/// Qualifiers narrow down the clips searched and whatever is left over is the text to look for.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Query {
+    pub text: String,
+    pub mode: Mode,
+    pub ignore_case: bool,
+    /// `app:` part of the title of the window the clip was copied from, ignoring case
+    pub application: Option<String>,
+    /// `after:` and `before:` dates, neither of which is included
+    pub after: Option<NaiveDate>,
+    pub before: Option<NaiveDate>,
+    /// `type:` a MIME type or only the part before the slash, ie: `image`
+    pub mime_type: Option<String>,
+}
+
+impl Query {
+    pub fn parse(query: &str, mode: Mode, ignore_case: bool) -> Result<Self> {
+        let mut parsed = Self {
+            mode,
+            ignore_case,
+            ..Default::default()
+        };
+        let mut text = Vec::new();
+
+        for word in query.split_whitespace() {
+            match word.split_once(':') {
+                Some(("app", app)) => parsed.application = Some(app.to_string()),
+                Some(("after", date)) => parsed.after = Some(parse_date(date)?),
+                Some(("before", date)) => parsed.before = Some(parse_date(date)?),
+                Some(("type", mime_type)) => parsed.mime_type = Some(mime_type.to_string()),
+                _ => text.push(word),
+            }
+        }
+
+        parsed.text = text.join(" ");
+        Ok(parsed)
+    }
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| anyhow!("Expected a date like 2026-01-31 but got `{date}`: {e}"))
+}
+
+/// What a clip is searched by, kept around so searching doesn't need to decode every clip
+#[derive(Debug, Clone)]
+pub struct IndexedClip {
+    id: u64,
+    epoch: chrono::DateTime<Local>,
+    selection: Selection,
+    application: Option<String>,
+    mime_types: Vec<String>,
+    text: Option<String>,
+    /// Lowercase `text` for case insensitive searches
+    folded: Option<String>,
+}
+
+impl From<&ClipEntry> for IndexedClip {
+    fn from(entry: &ClipEntry) -> Self {
+        let text = entry.text().ok();
+
+        Self {
+            id: entry.id.unwrap_or_default(),
+            epoch: entry.epoch.0,
+            selection: entry.selection,
+            application: entry.application.as_ref().map(|app| app.to_lowercase()),
+            mime_types: entry.mime_types().map(str::to_lowercase).collect(),
+            folded: text.as_ref().map(|text| text.to_lowercase()),
+            text,
+        }
+    }
+}
+
+enum TextMatcher {
+    Substring(String),
+    Regex(Regex),
+    Fuzzy(Box<SkimMatcherV2>, String),
+}
+
+/// A compiled [`Query`]
+pub struct Matcher {
+    query: Query,
+    text: Option<TextMatcher>,
+}
+
+impl Matcher {
+    /// Fails if a regex query doesn't compile
+    pub fn new(query: &Query) -> Result<Self> {
+        let text = match (query.text.is_empty(), query.mode) {
+            (true, _) => None,
+            (false, Mode::Substring) => Some(TextMatcher::Substring(match query.ignore_case {
+                true => query.text.to_lowercase(),
+                false => query.text.clone(),
+            })),
+            (false, Mode::Regex) => Some(TextMatcher::Regex(
+                RegexBuilder::new(&query.text).case_insensitive(query.ignore_case).build()?,
+            )),
+            // Smart case like fzf unless asked to ignore case
+            (false, Mode::Fuzzy) => Some(TextMatcher::Fuzzy(
+                match query.ignore_case {
+                    true => Box::new(SkimMatcherV2::default().ignore_case()),
+                    false => Box::new(SkimMatcherV2::default().smart_case()),
+                },
+                query.text.clone(),
+            )),
+        };
+
+        Ok(Self {
+            query: query.clone(),
+            text,
+        })
+    }
+
+    /// How well `clip` matches, higher is better. `None` when it doesn't match at all
+    pub fn score(&self, clip: &IndexedClip) -> Option<i64> {
+        let query = &self.query;
+        let date = clip.epoch.date_naive();
+
+        if query.application.as_ref().is_some_and(|app| {
+            !clip
+                .application
+                .as_ref()
+                .is_some_and(|title| title.contains(&app.to_lowercase()))
+        }) || query.after.is_some_and(|after| date <= after)
+            || query.before.is_some_and(|before| date >= before)
+            || query.mime_type.as_ref().is_some_and(|mime_type| !has_type(clip, mime_type))
+        {
+            return None;
+        }
+
+        let Some(matcher) = &self.text else {
+            return Some(0);
+        };
+        let text = clip.text.as_deref()?;
+
+        match matcher {
+            TextMatcher::Substring(needle) => match query.ignore_case {
+                true => clip.folded.as_deref()?.contains(needle.as_str()),
+                false => text.contains(needle.as_str()),
+            }
+            .then_some(0),
+            TextMatcher::Regex(regex) => regex.is_match(text).then_some(0),
+            TextMatcher::Fuzzy(matcher, pattern) => matcher.fuzzy_match(text, pattern),
+        }
+    }
+}
+
+fn has_type(clip: &IndexedClip, mime_type: &str) -> bool {
+    let mime_type = mime_type.to_lowercase();
+
+    // X11 apps often offer text under legacy targets that aren't MIME types at all
+    (mime_type == "text" && clip.text.is_some())
+        || clip.mime_types.iter().any(|offered| {
+            *offered == mime_type
+                || offered.split_once('/').is_some_and(|(kind, _)| kind == mime_type)
+        })
+}
+
+/// Every clip in a database held in memory, ready to be searched.
+///
+/// The index follows changes to the database it was built from, so it only has to be built once.
+/// Changes queue up with their full contents until [`SearchIndex::sync`] applies them, which
+/// long lived holders should call regularly.
+pub struct SearchIndex {
+    clips: BTreeMap<u64, IndexedClip>,
+    events: Receiver<Event>,
+    /// Only text kept in files is read, nothing else is searched through
+    blobs: Blobs,
+    /// Changes are read outside of any transaction
+    key: DatabaseKey,
+}
+
+impl SearchIndex {
+    pub fn build(db: &Database) -> Result<Self> {
+        // Watched before scanning so nothing stored in between is missed
+        let (events, _) = db.watch().scan().primary().all::<ClipEntry>()?;
+        let clips = db
+            .r_transaction()?
+            .scan()
+            .primary::<ClipEntry>()?
+            .all()?
+            .flatten()
+            .filter_map(|mut entry| {
+                db.blobs.load_where(&mut entry, is_text);
+                Some((entry.id?, IndexedClip::from(&entry)))
+            })
+            .collect();
+
+        Ok(Self {
+            clips,
+            events,
+            blobs: db.blobs.clone(),
+            key: db.key.clone(),
+        })
+    }
+
+    /// Applies changes made to the database since the last sync, keeping only what's searched
+    pub fn sync(&mut self) {
+        let _key = self.key.enter();
+        while let Ok(event) = self.events.try_recv() {
+            let (removed, added) = match event {
+                Event::Insert(insert) => (None, insert.inner::<ClipEntry>().ok()),
+                Event::Update(update) => (
+                    update.inner_old::<ClipEntry>().ok(),
+                    update.inner_new::<ClipEntry>().ok(),
+                ),
+                Event::Delete(delete) => (delete.inner::<ClipEntry>().ok(), None),
+            };
+
+            if let Some(id) = removed.and_then(|entry| entry.id) {
+                self.clips.remove(&id);
+            }
+            if let Some(mut entry) = added {
+                self.blobs.load_where(&mut entry, is_text);
+                if let Some(id) = entry.id {
+                    self.clips.insert(id, IndexedClip::from(&entry));
+                }
+            }
+        }
+    }
+
+    /// Ids and scores of every matching clip, limited to `selection` when given
+    pub fn search(&mut self, matcher: &Matcher, selection: &Option<Selection>) -> Vec<(u64, i64)> {
+        self.sync();
+        self.clips
+            .values()
+            .filter(|clip| selection.is_none_or(|selection| clip.selection == selection))
+            .filter_map(|clip| Some((clip.id, matcher.score(clip)?)))
+            .collect()
+    }
+}
+
+/// Clips matching `query`, along with any of `others` that match, ie: sensitive clips.
+///
+/// Fuzzy results are ranked best match first, everything else is oldest first like `list`. Clips
+/// are loaded like [`super::get_clips`] does.
+pub fn search(
+    db: &Database,
+    index: &mut SearchIndex,
+    query: &Query,
+    selection: &Option<Selection>,
+    others: Vec<ClipEntry>,
+) -> Result<Vec<ClipEntry>> {
+    let matcher = Matcher::new(query)?;
+    let tx = db.r_transaction()?;
+    let mut found = Vec::new();
+
+    for (id, score) in index.search(&matcher, selection) {
+        if let Some(mut entry) = tx.get().secondary::<ClipEntry>(ClipEntryKey::id, Some(id))? {
+            db.blobs.load_preview(&mut entry);
+            found.push((score, entry));
+        }
+    }
+    found.extend(
+        others
+            .into_iter()
+            .filter(|entry| entry.is_from(selection))
+            .filter_map(|entry| Some((matcher.score(&IndexedClip::from(&entry))?, entry))),
+    );
+
+    match query.mode {
+        Mode::Fuzzy => found.sort_by(|(a, a_entry), (b, b_entry)| {
+            b.cmp(a).then_with(|| b_entry.epoch.0.cmp(&a_entry.epoch.0))
+        }),
+        _ => found.sort_by_key(|(_, entry)| entry.epoch.0),
+    }
+
+    Ok(found.into_iter().map(|(_, entry)| entry).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use camino::Utf8Path;
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+    use crate::database::{get_db, insert_clip, remove_clip, Payload};
+
+    fn store(db: &Database, text: &str, app: &str) -> Result<u64> {
+        let mut entry = ClipEntry::new(text.as_bytes());
+        entry.application = Some(app.to_string());
+        let tx = db.rw_transaction()?;
+        let id = insert_clip(&tx, entry)?;
+        tx.commit()?;
+
+        Ok(id)
+    }
+
+    fn texts(clips: Vec<ClipEntry>) -> Vec<String> {
+        clips.iter().map(|clip| clip.text().unwrap_or_default()).collect()
+    }
+
+    #[test]
+    fn it_parses_qualifiers() -> Result<()> {
+        let query = Query::parse(
+            "app:firefox after:2026-01-01 type:image cat pics",
+            Mode::Fuzzy,
+            true,
+        )?;
+
+        assert_eq!(query.text, "cat pics");
+        assert_eq!(query.application.as_deref(), Some("firefox"));
+        assert_eq!(query.after, NaiveDate::from_ymd_opt(2026, 1, 1));
+        assert_eq!(query.mime_type.as_deref(), Some("image"));
+        assert!(Query::parse("before:yesterday", Mode::Substring, false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_follows_the_database() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("test");
+        let db = get_db(Utf8Path::new(path.as_str()))?;
+        store(&db, "Hello World", "Firefox")?;
+        let mut index = SearchIndex::build(&db)?;
+
+        let gone = store(&db, "hello there", "Terminal")?;
+        store(&db, "goodbye", "Firefox")?;
+        let mut image = ClipEntry::with_payload(
+            Payload::from([("image/png".to_string(), b"\x89PNG".to_vec())]),
+            Selection::Clipboard,
+        );
+        image.application = Some("Firefox".to_string());
+        let tx = db.rw_transaction()?;
+        insert_clip(&tx, image)?;
+        tx.commit()?;
+
+        let mut find = |query: &str, mode, ignore_case| -> Result<Vec<ClipEntry>> {
+            search(
+                &db,
+                &mut index,
+                &Query::parse(query, mode, ignore_case)?,
+                &None,
+                vec![],
+            )
+        };
+
+        assert_eq!(
+            texts(find("hello", Mode::Substring, false)?),
+            ["hello there"]
+        );
+        assert_eq!(find("hello", Mode::Substring, true)?.len(), 2);
+        assert_eq!(texts(find("^good", Mode::Regex, false)?), ["goodbye"]);
+        assert_eq!(
+            texts(find("app:firefox hlo", Mode::Fuzzy, false)?),
+            ["Hello World"]
+        );
+        assert_eq!(find("type:image", Mode::Substring, false)?.len(), 1);
+        assert!(find("(", Mode::Regex, false).is_err());
+
+        remove_clip(&db, gone)?;
+        index.sync();
+        assert!(index.events.try_recv().is_err());
+        assert_eq!(
+            texts(search(
+                &db,
+                &mut index,
+                &Query::parse("hello", Mode::Substring, true)?,
+                &None,
+                vec![],
+            )?),
+            ["Hello World"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{ClipEntry, Selection};
+
+/// Clips that must never touch the disk, ie: passwords copied from a password manager.
+///
+/// They only live in the daemon's memory and are forgotten once they're older than the TTL, so a
+/// restart loses them too.
+pub struct SensitiveClips {
+    ttl: Mutex<Duration>,
+    clips: Mutex<Vec<(Instant, ClipEntry)>>,
+}
+
+impl SensitiveClips {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl: Mutex::new(ttl),
+            clips: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Applies to clips already stored as well
+    pub fn set_ttl(&self, ttl: Duration) {
+        *self.ttl.lock().unwrap() = ttl;
+    }
+
+    /// `entry` should already have an id, see [`reserve_id`](super::reserve_id)
+    pub fn insert(&self, entry: ClipEntry) {
+        self.clips.lock().unwrap().push((Instant::now(), entry));
+    }
+
+    /// Every clip that hasn't expired yet oldest first, limited to `selection` when given
+    pub fn clips(&self, selection: &Option<Selection>) -> Vec<ClipEntry> {
+        self.purge();
+        self.clips
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| entry.is_from(selection))
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<ClipEntry> {
+        self.purge();
+        self.clips
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, entry)| entry.id == Some(id))
+            .map(|(_, entry)| entry.clone())
+    }
+
+    pub fn remove(&self, id: u64) -> Option<ClipEntry> {
+        let mut clips = self.clips.lock().unwrap();
+        let index = clips.iter().position(|(_, entry)| entry.id == Some(id))?;
+
+        Some(clips.remove(index).1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.purge();
+        self.clips.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every expired clip
+    pub fn purge(&self) {
+        let ttl = *self.ttl.lock().unwrap();
+        self.clips.lock().unwrap().retain(|(stored, _)| stored.elapsed() < ttl);
+    }
+
+    pub fn clear(&self) {
+        self.clips.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_expires_clips() {
+        let clips = SensitiveClips::new(Duration::from_secs(60));
+        let mut entry = ClipEntry::new(b"hunter2");
+        entry.id = Some(7);
+        clips.insert(entry);
+
+        assert_eq!(clips.clips(&None)[0].bytes(), b"hunter2");
+        assert_eq!(clips.get(7).map(|entry| entry.id), Some(Some(7)));
+        assert!(clips.clips(&Some(Selection::Primary)).is_empty());
+
+        clips.set_ttl(Duration::ZERO);
+
+        assert!(clips.is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::{anyhow, Result};
+
+use super::{Database, Snippet};
+
+/// Every snippet ordered by name
+pub fn get_snippets(db: &Database) -> Result<Vec<Snippet>> {
+    Ok(db.r_transaction()?.scan().primary::<Snippet>()?.all()?.flatten().collect())
+}
+
+pub fn get_snippet(db: &Database, name: &str) -> Result<Option<Snippet>> {
+    Ok(db.r_transaction()?.get().primary(name.to_string())?)
+}
+
+/// Adds `snippet`, or replaces the one with the same name when `overwrite` is set
+pub fn save_snippet(db: &Database, snippet: Snippet, overwrite: bool) -> Result<()> {
+    let tx = db.rw_transaction()?;
+
+    match tx.get().primary::<Snippet>(snippet.name.clone())? {
+        Some(_) if !overwrite => return Err(anyhow!("Snippet `{}` already exists", snippet.name)),
+        None if overwrite => return Err(anyhow!("There is no snippet named `{}`", snippet.name)),
+        _ => {
+            tx.upsert(snippet)?;
+        },
+    }
+
+    tx.commit()
+}
+
+pub fn remove_snippet(db: &Database, name: &str) -> Result<Option<Snippet>> {
+    let tx = db.rw_transaction()?;
+    let Some(snippet) = tx.get().primary::<Snippet>(name.to_string())? else {
+        return Ok(None);
+    };
+    let removed = tx.remove(snippet)?;
+    tx.commit()?;
+
+    Ok(Some(removed))
+}
+
+#[cfg(test)]
+mod test {
+    use camino::Utf8Path;
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+    use crate::database::get_db;
+
+    #[test]
+    fn it_manages_snippets() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("test");
+        let db = get_db(Utf8Path::new(path.as_str()))?;
+
+        save_snippet(&db, Snippet::new("sig", "Regards"), false)?;
+        assert!(save_snippet(&db, Snippet::new("sig", "Cheers"), false).is_err());
+        assert!(save_snippet(&db, Snippet::new("addr", "Main St"), true).is_err());
+
+        save_snippet(&db, Snippet::new("sig", "Cheers"), true)?;
+        save_snippet(&db, Snippet::new("addr", "Main St"), false)?;
+
+        let names = get_snippets(&db)?.into_iter().map(|snippet| snippet.name).collect::<Vec<_>>();
+        assert_eq!(names, ["addr", "sig"]);
+        assert_eq!(get_snippet(&db, "sig")?.unwrap().body, "Cheers");
+
+        assert!(remove_snippet(&db, "sig")?.is_some());
+        assert_eq!(get_snippet(&db, "sig")?, None);
+        Ok(())
+    }
+}
@@ -3,10 +3,7 @@ use itertools::Itertools;
 use shortcut_assert_fs::TmpFs;
 
 use super::*;
-use crate::{
-    database::schema::{ClipEntry, Database},
-    utils::random_str,
-};
+use crate::{database::schema::ClipEntry, utils::random_str};
 
 pub enum FillWith<'a> {
     Dupes(&'a str),
@@ -21,7 +18,7 @@ pub fn get_db_contents(db: &Database) -> Result<Vec<Vec<u8>>> {
         .primary::<ClipEntry>()?
         .all()?
         .flatten()
-        .map(|entry| entry.payload)
+        .map(|entry| entry.bytes().to_vec())
         .collect_vec();
 
     Ok(contents)
@@ -36,7 +33,7 @@ where
     let db = get_db(Utf8Path::new(path.as_str()))?;
     let mut all_items = Vec::<Vec<u8>>::new();
 
-    for i in 0..20 {
+    for i in 0..amount {
         let dummy = match fill {
             FillWith::Dupes(dupe) => dupe,
             FillWith::Random => &random_str(7),
@@ -49,7 +46,7 @@ where
 
         let tx = db.rw_transaction()?;
         {
-            tx.insert(ClipEntry::new(dummy.as_bytes()))?;
+            insert_clip(&tx, ClipEntry::new(dummy.as_bytes()))?;
         }
         tx.commit()?;
 
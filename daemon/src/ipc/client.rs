@@ -0,0 +1,180 @@
+use std::{os::unix::net::UnixStream, time::Duration};
+
+use anyhow::{anyhow, Result};
+
+use super::{read_message, socket_path, write_message, Request, Response, Status};
+use crate::database::{
+    retention::{Expired, Retention},
+    search::Query,
+    ClipEntry, Selection, Snippet,
+};
+
+/// Long enough for the daemon to answer a `list` of a large history
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Client {
+    stream: UnixStream,
+}
+
+impl Client {
+    /// `None` when no daemon is listening
+    pub fn connect() -> Option<Self> {
+        let stream = UnixStream::connect(socket_path()?).ok()?;
+        stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+
+        Some(Self { stream })
+    }
+
+    pub fn request(&mut self, request: Request) -> Result<Response> {
+        write_message(&mut self.stream, request)?;
+
+        match read_message(&mut self.stream)? {
+            Response::Error(e) => Err(anyhow!(e)),
+            response => Ok(response),
+        }
+    }
+
+    pub fn list(
+        &mut self,
+        selection: Option<Selection>,
+        sensitive: bool,
+    ) -> Result<Vec<ClipEntry>> {
+        match self.request(Request::List {
+            selection,
+            sensitive,
+        })? {
+            Response::Clips(clips) => Ok(clips),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn search(
+        &mut self,
+        query: Query,
+        selection: Option<Selection>,
+        sensitive: bool,
+    ) -> Result<Vec<ClipEntry>> {
+        match self.request(Request::Search {
+            query,
+            selection,
+            sensitive,
+        })? {
+            Response::Clips(clips) => Ok(clips),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn recall(&mut self, id: u64) -> Result<Option<ClipEntry>> {
+        match self.request(Request::Recall { id })? {
+            Response::Clip(clip) => Ok(clip),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn store(&mut self, entry: ClipEntry, duplicates: i64, keep: u64) -> Result<()> {
+        self.expect_done(Request::Store {
+            entry,
+            duplicates,
+            keep,
+        })
+    }
+
+    pub fn import(&mut self, entries: Vec<ClipEntry>, duplicates: i64, keep: u64) -> Result<u64> {
+        match self.request(Request::Import {
+            entries,
+            duplicates,
+            keep,
+        })? {
+            Response::Count(imported) => Ok(imported),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn prune(&mut self, retention: Retention, dry_run: bool) -> Result<Vec<Expired>> {
+        match self.request(Request::Prune { retention, dry_run })? {
+            Response::Pruned(expired) => Ok(expired),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn store_sensitive(&mut self, entry: ClipEntry) -> Result<()> {
+        self.expect_done(Request::StoreSensitive { entry })
+    }
+
+    pub fn remove(&mut self, id: u64) -> Result<Option<ClipEntry>> {
+        match self.request(Request::Remove { id })? {
+            Response::Clip(clip) => Ok(clip),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn pin(&mut self, id: u64, pinned: bool) -> Result<Option<ClipEntry>> {
+        match self.request(Request::Pin { id, pinned })? {
+            Response::Clip(clip) => Ok(clip),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn wipe(&mut self) -> Result<()> {
+        self.expect_done(Request::Wipe)
+    }
+
+    pub fn snippets(&mut self) -> Result<Vec<Snippet>> {
+        match self.request(Request::Snippets)? {
+            Response::Snippets(snippets) => Ok(snippets),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn snippet(&mut self, name: &str) -> Result<Option<Snippet>> {
+        match self.request(Request::Snippet {
+            name: name.to_string(),
+        })? {
+            Response::Snippet(snippet) => Ok(snippet),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn save_snippet(&mut self, snippet: Snippet, overwrite: bool) -> Result<()> {
+        self.expect_done(Request::SaveSnippet { snippet, overwrite })
+    }
+
+    pub fn remove_snippet(&mut self, name: &str) -> Result<Option<Snippet>> {
+        match self.request(Request::RemoveSnippet {
+            name: name.to_string(),
+        })? {
+            Response::Snippet(snippet) => Ok(snippet),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn status(&mut self) -> Result<Status> {
+        match self.request(Request::Status)? {
+            Response::Status(status) => Ok(status),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub fn pause(&mut self) -> Result<()> {
+        self.expect_done(Request::Pause)
+    }
+
+    pub fn resume(&mut self) -> Result<()> {
+        self.expect_done(Request::Resume)
+    }
+
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.expect_done(Request::Shutdown)
+    }
+
+    fn expect_done(&mut self, request: Request) -> Result<()> {
+        match self.request(request)? {
+            Response::Done => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> anyhow::Error {
+    anyhow!("Unexpected response from daemon: {response:?}")
+}
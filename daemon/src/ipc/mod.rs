@@ -0,0 +1,245 @@
+pub mod client;
+pub mod server;
+
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Result};
+pub use client::Client;
+use derive_more::{Display, Error};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+pub use server::{serve, DaemonState};
+
+use crate::{
+    database::{
+        retention::{Expired, Retention},
+        search::Query,
+        ClipEntry, Selection, Snippet,
+    },
+    utils::get_runtime_path,
+};
+
+/// Bumped whenever [`Request`] or [`Response`] change shape
+pub const PROTOCOL_VERSION: u32 = 10;
+
+/// Guards against allocating garbage lengths from a misbehaving peer
+const MAX_MESSAGE_SIZE: usize = 1 << 30;
+
+pub fn socket_path() -> Option<String> {
+    get_runtime_path("clippy", "clippy.sock")
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Request {
+    /// `sensitive` also lists clips only kept in memory
+    List {
+        selection: Option<Selection>,
+        sensitive: bool,
+    },
+    /// `sensitive` also searches clips only kept in memory
+    Search {
+        query: Query,
+        selection: Option<Selection>,
+        sensitive: bool,
+    },
+    /// Sensitive clips can be recalled by id too
+    Recall {
+        id: u64,
+    },
+    Store {
+        entry: ClipEntry,
+        duplicates: i64,
+        keep: u64,
+    },
+    /// Adds clips from a backup under their own timestamps, answered with how many were new
+    Import {
+        entries: Vec<ClipEntry>,
+        duplicates: i64,
+        keep: u64,
+    },
+    /// Keeps `entry` in memory only until it expires
+    StoreSensitive {
+        entry: ClipEntry,
+    },
+    Remove {
+        id: u64,
+    },
+    Pin {
+        id: u64,
+        pinned: bool,
+    },
+    Wipe,
+    /// Removes clips past `retention`, answered with what was or with `dry_run` would be removed
+    Prune {
+        retention: Retention,
+        dry_run: bool,
+    },
+    Snippets,
+    Snippet {
+        name: String,
+    },
+    /// Fails if `overwrite` doesn't match whether a snippet with the same name exists
+    SaveSnippet {
+        snippet: Snippet,
+        overwrite: bool,
+    },
+    RemoveSnippet {
+        name: String,
+    },
+    Status,
+    Pause,
+    Resume,
+    /// Stops the daemon once it has answered
+    Shutdown,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Response {
+    Clips(Vec<ClipEntry>),
+    Clip(Option<ClipEntry>),
+    Snippets(Vec<Snippet>),
+    Snippet(Option<Snippet>),
+    Count(u64),
+    Pruned(Vec<Expired>),
+    Status(Status),
+    Done,
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Status {
+    pub pid: u32,
+    pub paused: bool,
+    pub clips: u64,
+    pub sensitive_clips: u64,
+    pub db_path: String,
+}
+
+/// A message over [`MAX_MESSAGE_SIZE`], refused by whichever side sees it first
+#[derive(Error, Display, Debug, PartialEq)]
+#[display("Refusing to {verb} a {length} byte message, the limit is {MAX_MESSAGE_SIZE}")]
+pub struct MessageTooLarge {
+    verb: &'static str,
+    pub length: usize,
+}
+
+/// `length` as it's sent ahead of a message, which always fits
+fn frame_length(verb: &'static str, length: usize) -> Result<u32, MessageTooLarge> {
+    match length > MAX_MESSAGE_SIZE {
+        true => Err(MessageTooLarge { verb, length }),
+        false => Ok(length as u32),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    message: T,
+}
+
+/// Writes `message` prefixed with its length. Every message carries [`PROTOCOL_VERSION`]
+pub fn write_message<T: Serialize>(stream: &mut impl Write, message: T) -> Result<()> {
+    let envelope = Envelope {
+        version: PROTOCOL_VERSION,
+        message,
+    };
+    let bytes = bincode::serde::encode_to_vec(envelope, bincode::config::standard())?;
+    let length = frame_length("write", bytes.len())?;
+
+    stream.write_all(&length.to_be_bytes())?;
+    stream.write_all(&bytes)?;
+    Ok(stream.flush()?)
+}
+
+pub fn read_message<T: DeserializeOwned>(stream: &mut impl Read) -> Result<T> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+
+    let length = frame_length("read", u32::from_be_bytes(length) as usize)?;
+
+    let mut bytes = vec![0u8; length as usize];
+    stream.read_exact(&mut bytes)?;
+
+    let (version, _): (u32, _) =
+        bincode::serde::decode_from_slice(&bytes, bincode::config::standard())?;
+    if version != PROTOCOL_VERSION {
+        return Err(anyhow!(
+            "Daemon speaks protocol version {version} but clippy expects {PROTOCOL_VERSION}. \
+            Restart the daemon after upgrading"
+        ));
+    }
+
+    let (envelope, _): (Envelope<T>, _) =
+        bincode::serde::decode_from_slice(&bytes, bincode::config::standard())?;
+    Ok(envelope.message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_frames_messages() -> Result<()> {
+        let mut buf = Cursor::new(Vec::new());
+        write_message(
+            &mut buf,
+            Request::List {
+                selection: Some(Selection::Primary),
+                sensitive: false,
+            },
+        )?;
+        write_message(&mut buf, Request::Status)?;
+        buf.set_position(0);
+
+        assert_eq!(
+            read_message::<Request>(&mut buf)?,
+            Request::List {
+                selection: Some(Selection::Primary),
+                sensitive: false,
+            }
+        );
+        assert_eq!(read_message::<Request>(&mut buf)?, Request::Status);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_other_versions() -> Result<()> {
+        let mut buf = Cursor::new(Vec::new());
+        let bytes = bincode::serde::encode_to_vec(
+            Envelope {
+                version: PROTOCOL_VERSION + 1,
+                message: Request::Status,
+            },
+            bincode::config::standard(),
+        )?;
+        buf.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        buf.write_all(&bytes)?;
+        buf.set_position(0);
+
+        assert!(read_message::<Request>(&mut buf).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_refuses_large_messages() -> Result<()> {
+        assert_eq!(frame_length("write", MAX_MESSAGE_SIZE)?, MAX_MESSAGE_SIZE as u32);
+        // Would wrap around to 1 as a u32
+        assert_eq!(
+            frame_length("write", (1 << 32) + 1),
+            Err(MessageTooLarge {
+                verb: "write",
+                length: (1 << 32) + 1
+            })
+        );
+
+        let mut buf = Cursor::new(Vec::new());
+        buf.write_all(&(MAX_MESSAGE_SIZE as u32 + 1).to_be_bytes())?;
+        buf.set_position(0);
+        let err = read_message::<Request>(&mut buf).unwrap_err();
+        assert!(err.downcast_ref::<MessageTooLarge>().is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    fs,
+    os::unix::net::{UnixListener, UnixStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use camino::Utf8Path;
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+use super::{read_message, socket_path, write_message, Request, Response, Status};
+use crate::{
+    database::{
+        get_clip, get_clips, import_clips, open_db, remove_clip, reserve_id,
+        retention::prune,
+        search::{search, Query, SearchIndex},
+        sensitive::SensitiveClips,
+        set_pinned,
+        snippets::{get_snippet, get_snippets, remove_snippet, save_snippet},
+        store_clip, wipe, ClipEntry, Database, Selection, TableLen,
+    },
+    utils::{
+        config::{Clipboard, Config, Encryption},
+        filter::{Decision, Filters},
+    },
+};
+
+struct OpenDatabase {
+    path: String,
+    db: Arc<Database<'static>>,
+    index: Mutex<SearchIndex>,
+}
+
+impl OpenDatabase {
+    fn open(db_path: &str, encryption: Option<&Encryption>) -> Result<Self> {
+        let db = open_db(Utf8Path::new(db_path), encryption)?;
+        db.sweep()?;
+
+        Ok(Self {
+            path: db_path.to_string(),
+            index: Mutex::new(SearchIndex::build(&db)?),
+            db: Arc::new(db),
+        })
+    }
+}
+
+pub struct DaemonState {
+    /// Clips keep being watched while paused but none are stored
+    pub paused: AtomicBool,
+    pub sensitive: SensitiveClips,
+    /// Notified when a client asks the daemon to stop
+    pub shutdown: Notify,
+    database: RwLock<OpenDatabase>,
+    /// Every database the daemon opens is unlocked with the same key
+    encryption: Option<Encryption>,
+    /// The live config, whose include and exclude rules every clip goes through
+    config: Arc<Mutex<Config>>,
+}
+
+impl DaemonState {
+    /// The key is the one `config` has now, changing it takes a restart
+    pub fn open(db_path: &str, config: Arc<Mutex<Config>>) -> Result<Self> {
+        let encryption = config.lock().unwrap().encryption();
+
+        Ok(Self {
+            paused: AtomicBool::new(false),
+            sensitive: SensitiveClips::new(Clipboard::default().sensitive_ttl()),
+            shutdown: Notify::new(),
+            database: RwLock::new(OpenDatabase::open(db_path, encryption.as_ref())?),
+            encryption,
+            config,
+        })
+    }
+
+    /// Include and exclude rules of the live config. Fails on a broken rule, it could be the one
+    /// keeping a password out
+    pub fn filters(&self) -> Result<Filters> {
+        Filters::new(&self.config.lock().unwrap().clipboard())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// The open database, which stays open while this is held even if it's switched away from
+    pub fn db(&self) -> Arc<Database<'static>> {
+        Arc::clone(&self.database.read().unwrap().db)
+    }
+
+    pub fn db_path(&self) -> String {
+        self.database.read().unwrap().path.clone()
+    }
+
+    /// Searches the index kept for the open database, merged with the clips kept in memory when
+    /// `sensitive` is set
+    pub fn search(
+        &self,
+        query: &Query,
+        selection: &Option<Selection>,
+        sensitive: bool,
+    ) -> Result<Vec<ClipEntry>> {
+        let database = self.database.read().unwrap();
+        let others = match sensitive {
+            true => self.sensitive.clips(selection),
+            false => Vec::new(),
+        };
+
+        let mut index = database.index.lock().unwrap();
+
+        search(&database.db, &mut index, query, selection, others)
+    }
+
+    /// Applies the changes to the open database to its search index, which holds on to them
+    /// until then
+    pub fn sync_index(&self) {
+        self.database.read().unwrap().index.lock().unwrap().sync();
+    }
+
+    /// Moves over to the database at `db_path` unless it's the one already open. The old one
+    /// stays open if the new one can't be opened
+    pub fn switch_db(&self, db_path: &str) -> Result<()> {
+        if self.db_path() == db_path {
+            return Ok(());
+        }
+        let mut database = self.database.write().unwrap();
+        if database.path == db_path {
+            return Ok(());
+        }
+
+        *database = OpenDatabase::open(db_path, self.encryption.as_ref())?;
+        debug!("Switched database to {db_path}");
+
+        Ok(())
+    }
+}
+
+/// Binds the daemon socket and answers requests on a background thread.
+///
+/// Fails if another daemon is already listening.
+pub fn serve(state: Arc<DaemonState>) -> Result<()> {
+    let path = socket_path().ok_or_else(|| anyhow!("Unable to determine socket path"))?;
+
+    if UnixStream::connect(&path).is_ok() {
+        return Err(anyhow!(
+            "Another clippy daemon is already listening on {path}"
+        ));
+    }
+    // Left behind by a daemon that didn't shut down cleanly
+    let _ = fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path)?;
+    debug!("Listening for requests on {path}");
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let state = Arc::clone(&state);
+                    thread::spawn(move || serve_client(stream, &state));
+                },
+                Err(e) => warn!("Failed to accept connection: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve_client(mut stream: UnixStream, state: &DaemonState) {
+    loop {
+        let response = match read_message::<Request>(&mut stream) {
+            Ok(request) => handle(state, request),
+            // Also hit when the client hangs up, in which case the write below fails quietly
+            Err(e) => {
+                let _ = write_message(&mut stream, Response::Error(e.to_string()));
+                return;
+            },
+        };
+
+        if write_message(&mut stream, response).is_err() {
+            return;
+        }
+    }
+}
+
+pub fn handle(state: &DaemonState, request: Request) -> Response {
+    // Held for the whole request so a database switch can't close it underneath us
+    let db = &state.db();
+    let response = match request {
+        Request::List {
+            selection,
+            sensitive,
+        } => clips(db, state, &selection, sensitive).map(Response::Clips),
+        Request::Search {
+            query,
+            selection,
+            sensitive,
+        } => state.search(&query, &selection, sensitive).map(Response::Clips),
+        Request::Recall { id } => get_clip(db, id)
+            .map(|clip| clip.or_else(|| state.sensitive.get(id)))
+            .map(Response::Clip),
+        Request::Store {
+            entry,
+            duplicates,
+            keep,
+        } => state
+            .filters()
+            .and_then(|filters| store_clip(db, entry, &filters, duplicates, keep))
+            .map(|_| Response::Done),
+        Request::Import {
+            entries,
+            duplicates,
+            keep,
+        } => state
+            .filters()
+            .and_then(|filters| import_clips(db, entries, &filters, duplicates, keep))
+            .map(Response::Count),
+        Request::StoreSensitive { entry } =>
+            store_sensitive(db, state, entry).map(|_| Response::Done),
+        Request::Remove { id } => remove_clip(db, id)
+            .inspect(|_| sweep(db))
+            .map(|clip| clip.or_else(|| state.sensitive.remove(id)))
+            .map(Response::Clip),
+        Request::Pin { id, pinned } => set_pinned(db, id, pinned).map(Response::Clip),
+        Request::Wipe => {
+            state.sensitive.clear();
+            wipe(db).inspect(|_| sweep(db)).map(|_| Response::Done)
+        },
+        Request::Prune { retention, dry_run } => prune(db, &retention, dry_run, true)
+            .inspect(|_| {
+                if !dry_run {
+                    sweep(db)
+                }
+            })
+            .map(Response::Pruned),
+        Request::Snippets => get_snippets(db).map(Response::Snippets),
+        Request::Snippet { name } => get_snippet(db, &name).map(Response::Snippet),
+        Request::SaveSnippet { snippet, overwrite } =>
+            save_snippet(db, snippet, overwrite).map(|_| Response::Done),
+        Request::RemoveSnippet { name } => remove_snippet(db, &name).map(Response::Snippet),
+        Request::Status => status(db, state).map(Response::Status),
+        Request::Pause => {
+            state.paused.store(true, Ordering::Relaxed);
+            Ok(Response::Done)
+        },
+        Request::Resume => {
+            state.paused.store(false, Ordering::Relaxed);
+            Ok(Response::Done)
+        },
+        Request::Shutdown => {
+            state.shutdown.notify_one();
+            Ok(Response::Done)
+        },
+    };
+
+    response.unwrap_or_else(|e| Response::Error(e.to_string()))
+}
+
+/// Also catches files left behind by a crash, the clips are gone either way so failing is only
+/// logged
+fn sweep(db: &Database) {
+    if let Err(e) = db.sweep() {
+        warn!("Failed to remove files no clip references: {e:#}");
+    }
+}
+
+/// Kept in memory only, unless the rules exclude it
+fn store_sensitive(db: &Database, state: &DaemonState, mut entry: ClipEntry) -> Result<()> {
+    if let Decision::Excluded(rule) = state.filters()?.check(&entry) {
+        debug!("Ignoring clip matching {rule}");
+        return Ok(());
+    }
+    entry.id = Some(reserve_id(db)?);
+    state.sensitive.insert(entry);
+
+    Ok(())
+}
+
+/// Clips on disk, merged with the ones kept in memory when `sensitive` is set
+fn clips(
+    db: &Database,
+    state: &DaemonState,
+    selection: &Option<Selection>,
+    sensitive: bool,
+) -> Result<Vec<ClipEntry>> {
+    let mut clips = get_clips(db, selection)?;
+
+    if sensitive {
+        clips.extend(state.sensitive.clips(selection));
+        clips.sort_by_key(|entry| entry.epoch.0);
+    }
+
+    Ok(clips)
+}
+
+fn status(db: &Database, state: &DaemonState) -> Result<Status> {
+    Ok(Status {
+        pid: std::process::id(),
+        paused: state.is_paused(),
+        clips: db.r_transaction()?.length()?,
+        sensitive_clips: state.sensitive.len() as u64,
+        db_path: state.db_path(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+    use crate::{
+        database::insert_clip,
+        utils::config::{Encryption, General},
+    };
+
+    fn insert(state: &DaemonState, clip: &str) -> Result<()> {
+        let db = state.db();
+        let tx = db.rw_transaction()?;
+        insert_clip(&tx, ClipEntry::new(clip.as_bytes()))?;
+        tx.commit()
+    }
+
+    fn texts(db: &Database) -> Result<Vec<String>> {
+        get_clips(db, &None)?.iter().map(ClipEntry::text).collect()
+    }
+
+    fn list(state: &DaemonState, sensitive: bool) -> usize {
+        match handle(
+            state,
+            Request::List {
+                selection: None,
+                sensitive,
+            },
+        ) {
+            Response::Clips(clips) => clips.len(),
+            response => panic!("Expected clips, got {response:?}"),
+        }
+    }
+
+    #[test]
+    fn it_handles_requests() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let state = DaemonState::open(tf.path("test").as_str(), Default::default())?;
+
+        insert(&state, "asdf")?;
+        insert(&state, "qwer")?;
+
+        let Response::Clip(Some(clip)) = handle(&state, Request::Recall { id: 2 }) else {
+            panic!("Expected a clip");
+        };
+        assert_eq!(clip.bytes(), b"qwer");
+
+        handle(
+            &state,
+            Request::StoreSensitive {
+                entry: ClipEntry::new(b"hunter2"),
+            },
+        );
+        assert_eq!(list(&state, false), 2);
+        assert_eq!(list(&state, true), 3);
+
+        let search = |sensitive| {
+            handle(
+                &state,
+                Request::Search {
+                    query: Query::parse("hunter", Default::default(), false).unwrap(),
+                    selection: None,
+                    sensitive,
+                },
+            )
+        };
+        assert_eq!(search(false), Response::Clips(vec![]));
+        assert!(matches!(search(true), Response::Clips(clips) if clips.len() == 1));
+
+        handle(&state, Request::Pause);
+        let Response::Status(status) = handle(&state, Request::Status) else {
+            panic!("Expected a status");
+        };
+        assert!(status.paused);
+        assert_eq!(status.clips, 2);
+        assert_eq!(status.sensitive_clips, 1);
+
+        assert_eq!(handle(&state, Request::Wipe), Response::Done);
+        assert_eq!(list(&state, true), 0);
+
+        assert_eq!(handle(&state, Request::Shutdown), Response::Done);
+        tokio_test::block_on(state.shutdown.notified());
+        Ok(())
+    }
+
+    #[test]
+    fn it_switches_databases() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let (first, second) = (tf.path("first").to_string(), tf.path("second").to_string());
+        let state = DaemonState::open(&first, Default::default())?;
+
+        insert(&state, "asdf")?;
+        state.switch_db(&second)?;
+
+        assert_eq!(state.db_path(), second);
+        assert_eq!(list(&state, false), 0);
+
+        state.switch_db(&first)?;
+
+        assert_eq!(list(&state, false), 1);
+
+        // Can't be created under a file
+        assert!(state.switch_db(&format!("{first}/nested")).is_err());
+        assert_eq!(state.db_path(), first);
+        assert_eq!(list(&state, false), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn it_switches_between_encrypted_databases() -> Result<()> {
+        let tf = TmpFs::new()?;
+        fs::write(tf.path("key"), "correct horse\n")?;
+        let config = Config {
+            general: Some(General {
+                encryption: Some(Encryption::Keyfile {
+                    path: tf.path("key").to_string(),
+                }),
+                ..General::default()
+            }),
+            ..Config::default()
+        };
+        let [encrypted, plaintext, broken, fresh] =
+            ["encrypted", "plaintext", "broken", "fresh"].map(|name| tf.path(name).to_string());
+        let plain = open_db(Utf8Path::new(&plaintext), None)?;
+        let tx = plain.rw_transaction()?;
+        insert_clip(&tx, ClipEntry::new(b"plain clip"))?;
+        tx.commit()?;
+        drop(plain);
+
+        let state = DaemonState::open(&encrypted, Arc::new(Mutex::new(config)))?;
+        insert(&state, "secret clip")?;
+
+        // Has clips so it has to go through `clippy encrypt` first
+        assert!(state.switch_db(&plaintext).is_err());
+        // Encrypted under a key of its own, but its files can't be looked through
+        fs::write(format!("{broken}.blobs"), b"")?;
+        assert!(state.switch_db(&broken).is_err());
+        assert_eq!(state.db_path(), encrypted);
+        assert_eq!(texts(&state.db())?, ["secret clip"]);
+
+        state.switch_db(&fresh)?;
+        let old = open_db(Utf8Path::new(&plaintext), None)?;
+        insert(&state, "fresh clip")?;
+        // Neither goes through the key of the other
+        assert_eq!(texts(&state.db())?, ["fresh clip"]);
+        assert_eq!(texts(&old)?, ["plain clip"]);
+
+        state.switch_db(&encrypted)?;
+        assert_eq!(texts(&state.db())?, ["secret clip"]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_filters_stored_clips() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let config: Config = toml::from_str(
+            r#"
+            [clipboard.default.exclude.secrets]
+            patterns = "^hunter2$"
+            "#,
+        )?;
+        let state = DaemonState::open(tf.path("test").as_str(), Arc::new(Mutex::new(config)))?;
+        let store = |entry| Request::Store {
+            entry,
+            duplicates: 0,
+            keep: 0,
+        };
+
+        assert_eq!(
+            handle(&state, store(ClipEntry::new(b"hunter2"))),
+            Response::Done
+        );
+        assert_eq!(
+            handle(&state, store(ClipEntry::new(b"asdf"))),
+            Response::Done
+        );
+        assert_eq!(
+            handle(
+                &state,
+                Request::StoreSensitive {
+                    entry: ClipEntry::new(b"hunter2")
+                }
+            ),
+            Response::Done
+        );
+        let imported = handle(
+            &state,
+            Request::Import {
+                entries: vec![ClipEntry::new(b"hunter2")],
+                duplicates: 0,
+                keep: 0,
+            },
+        );
+
+        assert_eq!(imported, Response::Count(0));
+        assert_eq!(list(&state, true), 1);
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-extern crate log;
 pub mod database;
+pub mod ipc;
 pub mod platforms;
 pub mod utils;
@@ -1,57 +1,313 @@
 use std::{
+    fs, panic,
     path::Path,
+    process::ExitCode,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use anyhow::Result;
-use camino::Utf8Path;
+use anyhow::{anyhow, Result};
+use clap::Parser;
 use clippy_daemon::{
-    database::{ensure_db_size, get_db, remove_duplicates},
+    database::{
+        reserve_id,
+        retention::{prune, Retention},
+        store_clip, ClipEntry, Selection,
+    },
+    ipc::{serve, socket_path, DaemonState},
     platforms::listen_for_clips,
     utils::{
-        async_helpers::GeneratorStream,
         config::{watch_config, Config},
-        get_cache_path, get_config_path,
+        filter::{Decision, Filters},
+        get_config_path,
+        limits::{Oversized, SizeLimits},
+        logging::{self, LogFormat},
+        pidfile::{pid_path, PidFile},
     },
 };
-use futures::StreamExt;
-use tokio::task;
+use futures::{Stream, StreamExt};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task, time,
+};
+use tracing::{debug, error, info, instrument, warn};
+
+#[derive(Parser)]
+#[command(name = "clippy_daemon", version)]
+/// Watches the clipboard and stores every clip for clippy to recall
+struct DaemonCli {
+    /// Selection to record, may be repeated. Overrides `general.selections` from the config
+    #[arg(long = "board", value_enum)]
+    boards: Vec<Selection>,
+    /// Log more, may be repeated. `CLIPPY_LOG` takes precedence, ie: `clippy_daemon=trace`
+    #[arg(short, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Format of the log written to `stderr` and the log files
+    #[arg(long, value_enum, default_value_t)]
+    log_format: LogFormat,
+}
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> ExitCode {
+    let args = DaemonCli::parse();
+    let _log_guard = match logging::init(args.verbose, args.log_format, Some("daemon")) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Failed to set up logging: {e}");
+            return ExitCode::FAILURE;
+        },
+    };
+    // Nobody reads `stderr` once the daemon runs in the background
+    panic::set_hook(Box::new(|info| error!("{info}")));
+
+    match start(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{e:#}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn start(args: DaemonCli) -> Result<()> {
+    // Only one daemon ever watches the clipboard. The lock outlives the runtime so it's only let go
+    // once every task holding the database is gone
+    let _pid_file =
+        PidFile::acquire(&pid_path().ok_or_else(|| anyhow!("Unable to determine pid file path"))?)?;
+    info!(pid = std::process::id(), "Starting");
+
+    tokio::runtime::Runtime::new()?.block_on(run(args))
+}
+
+async fn run(args: DaemonCli) -> Result<()> {
     let config_path = get_config_path("clippy", "config.toml").unwrap();
-    let config = Arc::new(Mutex::new(
-        Config::from_file(Path::new(&config_path)).await?,
+    let config = Arc::new(Mutex::new(Config::load(Path::new(&config_path))?));
+    let selections = match args.boards.is_empty() {
+        true => config
+            .lock()
+            .unwrap()
+            .general
+            .as_ref()
+            .and_then(|general| general.selections.clone())
+            .unwrap_or_else(|| vec![Selection::Clipboard]),
+        false => args.boards,
+    };
+    task::spawn(watch_config(config_path.clone(), Arc::clone(&config)));
+
+    let db_path = config.lock().unwrap().db_path();
+    let state = Arc::new(DaemonState::open(
+        &db_path.ok_or_else(|| anyhow!("Unable to determine database path"))?,
+        Arc::clone(&config),
+    )?);
+    serve(Arc::clone(&state))?;
+    task::spawn(expire_sensitive_clips(
+        Arc::clone(&state),
+        Arc::clone(&config),
     ));
-    let watcher_task = {
-        let config_path = config_path.clone();
-        let config = Arc::clone(&config);
-        task::spawn(watch_config(config_path, config))
+    task::spawn(enforce_retention(Arc::clone(&state), Arc::clone(&config)));
+    task::spawn(sync_search_index(Arc::clone(&state)));
+
+    let result = match listen_for_clips(selections, Arc::clone(&config)) {
+        Ok(clips) => respond_until_shutdown(&state, &config, clips).await,
+        Err(e) => Err(e),
     };
 
-    respond_to_clips().await?;
+    if let Some(path) = socket_path() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
 
-    let _ = watcher_task.await;
+/// Stores `clips` as they come until the daemon is asked to stop. Clips are awaited rather than
+/// waited on so a stop is heard even while nothing is being copied
+async fn respond_until_shutdown(
+    state: &DaemonState,
+    config: &Arc<Mutex<Config>>,
+    clips: impl Stream<Item = ClipEntry> + Unpin,
+) -> Result<()> {
+    tokio::select! {
+        result = respond_to_clips(state, config, clips) => result,
+        result = shutdown_requested(state) => result.map(|()| info!("Shutting down")),
+    }
+}
 
+/// Resolves once `clippy daemon stop`, Ctrl-C or the service manager asks the daemon to stop
+async fn shutdown_requested(state: &DaemonState) -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        _ = state.shutdown.notified() => (),
+        _ = terminate.recv() => (),
+        result = tokio::signal::ctrl_c() => result?,
+    }
     Ok(())
 }
 
-async fn respond_to_clips() -> Result<()> {
-    let path = get_cache_path("clippy", "db").unwrap();
-    let db = get_db(Utf8Path::new(path.as_str()))?;
-    let generator = listen_for_clips().await?;
-    let mut stream = GeneratorStream::new(generator);
+/// Only returns once clips stop coming, ie: the thread watching the clipboard died. That's an error
+/// so the daemon exits for the service manager to restart it rather than idle on
+async fn respond_to_clips(
+    state: &DaemonState,
+    config: &Arc<Mutex<Config>>,
+    mut clips: impl Stream<Item = ClipEntry> + Unpin,
+) -> Result<()> {
+    while let Some(clip) = clips.next().await {
+        if state.is_paused() {
+            debug!("Paused, ignoring clip");
+            continue;
+        }
+
+        // Read on every clip so edits to the config apply without a restart
+        let config = config.lock().unwrap().clone();
+        // A full disk or a database that won't open only costs this clip, the next may fare better
+        if let Err(e) = handle_clip(state, &config, clip) {
+            error!("Failed to store clip: {e:#}");
+        }
+    }
+    Err(anyhow!("Stopped hearing about clips from the clipboard"))
+}
+
+/// Stores `clip` unless a filter excludes it. Sensitive clips are only kept in memory
+#[instrument(
+    skip_all,
+    fields(selection = %clip.selection, mime_type = clip.mime_type().unwrap_or_default())
+)]
+fn handle_clip(state: &DaemonState, config: &Config, mut clip: ClipEntry) -> Result<()> {
+    let clipboard = config.clipboard();
+
+    // Readers already leave out most of what's too large, this covers whatever they can't
+    for Oversized {
+        mime_type,
+        size,
+        limit,
+    } in SizeLimits::current(config).enforce(&mut clip)
+    {
+        warn!(
+            mime_type,
+            size, limit, "Leaving out a representation over the size limit"
+        );
+    }
+    if clip.payload.is_empty() {
+        debug!("Ignoring clip with nothing left to store");
+        return Ok(());
+    }
+
+    // A broken rule could be the one keeping a password out so nothing is stored until it's fixed
+    let filters = match Filters::new(&clipboard) {
+        Ok(filters) => filters,
+        Err(e) => {
+            error!("{e}. Ignoring clips until the config is fixed");
+            return Ok(());
+        },
+    };
+    if let Some(db_path) = config.db_path() {
+        if let Err(e) = state.switch_db(&db_path) {
+            error!(
+                "{e:#}. Storing clips in {} until the config is fixed",
+                state.db_path()
+            );
+        }
+    }
+
+    if clip.is_sensitive() {
+        // Kept out of the database so `store_clip` never gets to check it
+        if let Decision::Excluded(rule) = filters.check(&clip) {
+            debug!("Ignoring clip matching {rule}");
+            return Ok(());
+        }
+        clip.id = Some(reserve_id(&state.db())?);
+        debug!(id = clip.id, "Keeping sensitive clip in memory");
+        state.sensitive.insert(clip);
+        return Ok(());
+    }
+
+    store_clip(
+        &state.db(),
+        clip,
+        &filters,
+        clipboard.duplicates(),
+        clipboard.max_size.unwrap_or_default(),
+    )
+}
+
+/// Forgets sensitive clips as soon as they're past the configured TTL
+async fn expire_sensitive_clips(state: Arc<DaemonState>, config: Arc<Mutex<Config>>) {
+    let mut interval = time::interval(Duration::from_secs(1));
+
+    loop {
+        interval.tick().await;
+
+        let ttl = config.lock().unwrap().clipboard().sensitive_ttl();
+        state.sensitive.set_ttl(ttl);
+        state.sensitive.purge();
+    }
+}
 
-    while let Some(clip) = stream.next().await {
-        let tx = db.rw_transaction()?;
-        {
-            tx.insert(clip)?;
+/// Keeps the search index up to date every second so the changes it follows don't pile up
+async fn sync_search_index(state: Arc<DaemonState>) {
+    let mut interval = time::interval(Duration::from_secs(1));
 
-            // TODO: get these numbers from config
-            remove_duplicates(&db, 10)?;
-            ensure_db_size(&db, 100)?;
+    loop {
+        interval.tick().await;
+        state.sync_index();
+    }
+}
+
+/// Removes clips past the age and size limits of the config every minute
+async fn enforce_retention(state: Arc<DaemonState>, config: Arc<Mutex<Config>>) {
+    let mut interval = time::interval(Duration::from_secs(60));
+
+    loop {
+        interval.tick().await;
+
+        let retention = match Retention::new(&config.lock().unwrap().clipboard()) {
+            Ok(retention) if retention.is_empty() => continue,
+            Ok(retention) => retention,
+            Err(e) => {
+                warn!("{e}. Keeping every clip until the config is fixed");
+                continue;
+            },
+        };
+
+        match prune(&state.db(), &retention, false, false) {
+            Ok(expired) if !expired.is_empty() => info!("Pruned {} clips", expired.len()),
+            Ok(_) => (),
+            Err(e) => error!("Failed to prune clips: {e}"),
         }
-        tx.commit()?;
     }
-    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use clippy_daemon::ipc::{server::handle, Request, Response};
+    use shortcut_assert_fs::TmpFs;
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_shuts_down_while_idle() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let config = Arc::new(Mutex::new(Config::default()));
+        let state = Arc::new(DaemonState::open(
+            tf.path("test").as_str(),
+            Arc::clone(&config),
+        )?);
+        // Open but nothing is ever copied
+        let (_sender, receiver) = mpsc::unbounded_channel();
+
+        let stopping = Arc::clone(&state);
+        task::spawn(async move {
+            time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(handle(&stopping, Request::Shutdown), Response::Done);
+        });
+        let clips = UnboundedReceiverStream::new(receiver);
+        let stopped = time::timeout(
+            Duration::from_secs(5),
+            respond_until_shutdown(&state, &config, clips),
+        )
+        .await;
+
+        assert!(stopped.is_ok_and(|result| result.is_ok()));
+        Ok(())
+    }
 }
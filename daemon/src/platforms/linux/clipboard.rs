@@ -1,77 +1,228 @@
-use std::io::Read;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
-use anyhow::Result;
-use genawaiter::{sync::gen, yield_, Generator};
+use anyhow::{Context, Result};
 use tokio::{
-    time,
-    time::{Duration, Interval},
+    sync::mpsc::{self, UnboundedSender},
+    task,
+    time::{self, Instant, Interval},
 };
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{error, info, instrument, warn};
 use wl_clipboard_rs::paste::{
-    get_contents as get_clip_wayland, ClipboardType, MimeType as WaylandMimeType, Seat,
+    get_contents as get_clip_wayland, get_mime_types as get_mime_types_wayland, ClipboardType,
+    MimeType as WaylandMimeType, Seat,
 };
 use x11_clipboard::Clipboard as X11Clipboard;
 
-use super::{detect_window_manager, WindowManager as WM};
-use crate::database::ClipEntry;
+use super::{
+    data_control::{read_limited, SelectionWatcher},
+    detect_window_manager,
+    xfixes::X11SelectionWatcher,
+    WindowManager as WM,
+};
+use crate::{
+    database::{ClipEntry, Payload, Selection},
+    utils::{config::Config, limits::SizeLimits, mime::is_meta_target},
+};
 
-#[inline]
-fn get_interval() -> Interval {
-    time::interval(Duration::from_secs(1))
+fn listen_for_clips_x11(watcher: X11SelectionWatcher, sender: UnboundedSender<ClipEntry>) {
+    thread::spawn(move || {
+        if let Err(e) = watcher.run(sender) {
+            error!("Stopped watching the X11 clipboard: {e}");
+        }
+    });
 }
 
-async fn listen_for_clips_x11() -> impl Generator<Yield = ClipEntry, Return = ()> {
-    let client = X11Clipboard::new().expect("Failed to initialize X11 clipboard");
-    let mut interval = get_interval();
-    let timeout = std::time::Duration::from_secs(3);
-    let mut previous_content = Vec::<u8>::new();
-
-    gen!({
-        let maybe_clip = client.load(
-            client.setter.atoms.clipboard,
-            client.setter.atoms.utf8_string,
-            client.setter.atoms.property,
-            timeout,
-        );
-
-        if let Ok(contents) = maybe_clip {
-            let new_contents = contents;
-            if new_contents != previous_content {
-                yield_!(ClipEntry::new(previous_content.as_slice()));
-                previous_content = new_contents;
-            }
-        }
+/// Picks up a polling rate changed in the config from the next tick on
+fn follow_polling_rate(interval: &mut Interval, polling_rate: Duration) {
+    if interval.period() != polling_rate {
+        *interval = time::interval_at(Instant::now() + polling_rate, polling_rate);
+    }
+}
 
+/// Fallback for X servers without the XFixes extension. Reads block so they're done off the
+/// runtime
+async fn poll_for_clips_x11(
+    client: X11Clipboard,
+    selections: Vec<Selection>,
+    config: Arc<Mutex<Config>>,
+    sender: UnboundedSender<ClipEntry>,
+) {
+    let client = Arc::new(client);
+    let mut previous_contents = HashMap::<Selection, Vec<u8>>::new();
+    let mut interval = time::interval(config.lock().unwrap().polling_rate());
+
+    while !sender.is_closed() {
         interval.tick().await;
-    })
+        let (polling_rate, timeout) = {
+            let config = config.lock().unwrap();
+            (config.polling_rate(), config.timeout_rate())
+        };
+        follow_polling_rate(&mut interval, polling_rate);
+
+        let (client, watched) = (Arc::clone(&client), selections.clone());
+        let read = task::spawn_blocking(move || {
+            watched
+                .into_iter()
+                .filter_map(|selection| {
+                    let atom = match selection {
+                        Selection::Clipboard => client.setter.atoms.clipboard,
+                        Selection::Primary => client.setter.atoms.primary,
+                    };
+                    let contents = client.load(
+                        atom,
+                        client.setter.atoms.utf8_string,
+                        client.setter.atoms.property,
+                        timeout,
+                    );
+                    Some((selection, contents.ok()?))
+                })
+                .collect::<Vec<_>>()
+        });
+        let read = match read.await {
+            Ok(read) => read,
+            Err(e) => {
+                error!("Failed to poll the clipboard: {e}");
+                continue;
+            },
+        };
+
+        for (selection, contents) in read {
+            let previous = previous_contents.entry(selection).or_default();
+            if contents != *previous {
+                previous.clone_from(&contents);
+                let payload = Payload::from([("UTF8_STRING".to_string(), contents)]);
+                let _ = sender.send(ClipEntry::with_payload(payload, selection));
+            }
+        }
+    }
 }
 
-async fn listen_for_clips_wayland() -> impl Generator<Yield = ClipEntry, Return = ()> {
-    let mut interval = get_interval();
-    let mut previous_content = Vec::<u8>::new();
+/// Reads every representation currently offered on a Wayland selection.
+#[instrument(level = "trace", skip(limits))]
+fn read_offer_wayland(
+    selection: Selection,
+    limits: &SizeLimits,
+    timeout: Duration,
+) -> Option<Payload> {
+    let clipboard = match selection {
+        Selection::Clipboard => ClipboardType::Regular,
+        Selection::Primary => ClipboardType::Primary,
+    };
+    let mime_types = get_mime_types_wayland(clipboard, Seat::Unspecified).ok()?;
+    let mut payload = Payload::new();
 
-    gen!({
-        if let Ok((mut pipe, _)) = get_clip_wayland(
-            ClipboardType::Regular,
+    for mime_type in mime_types.into_iter().filter(|mime_type| !is_meta_target(mime_type)) {
+        let Ok((pipe, _)) = get_clip_wayland(
+            clipboard,
             Seat::Unspecified,
-            WaylandMimeType::Any,
-        ) {
-            let mut new_content = Vec::<u8>::new();
-            if let Ok(_) = pipe.read_exact(&mut new_content) {
-                if new_content != previous_content {
-                    yield_!(ClipEntry::new(previous_content.as_slice()));
-                    previous_content = new_content;
-                }
-            }
+            WaylandMimeType::Specific(&mime_type),
+        ) else {
+            continue;
+        };
+
+        if let Some(content) = read_limited(pipe, &mime_type, limits.limit(&mime_type), timeout) {
+            payload.insert(mime_type, content);
+        }
+    }
+
+    (!payload.is_empty()).then_some(payload)
+}
+
+fn listen_for_clips_wayland(watcher: SelectionWatcher, sender: UnboundedSender<ClipEntry>) {
+    thread::spawn(move || {
+        if let Err(e) = watcher.run(sender) {
+            error!("Stopped watching the Wayland clipboard: {e}");
         }
+    });
+}
+
+/// Fallback for compositors that don't implement wlr-data-control. Reads block so they're done
+/// off the runtime
+async fn poll_for_clips_wayland(
+    selections: Vec<Selection>,
+    config: Arc<Mutex<Config>>,
+    sender: UnboundedSender<ClipEntry>,
+) {
+    let mut previous_payloads = HashMap::<Selection, Payload>::new();
+    let mut interval = time::interval(config.lock().unwrap().polling_rate());
 
+    while !sender.is_closed() {
         interval.tick().await;
-    })
+        let (polling_rate, timeout, limits) = {
+            let config = config.lock().unwrap();
+            (
+                config.polling_rate(),
+                config.timeout_rate(),
+                SizeLimits::current(&config),
+            )
+        };
+        follow_polling_rate(&mut interval, polling_rate);
+
+        let watched = selections.clone();
+        let read = task::spawn_blocking(move || {
+            watched
+                .into_iter()
+                .filter_map(|selection| {
+                    Some((selection, read_offer_wayland(selection, &limits, timeout)?))
+                })
+                .collect::<Vec<_>>()
+        });
+        let read = match read.await {
+            Ok(read) => read,
+            Err(e) => {
+                error!("Failed to poll the clipboard: {e}");
+                continue;
+            },
+        };
+
+        for (selection, payload) in read {
+            let previous = previous_payloads.entry(selection).or_default();
+            if payload != *previous {
+                previous.clone_from(&payload);
+                let _ = sender.send(ClipEntry::with_payload(payload, selection));
+            }
+        }
+    }
 }
 
-pub async fn listen_for_clips() -> Result<Box<dyn Generator<Yield = ClipEntry, Return = ()>>> {
-    match detect_window_manager() {
-        Ok(WM::Wayland) => Ok(Box::new(listen_for_clips_wayland().await)),
-        Ok(WM::X11) => Ok(Box::new(listen_for_clips_x11().await)),
-        Err(e) => Err(e),
+/// Clips as they're copied. Watchers run on threads of their own and polling on a task, either
+/// stops once the stream is dropped
+pub fn listen_for_clips(
+    selections: Vec<Selection>,
+    config: Arc<Mutex<Config>>,
+) -> Result<UnboundedReceiverStream<ClipEntry>> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    match detect_window_manager()? {
+        WM::Wayland => match SelectionWatcher::connect(&selections, Arc::clone(&config)) {
+            Ok(watcher) => {
+                info!(?selections, "Watching the Wayland clipboard");
+                listen_for_clips_wayland(watcher, sender);
+            },
+            Err(e) => {
+                warn!("{e}. Falling back to polling the clipboard");
+                task::spawn(poll_for_clips_wayland(selections, config, sender));
+            },
+        },
+        WM::X11 => match X11SelectionWatcher::connect(&selections, Arc::clone(&config)) {
+            Ok(watcher) => {
+                info!(?selections, "Watching the X11 clipboard");
+                listen_for_clips_x11(watcher, sender);
+            },
+            Err(e) => {
+                warn!("{e}. Falling back to polling the clipboard");
+                let client =
+                    X11Clipboard::new().context("Failed to initialize the X11 clipboard")?;
+                task::spawn(poll_for_clips_x11(client, selections, config, sender));
+            },
+        },
     }
+
+    Ok(UnboundedReceiverStream::new(receiver))
 }
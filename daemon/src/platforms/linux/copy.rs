@@ -0,0 +1,368 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    os::fd::{AsRawFd, RawFd},
+};
+
+use anyhow::{anyhow, Result};
+use wl_clipboard_rs::copy::{
+    ClipboardType, MimeSource, MimeType as WaylandMimeType, Options, Source,
+};
+use x11rb::{
+    connection::{Connection as X11Connection, RequestConnection},
+    protocol::{
+        xproto::{
+            Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt, CreateWindowAux, EventMask,
+            PropMode, Property, SelectionNotifyEvent, SelectionRequestEvent, Timestamp, Window,
+            WindowClass, SELECTION_NOTIFY_EVENT,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as WrapperConnectionExt,
+    COPY_DEPTH_FROM_PARENT, NONE,
+};
+
+use super::{detect_window_manager, WindowManager as WM};
+use crate::{
+    database::{Payload, Selection},
+    utils::mime::{is_text, TEXT_MIME_TYPES},
+};
+
+/// Makes `payload` the contents of `selection`, offered in every MIME type it was copied in.
+///
+/// Like `wl-copy` and `xclip` a background process keeps serving the contents until something
+/// else takes over the selection, so this returns right away.
+pub fn set_clipboard(payload: &Payload, selection: Selection) -> Result<()> {
+    // Taken before connecting so the connection isn't among them
+    let inherited = open_fds()?;
+
+    match detect_window_manager()? {
+        WM::Wayland => set_clipboard_wayland(payload, selection, &inherited),
+        WM::X11 => set_clipboard_x11(payload, selection, &inherited),
+    }
+}
+
+fn set_clipboard_wayland(
+    payload: &Payload,
+    selection: Selection,
+    inherited: &[RawFd],
+) -> Result<()> {
+    let mut options = Options::new();
+    options
+        .clipboard(match selection {
+            Selection::Clipboard => ClipboardType::Regular,
+            Selection::Primary => ClipboardType::Primary,
+        })
+        .foreground(true);
+
+    let sources = payload
+        .iter()
+        .map(|(mime_type, content)| MimeSource {
+            source: Source::Bytes(content.clone().into_boxed_slice()),
+            mime_type: WaylandMimeType::Specific(mime_type.clone()),
+        })
+        .collect();
+    // Prepared before forking so errors still reach the caller
+    let prepared = options.prepare_copy_multi(sources)?;
+
+    serve_in_background(inherited, move || prepared.serve().map_err(Into::into))
+}
+
+fn set_clipboard_x11(payload: &Payload, selection: Selection, inherited: &[RawFd]) -> Result<()> {
+    let owner = X11SelectionOwner::acquire(payload, selection)?;
+
+    serve_in_background(inherited, move || owner.serve())
+}
+
+/// File descriptors past `stderr` this process has open, ie: the database
+fn open_fds() -> Result<Vec<RawFd>> {
+    let fds = fs::read_dir("/proc/self/fd")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<RawFd>().ok())
+        .collect::<Vec<_>>();
+
+    // Leaves out the one the listing was read through, it's closed by now
+    // SAFETY: F_GETFD only looks the descriptor up
+    Ok(fds
+        .into_iter()
+        .filter(|&fd| fd > 2 && unsafe { libc::fcntl(fd, libc::F_GETFD) } != -1)
+        .collect())
+}
+
+/// Runs `serve` in a forked child so it outlives this process.
+///
+/// Like `wl-copy`, the child starts a session of its own and lets go of the `inherited` file
+/// descriptors and of our `stdin`, `stdout` and `stderr`. Otherwise `$(clippy recall 3 --copy)`
+/// and pipelines would wait on it until the selection changes, Ctrl-C would stop it and it would
+/// keep the database locked.
+fn serve_in_background(inherited: &[RawFd], serve: impl FnOnce() -> Result<()>) -> Result<()> {
+    // Only the forking thread lives on in the child, any lock another thread held stays locked
+    // for good. Past fork only async-signal-safe calls are made until `serve` runs
+    if fs::read_dir("/proc/self/task")?.count() != 1 {
+        return Err(anyhow!(
+            "Can't serve the clipboard from a multi-threaded process"
+        ));
+    }
+    let null = File::options().read(true).write(true).open("/dev/null")?;
+
+    // SAFETY: this process is single threaded, checked above
+    match unsafe { libc::fork() } {
+        -1 => Err(anyhow!("Failed to fork: {}", io::Error::last_os_error())),
+        0 => {
+            // SAFETY: setsid, dup2 and close are async-signal-safe and only touch descriptors
+            unsafe {
+                libc::setsid();
+                for fd in 0..=2 {
+                    libc::dup2(null.as_raw_fd(), fd);
+                }
+                for &fd in inherited {
+                    libc::close(fd);
+                }
+            }
+            drop(null);
+
+            let code = serve().is_err() as i32;
+            std::process::exit(code);
+        },
+        _ => Ok(()),
+    }
+}
+
+/// A transfer too large for a single request, sent in chunks as the requestor deletes each one
+struct IncrTransfer {
+    target: Atom,
+    content: Vec<u8>,
+    offset: usize,
+}
+
+struct X11SelectionOwner {
+    connection: RustConnection,
+    selection: Atom,
+    targets: Atom,
+    incr: Atom,
+    /// Every target we answer for and its content
+    contents: HashMap<Atom, Vec<u8>>,
+    transfers: HashMap<(Window, Atom), IncrTransfer>,
+}
+
+impl X11SelectionOwner {
+    fn acquire(payload: &Payload, selection: Selection) -> Result<Self> {
+        let (connection, screen_num) = x11rb::connect(None)?;
+        let root = connection.setup().roots[screen_num].root;
+        let window = connection.generate_id()?;
+        connection.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+
+        let selection = match selection {
+            Selection::Clipboard => intern(&connection, "CLIPBOARD")?,
+            Selection::Primary => AtomEnum::PRIMARY.into(),
+        };
+        let mut contents = HashMap::new();
+
+        for (mime_type, content) in payload {
+            contents.insert(intern(&connection, mime_type)?, content.clone());
+        }
+
+        // X11 apps mostly ask for the legacy text targets so offer those too
+        if let Some(text) = payload.iter().find(|(mime_type, _)| is_text(mime_type)) {
+            for mime_type in TEXT_MIME_TYPES {
+                contents
+                    .entry(intern(&connection, mime_type)?)
+                    .or_insert_with(|| text.1.clone());
+            }
+        }
+
+        connection.set_selection_owner(window, selection, server_time(&connection, window)?)?;
+
+        if connection.get_selection_owner(selection)?.reply()?.owner != window {
+            return Err(anyhow!("Failed to take ownership of the selection"));
+        }
+
+        Ok(Self {
+            targets: intern(&connection, "TARGETS")?,
+            incr: intern(&connection, "INCR")?,
+            connection,
+            selection,
+            contents,
+            transfers: HashMap::new(),
+        })
+    }
+
+    /// Answers requests until another client takes over the selection
+    fn serve(mut self) -> Result<()> {
+        loop {
+            match self.connection.wait_for_event()? {
+                Event::SelectionClear(event) if event.selection == self.selection => return Ok(()),
+                Event::SelectionRequest(request) => self.answer(&request)?,
+                Event::PropertyNotify(event) if event.state == Property::DELETE =>
+                    self.continue_transfer(event.window, event.atom)?,
+                _ => (),
+            }
+        }
+    }
+
+    fn answer(&mut self, request: &SelectionRequestEvent) -> Result<()> {
+        // Obsolete clients leave out the property and expect the target to be used instead
+        let property = match request.property {
+            NONE => request.target,
+            property => property,
+        };
+        let max_chunk = self.connection.maximum_request_bytes() / 2;
+
+        let accepted = match request.target {
+            target if target == self.targets => {
+                let mut targets = self.contents.keys().copied().collect::<Vec<_>>();
+                targets.push(self.targets);
+                self.connection.change_property32(
+                    PropMode::REPLACE,
+                    request.requestor,
+                    property,
+                    AtomEnum::ATOM,
+                    &targets,
+                )?;
+                true
+            },
+            target => match self.contents.get(&target) {
+                Some(content) if content.len() > max_chunk => {
+                    self.connection.change_window_attributes(
+                        request.requestor,
+                        &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+                    )?;
+                    self.connection.change_property32(
+                        PropMode::REPLACE,
+                        request.requestor,
+                        property,
+                        self.incr,
+                        &[content.len() as u32],
+                    )?;
+                    self.transfers.insert(
+                        (request.requestor, property),
+                        IncrTransfer {
+                            target,
+                            content: content.clone(),
+                            offset: 0,
+                        },
+                    );
+                    true
+                },
+                Some(content) => {
+                    self.connection.change_property8(
+                        PropMode::REPLACE,
+                        request.requestor,
+                        property,
+                        target,
+                        content,
+                    )?;
+                    true
+                },
+                None => false,
+            },
+        };
+
+        self.connection.send_event(
+            false,
+            request.requestor,
+            EventMask::NO_EVENT,
+            SelectionNotifyEvent {
+                response_type: SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: request.time,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property: if accepted { property } else { NONE },
+            },
+        )?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    /// Sends the next chunk of an INCR transfer, ending with an empty one
+    fn continue_transfer(&mut self, requestor: Window, property: Atom) -> Result<()> {
+        let max_chunk = self.connection.maximum_request_bytes() / 2;
+        let Some(transfer) = self.transfers.get_mut(&(requestor, property)) else {
+            return Ok(());
+        };
+
+        let end = (transfer.offset + max_chunk).min(transfer.content.len());
+        self.connection.change_property8(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            transfer.target,
+            &transfer.content[transfer.offset..end],
+        )?;
+        self.connection.flush()?;
+
+        if transfer.offset == end {
+            self.transfers.remove(&(requestor, property));
+        } else {
+            transfer.offset = end;
+        }
+
+        Ok(())
+    }
+}
+
+/// The current time of the X server, which ICCCM asks selection owners to claim ownership with.
+///
+/// Appending nothing to a property of `window` still has the server report the change, along
+/// with its timestamp
+fn server_time(connection: &RustConnection, window: Window) -> Result<Timestamp> {
+    connection.change_property8(
+        PropMode::APPEND,
+        window,
+        AtomEnum::WM_NAME,
+        AtomEnum::STRING,
+        &[],
+    )?;
+    connection.flush()?;
+
+    loop {
+        if let Event::PropertyNotify(event) = connection.wait_for_event()? {
+            if event.window == window {
+                return Ok(event.time);
+            }
+        }
+    }
+}
+
+fn intern(connection: &RustConnection, name: &str) -> Result<Atom> {
+    Ok(connection.intern_atom(false, name.as_bytes())?.reply()?.atom)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+
+    #[test]
+    fn it_forks_only_single_threaded() -> Result<()> {
+        let file = File::open("/dev/null")?;
+        assert!(open_fds()?.contains(&file.as_raw_fd()));
+        assert!(!open_fds()?.contains(&0));
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let other = thread::spawn(move || stopped.recv());
+        let served = serve_in_background(&[], || unreachable!());
+        assert!(served.is_err_and(|e| e.to_string().contains("multi-threaded")));
+
+        drop(stop);
+        let _ = other.join();
+        Ok(())
+    }
+}
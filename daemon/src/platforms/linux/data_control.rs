@@ -0,0 +1,482 @@
+use std::{
+    collections::HashMap,
+    io::{self, pipe, ErrorKind, Read},
+    os::fd::{AsFd, AsRawFd},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::warn;
+use wayland_client::{
+    event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_registry::WlRegistry, wl_seat::WlSeat},
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+};
+
+use crate::{
+    database::{ClipEntry, Payload, Selection},
+    utils::{config::Config, limits::SizeLimits, mime::is_meta_target},
+};
+
+#[derive(Default)]
+struct State {
+    watched: Vec<Selection>,
+    /// MIME types announced for every offer we haven't handled yet
+    offers: HashMap<ZwlrDataControlOfferV1, Vec<String>>,
+    /// Newest offer of each watched selection that is waiting to be read
+    selections: HashMap<Selection, ZwlrDataControlOfferV1>,
+    finished: bool,
+}
+
+impl State {
+    fn discard(&mut self, offer: ZwlrDataControlOfferV1) {
+        self.offers.remove(&offer);
+        offer.destroy();
+    }
+
+    fn select(&mut self, selection: Selection, offer: Option<ZwlrDataControlOfferV1>) {
+        // A selection we never got around to reading has already been replaced
+        let stale = match offer {
+            Some(offer) if self.watched.contains(&selection) =>
+                self.selections.insert(selection, offer),
+            Some(offer) => Some(offer),
+            None => self.selections.remove(&selection),
+        };
+
+        if let Some(stale) = stale {
+            self.discard(stale);
+        }
+    }
+}
+
+/// Watches the Wayland clipboard through the wlr-data-control protocol.
+///
+/// The compositor pushes a new offer every time the selection changes so there's no polling and
+/// no copy is missed no matter how quickly they happen.
+pub struct SelectionWatcher {
+    connection: Connection,
+    queue: EventQueue<State>,
+    state: State,
+    /// Read for the size limits on every offer so edits apply without a restart
+    config: Arc<Mutex<Config>>,
+    _device: ZwlrDataControlDeviceV1,
+}
+
+impl SelectionWatcher {
+    /// Fails if the compositor doesn't implement wlr-data-control
+    ///
+    /// Only changes to `selections` are reported. Primary selection support depends on the
+    /// compositor offering version 2 of the protocol.
+    pub fn connect(selections: &[Selection], config: Arc<Mutex<Config>>) -> Result<Self> {
+        Self::with_connection(Connection::connect_to_env()?, selections, config)
+    }
+
+    fn with_connection(
+        connection: Connection,
+        selections: &[Selection],
+        config: Arc<Mutex<Config>>,
+    ) -> Result<Self> {
+        let (globals, queue) = registry_queue_init::<State>(&connection)?;
+        let qh = queue.handle();
+
+        let manager: ZwlrDataControlManagerV1 = globals
+            .bind(&qh, 1..=2, ())
+            .map_err(|e| anyhow!("Compositor does not support wlr-data-control: {e}"))?;
+        let seat: WlSeat = globals.bind(&qh, 1..=1, ())?;
+        let device = manager.get_data_device(&seat, &qh, ());
+
+        Ok(Self {
+            connection,
+            queue,
+            state: State {
+                watched: selections.to_vec(),
+                ..Default::default()
+            },
+            config,
+            _device: device,
+        })
+    }
+
+    /// Sends the contents of every new selection until `sender` hangs up.
+    pub fn run(mut self, sender: UnboundedSender<ClipEntry>) -> Result<()> {
+        loop {
+            self.queue.blocking_dispatch(&mut self.state)?;
+
+            if self.state.finished {
+                return Err(anyhow!("Compositor invalidated the data control device"));
+            }
+
+            let ready = self.state.selections.drain().collect::<Vec<_>>();
+
+            for (selection, offer) in ready {
+                let mime_types = self.state.offers.remove(&offer).unwrap_or_default();
+                let payload = self.read_offer(&offer, mime_types);
+                offer.destroy();
+
+                if !payload.is_empty()
+                    && sender.send(ClipEntry::with_payload(payload, selection)).is_err()
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn read_offer(&self, offer: &ZwlrDataControlOfferV1, mime_types: Vec<String>) -> Payload {
+        let (limits, timeout) = {
+            let config = self.config.lock().unwrap();
+            (SizeLimits::current(&config), config.timeout_rate())
+        };
+
+        mime_types
+            .into_iter()
+            .filter(|mime_type| !is_meta_target(mime_type))
+            .filter_map(|mime_type| {
+                let (reader, writer) = pipe().ok()?;
+                offer.receive(mime_type.clone(), writer.as_fd());
+                // Our end of the write side has to be closed or we'll never see EOF
+                drop(writer);
+                self.connection.flush().ok()?;
+
+                read_limited(reader, &mime_type, limits.limit(&mime_type), timeout)
+                    .map(|content| (mime_type, content))
+            })
+            .collect()
+    }
+}
+
+/// Reads at most `limit` bytes of `mime_type`, discarding the read entirely if the source had
+/// more to give or stopped writing for `timeout` without closing its end.
+pub fn read_limited(
+    source: impl Read + AsFd,
+    mime_type: &str,
+    limit: usize,
+    timeout: Duration,
+) -> Option<Vec<u8>> {
+    let mut content = Vec::<u8>::new();
+    let source = Timeout { source, timeout };
+
+    if let Err(err) = source.take(limit as u64 + 1).read_to_end(&mut content) {
+        warn!(mime_type, "Leaving out a representation: {err}");
+        return None;
+    }
+
+    if content.len() > limit {
+        warn!(
+            mime_type,
+            limit, "Leaving out a representation over the size limit"
+        );
+        return None;
+    }
+    (!content.is_empty()).then_some(content)
+}
+
+/// Reads that fail once `source` goes `timeout` without anything to read, a source that never
+/// closes its end of the pipe would otherwise block the watcher for good
+struct Timeout<R> {
+    source: R,
+    timeout: Duration,
+}
+
+impl<R: Read + AsFd> Read for Timeout<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fd = libc::pollfd {
+            fd: self.source.as_fd().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = self.timeout.as_millis().try_into().unwrap_or(libc::c_int::MAX);
+
+        // SAFETY: `fd` is a single valid pollfd that outlives the call
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            -1 => Err(io::Error::last_os_error()),
+            0 => Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "source stopped writing",
+            )),
+            _ => self.source.read(buf),
+        }
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as wayland_client::Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlSeat, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlSeat,
+        _: <WlSeat as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrDataControlManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrDataControlManagerV1,
+        _: <ZwlrDataControlManagerV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for State {
+    event_created_child!(State, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+
+    fn event(
+        state: &mut Self,
+        _: &ZwlrDataControlDeviceV1,
+        event: <ZwlrDataControlDeviceV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_device_v1::Event::DataOffer { id } => {
+                state.offers.insert(id, Vec::new());
+            },
+            zwlr_data_control_device_v1::Event::Selection { id } => {
+                state.select(Selection::Clipboard, id);
+            },
+            zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+                state.select(Selection::Primary, id);
+            },
+            zwlr_data_control_device_v1::Event::Finished => state.finished = true,
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        offer: &ZwlrDataControlOfferV1,
+        event: <ZwlrDataControlOfferV1 as wayland_client::Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state.offers.entry(offer.clone()).or_default().push(mime_type);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{PipeReader, Write},
+        mem,
+        os::{
+            fd::{FromRawFd, OwnedFd, RawFd},
+            unix::net::UnixStream,
+        },
+        thread,
+    };
+
+    use pretty_assertions::assert_eq;
+    use tokio::{sync::mpsc, time};
+
+    use super::*;
+
+    /// First id compositors hand out to objects they create
+    const SERVER_ID: u32 = 0xff00_0000;
+
+    enum Arg<'a> {
+        Uint(u32),
+        Str(&'a str),
+    }
+
+    /// Just enough of a compositor to offer a single selection, spoken in the wire format
+    struct Compositor {
+        socket: UnixStream,
+        received: Vec<u8>,
+        fds: Vec<OwnedFd>,
+    }
+
+    impl Compositor {
+        fn send(&mut self, object: u32, opcode: u16, args: &[Arg]) {
+            let mut body = Vec::new();
+            for arg in args {
+                match arg {
+                    Arg::Uint(value) => body.extend(value.to_ne_bytes()),
+                    Arg::Str(value) => {
+                        body.extend((value.len() as u32 + 1).to_ne_bytes());
+                        body.extend(value.as_bytes());
+                        body.resize(body.len() + 4 - value.len() % 4, 0);
+                    },
+                }
+            }
+            let size = (body.len() as u32 + 8) << 16 | u32::from(opcode);
+
+            self.socket.write_all(&object.to_ne_bytes()).unwrap();
+            self.socket.write_all(&size.to_ne_bytes()).unwrap();
+            self.socket.write_all(&body).unwrap();
+        }
+
+        /// Object, opcode and arguments of the next request
+        fn request(&mut self) -> (u32, u16, Vec<u32>) {
+            let word = |bytes: &[u8], i: usize| {
+                u32::from_ne_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap())
+            };
+            while self.received.len() < 8
+                || self.received.len() < (word(&self.received, 1) >> 16) as usize
+            {
+                self.receive();
+            }
+            let size = (word(&self.received, 1) >> 16) as usize;
+            let message = self.received.drain(..size).collect::<Vec<_>>();
+            let args = (2..size / 4).map(|i| word(&message, i)).collect();
+
+            (word(&message, 0), word(&message, 1) as u16, args)
+        }
+
+        /// Reads whatever the client sent along with any file descriptors passed with it
+        fn receive(&mut self) {
+            let mut data = [0_u8; 4096];
+            let mut control = [0_u64; 32];
+            let mut iov = libc::iovec {
+                iov_base: data.as_mut_ptr().cast(),
+                iov_len: data.len(),
+            };
+            // SAFETY: a zeroed msghdr is valid, its buffers are filled in right after
+            let mut message: libc::msghdr = unsafe { mem::zeroed() };
+            message.msg_iov = &mut iov;
+            message.msg_iovlen = 1;
+            message.msg_control = control.as_mut_ptr().cast();
+            message.msg_controllen = mem::size_of_val(&control) as _;
+
+            // SAFETY: every buffer in `message` outlives the call
+            let read = unsafe { libc::recvmsg(self.socket.as_raw_fd(), &mut message, 0) };
+            assert!(read > 0, "Client hung up");
+            self.received.extend(&data[..read as usize]);
+
+            // SAFETY: the headers are walked within `msg_controllen` as the kernel filled them in
+            unsafe {
+                let mut header = libc::CMSG_FIRSTHDR(&message);
+                while !header.is_null() {
+                    if (*header).cmsg_type == libc::SCM_RIGHTS {
+                        let fds = libc::CMSG_DATA(header).cast::<RawFd>();
+                        let count = ((*header).cmsg_len as usize - libc::CMSG_LEN(0) as usize)
+                            / mem::size_of::<RawFd>();
+                        for i in 0..count {
+                            self.fds.push(OwnedFd::from_raw_fd(fds.add(i).read_unaligned()));
+                        }
+                    }
+                    header = libc::CMSG_NXTHDR(&message, header);
+                }
+            }
+        }
+    }
+
+    fn piped(bytes: &[u8]) -> PipeReader {
+        let (reader, mut writer) = pipe().unwrap();
+        writer.write_all(bytes).unwrap();
+        reader
+    }
+
+    #[test]
+    fn it_reads_within_limit() {
+        let timeout = Duration::from_secs(1);
+
+        assert_eq!(
+            read_limited(piped(b"asdf"), "text/plain", 4, timeout),
+            Some(b"asdf".to_vec())
+        );
+        assert_eq!(read_limited(piped(b"asdf"), "text/plain", 3, timeout), None);
+        assert_eq!(read_limited(piped(b""), "text/plain", 4, timeout), None);
+
+        // Never closed by the source
+        let (reader, mut writer) = pipe().unwrap();
+        writer.write_all(b"as").unwrap();
+        assert_eq!(
+            read_limited(reader, "text/plain", 4, Duration::from_millis(50)),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reads_offered_selections() -> Result<()> {
+        let (client, server) = UnixStream::pair()?;
+        let mut compositor = Compositor {
+            socket: server,
+            received: Vec::new(),
+            fds: Vec::new(),
+        };
+        let config = Config {
+            timeout_rate: Some(100),
+            ..Config::default()
+        };
+
+        // The registry and the callback of the roundtrip listing globals are objects 2 and 3
+        compositor.send(
+            2,
+            0,
+            &[Arg::Uint(1), Arg::Str("zwlr_data_control_manager_v1"), Arg::Uint(2)],
+        );
+        compositor.send(2, 0, &[Arg::Uint(2), Arg::Str("wl_seat"), Arg::Uint(1)]);
+        compositor.send(3, 0, &[Arg::Uint(0)]);
+        let watcher = SelectionWatcher::with_connection(
+            Connection::from_socket(client)?,
+            &[Selection::Clipboard],
+            Arc::new(Mutex::new(config)),
+        )?;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        thread::spawn(move || watcher.run(sender));
+
+        // Past getting the registry and its roundtrip, binding the manager and the seat
+        let requests = (0..5).map(|_| compositor.request()).collect::<Vec<_>>();
+        let objects = requests.iter().map(|(object, opcode, _)| (*object, *opcode));
+        assert_eq!(
+            objects.collect::<Vec<_>>(),
+            [(1, 1), (1, 0), (2, 0), (2, 0), (4, 1)]
+        );
+        let device = requests[4].2[0];
+
+        compositor.send(device, 0, &[Arg::Uint(SERVER_ID)]);
+        compositor.send(SERVER_ID, 0, &[Arg::Str("text/plain")]);
+        compositor.send(SERVER_ID, 0, &[Arg::Str("text/html")]);
+        compositor.send(device, 1, &[Arg::Uint(SERVER_ID)]);
+
+        let (offer, opcode, _) = compositor.request();
+        assert_eq!((offer, opcode), (SERVER_ID, 0));
+        std::fs::File::from(compositor.fds.remove(0)).write_all(b"hi")?;
+
+        // A source that never closes its end only costs its representation
+        let (offer, opcode, _) = compositor.request();
+        assert_eq!((offer, opcode), (SERVER_ID, 0));
+        let clip = time::timeout(Duration::from_secs(5), receiver.recv()).await?.unwrap();
+
+        assert_eq!(clip.selection, Selection::Clipboard);
+        assert_eq!(
+            clip.payload,
+            Payload::from([("text/plain".to_string(), b"hi".to_vec())])
+        );
+        Ok(())
+    }
+}
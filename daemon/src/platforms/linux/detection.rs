@@ -4,6 +4,7 @@ use anyhow::{anyhow, Result};
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 use regex::{Regex, RegexBuilder};
+use tracing::debug;
 use x11rb::{
     connection::Connection as X11Connection,
     protocol::xproto::{
@@ -22,13 +23,17 @@ fn get_active_window_sway() -> Result<String> {
         return Err(anyhow!("Failed to query sway IPC"));
     }
 
-    Ok(std::str::from_utf8(&output.stdout)?
-        .split("\"focused\":true,")
+    focused_sway_window(std::str::from_utf8(&output.stdout)?)
+        .ok_or_else(|| anyhow!("No focused window in the sway tree"))
+}
+
+/// Name of the focused window in the output of `swaymsg -t get_tree`
+fn focused_sway_window(tree: &str) -> Option<String> {
+    tree.split("\"focused\":true,")
         .nth(1)
         .and_then(|s| s.split("\"name\":\"").nth(1))
         .and_then(|s| s.split('"').next())
-        .unwrap()
-        .to_string())
+        .map(str::to_string)
 }
 
 fn get_active_window_gnome() -> Result<String> {
@@ -54,7 +59,7 @@ fn get_active_window_kde() -> Result<String> {
 }
 
 fn get_active_x11_window() -> Result<String> {
-    let (ref conn, _) = x11rb::connect(None).expect("Failed to connect to the X server");
+    let (ref conn, _) = x11rb::connect(None)?;
 
     let active_window: X11Window = get_property_x11(
         conn,
@@ -67,10 +72,8 @@ fn get_active_x11_window() -> Result<String> {
     )?
     .reply()?
     .value32()
-    .ok_or("Failed to get active window")
-    .unwrap()
-    .next()
-    .unwrap();
+    .and_then(|mut windows| windows.next())
+    .ok_or_else(|| anyhow!("Failed to get active window"))?;
 
     let window_name = get_property_x11(
         conn,
@@ -133,7 +136,9 @@ pub fn detect_window_manager() -> Result<WindowManager> {
     } else if std::env::var_os("DISPLAY").is_some() {
         Ok(WindowManager::X11)
     } else {
-        Err(anyhow!("Unable to determine "))
+        Err(anyhow!(
+            "Unable to determine the display server, neither WAYLAND_DISPLAY nor DISPLAY is set"
+        ))
     }
 }
 
@@ -145,15 +150,16 @@ pub fn get_active_wayland_window() -> Option<String> {
         Some(Compositor::Sway) => get_active_window_sway(),
         None => Err(anyhow!("Unable to determine compositor")),
     };
-    // TODO: need to log this instead of ignoring it.
 
-    compositor.ok()
+    compositor.inspect_err(|e| debug!("Clip won't have an application: {e}")).ok()
 }
 
 pub fn get_active_window_title() -> Option<String> {
     match detect_window_manager() {
         Ok(WindowManager::Wayland) => get_active_wayland_window(),
-        Ok(WindowManager::X11) => get_active_x11_window().ok(),
+        Ok(WindowManager::X11) => get_active_x11_window()
+            .inspect_err(|e| debug!("Clip won't have an application: {e}"))
+            .ok(),
         Err(_) => None,
     }
 }
@@ -177,4 +183,16 @@ mod test {
         env::set_var("XDG_CURRENT_DESKTOP", "no compositor");
         assert!(detect_wayland_compositor().is_none())
     }
+
+    #[test]
+    fn it_finds_the_focused_sway_window() {
+        assert_eq!(
+            focused_sway_window(r#"{"focused":false,"nodes":[{"focused":true,"name":"vim"}]}"#),
+            Some("vim".to_string())
+        );
+        assert_eq!(
+            focused_sway_window(r#"{"focused":false,"name":"root"}"#),
+            None
+        );
+    }
 }
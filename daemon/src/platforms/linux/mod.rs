@@ -1,7 +1,11 @@
 mod clipboard;
+mod copy;
+mod data_control;
 mod detection;
+mod xfixes;
 
 pub use clipboard::listen_for_clips;
+pub use copy::set_clipboard;
 use derive_more::Display;
 use detection::detect_window_manager;
 pub use detection::get_active_window_title;
@@ -0,0 +1,387 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::warn;
+use x11rb::{
+    connection::Connection as X11Connection,
+    protocol::{
+        xfixes::{self, ConnectionExt as XFixesConnectionExt, SelectionEventMask},
+        xproto::{
+            Atom, AtomEnum, ConnectionExt, CreateWindowAux, EventMask, Property,
+            SelectionNotifyEvent, Timestamp, Window, WindowClass,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+    COPY_DEPTH_FROM_PARENT, NONE,
+};
+
+use crate::{
+    database::{ClipEntry, Payload, Selection},
+    utils::{config::Config, limits::SizeLimits, mime::is_meta_target},
+};
+
+#[derive(Clone, Copy)]
+struct Atoms {
+    clipboard: Atom,
+    targets: Atom,
+    incr: Atom,
+    property: Atom,
+}
+
+/// Watches X11 selections through XFixes selection notifications.
+///
+/// Every owner change is reported by the server so nothing is polled. All offered targets are
+/// fetched, including INCR transfers used for large payloads.
+pub struct X11SelectionWatcher {
+    connection: RustConnection,
+    window: Window,
+    atoms: Atoms,
+    watched: Vec<(Atom, Selection)>,
+    /// Owner changes that arrived while we were busy reading a previous selection
+    pending: VecDeque<xfixes::SelectionNotifyEvent>,
+    /// Read for the size limits and the timeout on every selection so edits apply without a
+    /// restart
+    config: Arc<Mutex<Config>>,
+}
+
+impl X11SelectionWatcher {
+    /// Fails if the X server can't be reached or doesn't support XFixes
+    pub fn connect(selections: &[Selection], config: Arc<Mutex<Config>>) -> Result<Self> {
+        let (connection, screen_num) = x11rb::connect(None)?;
+        let root = connection.setup().roots[screen_num].root;
+
+        connection
+            .xfixes_query_version(5, 0)?
+            .reply()
+            .map_err(|e| anyhow!("X server does not support XFixes: {e}"))?;
+
+        let atoms = Atoms {
+            clipboard: intern(&connection, b"CLIPBOARD")?,
+            targets: intern(&connection, b"TARGETS")?,
+            incr: intern(&connection, b"INCR")?,
+            property: intern(&connection, b"CLIPPY_SELECTION")?,
+        };
+
+        // Selection conversions need a window to deliver their results to
+        let window = connection.generate_id()?;
+        connection.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+        let watched = selections
+            .iter()
+            .map(|selection| match selection {
+                Selection::Clipboard => (atoms.clipboard, *selection),
+                Selection::Primary => (AtomEnum::PRIMARY.into(), *selection),
+            })
+            .collect::<Vec<_>>();
+
+        for (atom, _) in &watched {
+            connection.xfixes_select_selection_input(
+                window,
+                *atom,
+                SelectionEventMask::SET_SELECTION_OWNER,
+            )?;
+        }
+        connection.flush()?;
+
+        Ok(Self {
+            connection,
+            window,
+            atoms,
+            watched,
+            pending: VecDeque::new(),
+            config,
+        })
+    }
+
+    /// Sends the contents of every new selection until `sender` hangs up.
+    pub fn run(mut self, sender: UnboundedSender<ClipEntry>) -> Result<()> {
+        loop {
+            let event = match self.pending.pop_front() {
+                Some(event) => event,
+                None => match self.connection.wait_for_event()? {
+                    Event::XfixesSelectionNotify(event) => event,
+                    _ => continue,
+                },
+            };
+
+            let Some((_, selection)) =
+                self.watched.iter().find(|(atom, _)| *atom == event.selection)
+            else {
+                continue;
+            };
+
+            if event.owner == NONE || event.owner == self.window {
+                continue;
+            }
+
+            let selection = *selection;
+            // One owner misbehaving shouldn't stop us from hearing about the next
+            let payload = match self.read_selection(event.selection, event.timestamp) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!(?selection, "Couldn't read the selection: {e}");
+                    continue;
+                },
+            };
+
+            if !payload.is_empty()
+                && sender.send(ClipEntry::with_payload(payload, selection)).is_err()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    fn read_selection(&mut self, selection: Atom, time: Timestamp) -> Result<Payload> {
+        let Some(targets) =
+            self.convert(selection, self.atoms.targets, "TARGETS", time, usize::MAX)?
+        else {
+            return Ok(Payload::new());
+        };
+        let limits = SizeLimits::current(&self.config.lock().unwrap());
+        let mut payload = Payload::new();
+
+        for target in
+            targets.chunks_exact(4).map(|atom| u32::from_ne_bytes(atom.try_into().unwrap()))
+        {
+            let Ok(mime_type) =
+                String::from_utf8(self.connection.get_atom_name(target)?.reply()?.name)
+            else {
+                warn!(target, "Leaving out a target whose name isn't UTF-8");
+                continue;
+            };
+
+            if is_meta_target(&mime_type) {
+                continue;
+            }
+
+            let limit = limits.limit(&mime_type);
+            if let Some(content) = self.convert(selection, target, &mime_type, time, limit)? {
+                payload.insert(mime_type, content);
+            }
+        }
+
+        Ok(payload)
+    }
+
+    /// How long the selection owner gets to answer each step of a conversion
+    fn timeout(&self) -> Duration {
+        self.config.lock().unwrap().timeout_rate()
+    }
+
+    /// Asks the selection owner for `target` and waits for the result to land on our window.
+    ///
+    /// Anything larger than `limit` bytes is dropped. Answers to conversions we already gave up
+    /// on are skipped so they can't be taken for this one.
+    fn convert(
+        &mut self,
+        selection: Atom,
+        target: Atom,
+        mime_type: &str,
+        time: Timestamp,
+        limit: usize,
+    ) -> Result<Option<Vec<u8>>> {
+        let (window, atoms) = (self.window, self.atoms);
+        self.connection
+            .convert_selection(window, selection, target, atoms.property, time)?;
+        self.connection.flush()?;
+
+        let deadline = Instant::now() + self.timeout();
+        let notified = self.wait_for(deadline, |event| match event {
+            Event::SelectionNotify(notify) => answer(notify, window, selection, target),
+            _ => None,
+        })?;
+
+        if notified != Some(true) {
+            return Ok(None);
+        }
+
+        let reply = self
+            .connection
+            .get_property(true, window, atoms.property, AtomEnum::ANY, 0, u32::MAX)?
+            .reply()?;
+
+        if reply.type_ == atoms.incr {
+            return self.read_incr(mime_type, limit);
+        }
+
+        if reply.value.len() > limit {
+            warn!(
+                mime_type,
+                limit, "Leaving out a representation over the size limit"
+            );
+            return Ok(None);
+        }
+        Ok((!reply.value.is_empty()).then_some(reply.value))
+    }
+
+    /// Large selections are sent in chunks, each one announced by a property change.
+    ///
+    /// The transfer ends with a zero length chunk.
+    fn read_incr(&mut self, mime_type: &str, limit: usize) -> Result<Option<Vec<u8>>> {
+        let (window, atoms) = (self.window, self.atoms);
+        let mut content = Vec::<u8>::new();
+        let mut oversized = false;
+
+        loop {
+            let deadline = Instant::now() + self.timeout();
+            let new_value = self.wait_for(deadline, |event| match event {
+                Event::PropertyNotify(notify)
+                    if notify.window == window
+                        && notify.atom == atoms.property
+                        && notify.state == Property::NEW_VALUE =>
+                    Some(()),
+                _ => None,
+            })?;
+
+            if new_value.is_none() {
+                return Ok(None);
+            }
+
+            let chunk = self
+                .connection
+                .get_property(true, window, atoms.property, AtomEnum::ANY, 0, u32::MAX)?
+                .reply()?
+                .value;
+
+            if chunk.is_empty() {
+                if oversized {
+                    warn!(
+                        mime_type,
+                        limit, "Leaving out a representation over the size limit"
+                    );
+                }
+                return Ok((!oversized && !content.is_empty()).then_some(content));
+            }
+
+            // Keep draining so the owner finishes cleanly but don't hold on to the bytes
+            oversized |= content.len() + chunk.len() > limit;
+            match oversized {
+                true => content.clear(),
+                false => content.extend(chunk),
+            }
+        }
+    }
+
+    /// Waits until `matches` accepts an event, queueing any owner changes seen on the way.
+    fn wait_for<T>(
+        &mut self,
+        deadline: Instant,
+        matches: impl Fn(&Event) -> Option<T>,
+    ) -> Result<Option<T>> {
+        while Instant::now() < deadline {
+            let Some(event) = self.connection.poll_for_event()? else {
+                thread::sleep(Duration::from_millis(5));
+                continue;
+            };
+
+            if let Some(found) = matches(&event) {
+                return Ok(Some(found));
+            }
+
+            if let Event::XfixesSelectionNotify(notify) = event {
+                self.pending.push_back(notify);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Whether the owner converted `selection` to `target` for `window`, if `notify` is about that
+/// conversion at all
+fn answer(
+    notify: &SelectionNotifyEvent,
+    window: Window,
+    selection: Atom,
+    target: Atom,
+) -> Option<bool> {
+    (notify.requestor == window && notify.selection == selection && notify.target == target)
+        .then_some(notify.property != NONE)
+}
+
+fn intern(connection: &RustConnection, name: &[u8]) -> Result<Atom> {
+    Ok(connection.intern_atom(false, name)?.reply()?.atom)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use tokio::{sync::mpsc, time};
+    use x11_clipboard::Clipboard as X11Clipboard;
+
+    use super::*;
+
+    #[test]
+    fn it_skips_answers_to_other_conversions() {
+        let notify = SelectionNotifyEvent {
+            requestor: 1,
+            selection: 2,
+            target: 3,
+            property: 4,
+            ..Default::default()
+        };
+
+        assert_eq!(answer(&notify, 1, 2, 3), Some(true));
+        assert_eq!(
+            answer(
+                &SelectionNotifyEvent {
+                    property: NONE,
+                    ..notify
+                },
+                1,
+                2,
+                3
+            ),
+            Some(false)
+        );
+        // Late for a target that timed out
+        assert_eq!(answer(&notify, 1, 2, 5), None);
+        assert_eq!(answer(&notify, 9, 2, 3), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an X server, run with `xvfb-run cargo test -- --ignored`"]
+    async fn it_captures_owner_changes() -> Result<()> {
+        let watcher = X11SelectionWatcher::connect(
+            &[Selection::Clipboard],
+            Arc::new(Mutex::new(Config::default())),
+        )?;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        thread::spawn(move || watcher.run(sender));
+
+        let owner = X11Clipboard::new()?;
+        owner.store(
+            owner.setter.atoms.clipboard,
+            owner.setter.atoms.utf8_string,
+            b"asdf".to_vec(),
+        )?;
+
+        let clip = time::timeout(Duration::from_secs(3), receiver.recv()).await?.unwrap();
+
+        assert_eq!(clip.selection, Selection::Clipboard);
+        assert_eq!(
+            clip.get("UTF8_STRING"),
+            Some(&b"asdf"[..])
+        );
+        Ok(())
+    }
+}
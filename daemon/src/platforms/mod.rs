@@ -1,7 +1,12 @@
+use std::sync::{Arc, Mutex};
+
 use anyhow::Result;
-use genawaiter::Generator;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
-use crate::database::ClipEntry;
+use crate::{
+    database::{ClipEntry, Payload, Selection},
+    utils::config::Config,
+};
 
 #[cfg(target_os = "linux")]
 mod linux;
@@ -27,19 +32,37 @@ pub fn get_active_window() -> Option<String> {
     }
 }
 
-pub async fn listen_for_clips() -> Result<Box<dyn Generator<Yield = ClipEntry, Return = ()>>> {
+/// `config` is read as clips arrive so the polling and timeout rates can change while running
+pub fn listen_for_clips(
+    selections: Vec<Selection>,
+    config: Arc<Mutex<Config>>,
+) -> Result<UnboundedReceiverStream<ClipEntry>> {
     #[cfg(target_os = "linux")]
     {
-        linux::listen_for_clips().await
+        linux::listen_for_clips(selections, config)
     }
 
-    #[cfg(target_os = "windows")]
+    #[cfg(not(target_os = "linux"))]
     {
-        todo!()
+        let _ = (selections, config);
+        Err(anyhow!(
+            "Watching the clipboard is not supported on this platform"
+        ))
     }
+}
 
-    #[cfg(target_os = "macos")]
+/// Puts `payload` back into `selection` with every MIME type it was copied in
+pub fn set_clipboard(payload: &Payload, selection: Selection) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::set_clipboard(payload, selection)
+    }
+
+    #[cfg(not(target_os = "linux"))]
     {
-        todo!()
+        let _ = (payload, selection);
+        Err(anyhow!(
+            "Copying to the clipboard is not supported on this platform"
+        ))
     }
 }
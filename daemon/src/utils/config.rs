@@ -1,19 +1,22 @@
 use std::{
     collections::HashMap,
     path::Path,
-    sync::{mpsc, Arc, Mutex},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use anyhow::{anyhow, Result};
-use log::debug;
 use notify::{
     event::ModifyKind, Config as NotifyConfig, EventKind, RecommendedWatcher, RecursiveMode,
     Watcher,
 };
-use serde::{Deserialize, Serialize};
-use tokio::fs;
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::{fs, sync::mpsc};
+use tracing::{debug, warn};
+use zeroize::Zeroizing;
 
 use super::get_cache_path;
+use crate::database::Selection;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Preview {
@@ -30,42 +33,189 @@ impl Default for Preview {
     }
 }
 
+/// Rules for `[clipboard.<name>.exclude.<group>]` and `[clipboard.<name>.include.<group>]`.
+///
+/// Every entry is a regular expression, either a single one or a list of them. See
+/// [`Filters`](super::filter::Filters) for how they're applied.
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Clude {
-    pub applications: Option<String>,
-    pub patterns: Option<String>,
-    pub mime_types: Option<String>,
+    /// Matched against the title of the window the clip was copied from
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub applications: Option<Vec<String>>,
+    /// Matched against the text of the clip
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub patterns: Option<Vec<String>>,
+    /// Matched against every MIME type the clip was offered in
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub mime_types: Option<Vec<String>>,
+}
+
+/// Rules used to be a single string, configs written back then still set them that way
+fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(
+        Option::<OneOrMany>::deserialize(deserializer)?.map(|rules| match rules {
+            OneOrMany::One(rule) => vec![rule],
+            OneOrMany::Many(rules) => rules,
+        }),
+    )
+}
+
+/// Where the secret for `[general.encryption]` comes from. Whatever it is, the key itself is
+/// derived from it with Argon2.
+///
+/// ```toml
+/// [general.encryption]
+/// key = "command"
+/// command = "secret-tool lookup application clippy"
+/// ```
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "key", rename_all = "lowercase")]
+pub enum Encryption {
+    /// A passphrase in `$CLIPPY_PASSPHRASE`
+    Passphrase,
+    /// The contents of a file, ie: random bytes only readable by you
+    Keyfile { path: String },
+    /// What a command prints, ie: a lookup in a password manager or the secret service
+    Command { command: String },
+}
+
+impl Encryption {
+    pub const PASSPHRASE_VAR: &'static str = "CLIPPY_PASSPHRASE";
+
+    pub fn secret(&self) -> Result<Zeroizing<Vec<u8>>> {
+        let secret = match self {
+            Self::Passphrase => std::env::var(Self::PASSPHRASE_VAR)
+                .map_err(|_| anyhow!("The database key is missing, set ${}", Self::PASSPHRASE_VAR))?
+                .into_bytes(),
+            Self::Keyfile { path } => std::fs::read(path)
+                .map_err(|err| anyhow!("The database key is missing, can't read {path}: {err}"))?,
+            Self::Command { command } => {
+                let output = std::process::Command::new("sh").arg("-c").arg(command).output()?;
+                if !output.status.success() {
+                    return Err(anyhow!(
+                        "The database key is missing, `{command}` failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                output.stdout
+            },
+        };
+        let secret = Zeroizing::new(secret);
+        let trimmed = secret.trim_ascii_end();
+
+        if trimmed.is_empty() {
+            return Err(anyhow!("The database key is empty"));
+        }
+        Ok(Zeroizing::new(trimmed.to_vec()))
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct General {
     pub db_path: Option<String>,
+    /// Selections the daemon records. Add `primary` to also keep middle click selections.
+    pub selections: Option<Vec<Selection>>,
+    /// Encrypts clips and snippets on disk. Run `clippy encrypt` after turning this on for an
+    /// existing database
+    pub encryption: Option<Encryption>,
 }
 
 impl Default for General {
     fn default() -> Self {
         Self {
             db_path: get_cache_path("clippy", "db"),
+            selections: Some(vec![Selection::Clipboard]),
+            encryption: None,
         }
     }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Clipboard {
+    /// Amount of clips to keep in the database, the oldest are removed past it. 0 keeps every clip
     pub max_size: Option<u64>,
+    /// Remove duplicates among this many of the newest clips, keeping the newest copy. 0 removes
+    /// every duplicate
     pub keep_duplicates: Option<u64>,
+    /// Remove duplicates among this many of the oldest clips instead, keeping the oldest copy.
+    /// Takes precedence over `keep_duplicates`
     pub remove_duplicates: Option<u64>,
+    /// Seconds a sensitive clip is remembered for. They're never written to disk
+    pub sensitive_ttl: Option<u64>,
+    /// Clips older than this are removed, ie: `"30d"`. Pinned clips are always kept
+    pub max_age: Option<String>,
+    /// `max_age` for clips of a MIME type instead, `type/*` matching a whole type. ie:
+    /// `"image/*" = "1d"`
+    pub max_age_by_type: Option<HashMap<String, String>>,
+    /// Size all clips may take up together, the oldest are removed past it. ie: `"500MB"`
+    pub max_total_size: Option<String>,
+    /// Largest any representation of a clip may be, larger ones aren't stored. ie: `"20MB"`.
+    /// Without it text may take 5MB, images 50MB and anything else 10MB
+    pub max_clip_size: Option<String>,
+    /// `max_clip_size` for a MIME type instead, `type/*` matching a whole type. ie:
+    /// `"image/*" = "100MB"`
+    pub max_clip_size_by_type: Option<HashMap<String, String>>,
     pub preview: Option<HashMap<String, Preview>>,
     pub exclude: Option<HashMap<String, Clude>>,
     pub include: Option<HashMap<String, Clude>>,
 }
 
+impl Clipboard {
+    /// Fills whatever this section leaves out from the defaults
+    fn or_defaults(self) -> Self {
+        let defaults = Self::default();
+
+        Self {
+            max_size: self.max_size.or(defaults.max_size),
+            keep_duplicates: self.keep_duplicates.or(defaults.keep_duplicates),
+            remove_duplicates: self.remove_duplicates.or(defaults.remove_duplicates),
+            sensitive_ttl: self.sensitive_ttl.or(defaults.sensitive_ttl),
+            max_age: self.max_age.or(defaults.max_age),
+            max_age_by_type: self.max_age_by_type.or(defaults.max_age_by_type),
+            max_total_size: self.max_total_size.or(defaults.max_total_size),
+            max_clip_size: self.max_clip_size.or(defaults.max_clip_size),
+            max_clip_size_by_type: self.max_clip_size_by_type.or(defaults.max_clip_size_by_type),
+            preview: self.preview.or(defaults.preview),
+            exclude: self.exclude.or(defaults.exclude),
+            include: self.include.or(defaults.include),
+        }
+    }
+
+    /// Amount of duplicates in the form [`remove_duplicates`](crate::database::remove_duplicates)
+    /// expects. Negative looks at the newest clips, positive at the oldest.
+    pub fn duplicates(&self) -> i64 {
+        match (self.remove_duplicates, self.keep_duplicates) {
+            (Some(remove), _) if remove > 0 => remove as i64,
+            (_, keep) => -(keep.unwrap_or_default() as i64),
+        }
+    }
+
+    pub fn sensitive_ttl(&self) -> Duration {
+        Duration::from_secs(self.sensitive_ttl.unwrap_or_default())
+    }
+}
+
 impl Default for Clipboard {
     fn default() -> Self {
         Self {
             max_size: Some(1_000),
             keep_duplicates: Some(10),
             remove_duplicates: Some(0),
+            sensitive_ttl: Some(60),
+            max_age: None,
+            max_age_by_type: None,
+            max_total_size: None,
+            max_clip_size: None,
+            max_clip_size_by_type: None,
             preview: Some(HashMap::from([("default".to_string(), Preview::default())])),
             exclude: Some(HashMap::from([("default".to_string(), Clude::default())])),
             include: Some(HashMap::from([("default".to_string(), Clude::default())])),
@@ -86,9 +236,53 @@ impl Config {
         fs::read_to_string(path)
             .await
             .map_err(|err| anyhow!("Failed to read config file: {err}"))
-            .and_then(|content| {
-                toml::from_str(&content).map_err(|err| anyhow!("Failed to parse TOML: {err}"))
-            })
+            .and_then(|content| Self::parse(&content))
+    }
+
+    /// Blocking version of [`Config::from_file`] where a missing file just means nothing was
+    /// customised
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read config file: {err}"))
+            .and_then(|content| Self::parse(&content))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        toml::from_str(content).map_err(|err| anyhow!("Failed to parse TOML: {err}"))
+    }
+
+    pub fn db_path(&self) -> Option<String> {
+        self.general
+            .as_ref()
+            .and_then(|general| general.db_path.clone())
+            .or_else(|| General::default().db_path)
+    }
+
+    pub fn encryption(&self) -> Option<Encryption> {
+        self.general.as_ref().and_then(|general| general.encryption.clone())
+    }
+
+    /// How long the polling fallbacks wait between reads of the clipboard
+    pub fn polling_rate(&self) -> Duration {
+        Duration::from_millis(self.polling_rate.unwrap_or(100) as u64)
+    }
+
+    /// How long the clipboard owner gets to answer before a read is abandoned
+    pub fn timeout_rate(&self) -> Duration {
+        Duration::from_millis(self.timeout_rate.unwrap_or(300) as u64)
+    }
+
+    /// The `[clipboard.default]` section with anything it leaves out filled from the defaults
+    pub fn clipboard(&self) -> Clipboard {
+        self.clipboard
+            .as_ref()
+            .and_then(|sections| sections.get("default").cloned())
+            .unwrap_or_default()
+            .or_defaults()
     }
 }
 
@@ -107,34 +301,102 @@ impl Default for Config {
 }
 
 pub async fn watch_config(path: String, config: Arc<Mutex<Config>>) -> Result<()> {
-    let (tx, rx) = mpsc::channel();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let path = Path::new(&path);
+    let parent = path.parent().ok_or_else(|| anyhow!("Config path has no parent"))?;
 
-    RecommendedWatcher::new(
+    // Editors usually save by replacing the file so the directory is watched rather than the file
+    let mut watcher = RecommendedWatcher::new(
         move |res| {
             if let Ok(event) = res {
                 let _ = tx.send(event);
             }
         },
         NotifyConfig::default(),
-    )
-    .expect("Failed to create watcher")
-    .watch(Path::new(&path), RecursiveMode::NonRecursive)
-    .expect("Failed to watch file");
+    )?;
+    watcher.watch(parent, RecursiveMode::NonRecursive)?;
 
-    while let Ok(event) = rx.recv() {
-        if let EventKind::Modify(ModifyKind::Data(_)) = event.kind {
-            debug!("Config file changed, reloading...");
+    while let Some(event) = rx.recv().await {
+        let changed = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_))
+        );
 
-            let new_config = Config::from_file(Path::new(&path))
-                .await
-                .map_err(|e| anyhow!("Found malformed config format. {e}"))?;
-            let mut config_guard = config.lock().unwrap();
+        if !changed || !event.paths.iter().any(|changed| changed == path) {
+            continue;
+        }
+
+        debug!("Config file changed, reloading...");
 
-            *config_guard = new_config;
+        // Keep running on the previous config until the file is fixed
+        match Config::from_file(path).await {
+            Ok(new_config) => {
+                let mut config_guard = config.lock().unwrap();
+                *config_guard = new_config;
 
-            debug!("Config updated: {:?}", *config_guard);
+                debug!("Config updated: {:?}", *config_guard);
+            },
+            Err(e) => warn!("Found malformed config format. {e}"),
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_fills_missing_settings() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+            polling_rate = 50
+
+            [general.encryption]
+            key = "keyfile"
+            path = "/run/user/1000/clippy.key"
+
+            [clipboard.default]
+            max_size = 20
+            remove_duplicates = 3
+            "#,
+        )?;
+        let clipboard = config.clipboard();
+
+        assert_eq!(config.polling_rate(), Duration::from_millis(50));
+        assert_eq!(config.timeout_rate(), Duration::from_millis(300));
+        assert_eq!(config.db_path(), General::default().db_path);
+        assert_eq!(
+            config.encryption(),
+            Some(Encryption::Keyfile {
+                path: "/run/user/1000/clippy.key".to_string()
+            })
+        );
+        assert_eq!(clipboard.max_size, Some(20));
+        assert_eq!(clipboard.keep_duplicates, Some(10));
+        assert_eq!(clipboard.duplicates(), 3);
+        assert_eq!(Config::default().clipboard().duplicates(), -10);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_single_rules() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+            [clipboard.default.exclude.passwords]
+            applications = "KeePassXC"
+            mime_types = ["x-kde-passwordManagerHint", "application/x-secret"]
+            "#,
+        )?;
+        let exclude = config.clipboard().exclude.unwrap();
+        let passwords = &exclude["passwords"];
+
+        assert_eq!(passwords.applications, Some(vec!["KeePassXC".to_string()]));
+        assert_eq!(passwords.mime_types.as_ref().map(Vec::len), Some(2));
+        assert_eq!(passwords.patterns, None);
+        Ok(())
+    }
+}
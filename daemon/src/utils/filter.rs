@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use derive_more::Display;
+use regex::{Regex, RegexBuilder};
+
+use super::config::{Clipboard, Clude};
+use crate::database::ClipEntry;
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum List {
+    #[display("include")]
+    Include,
+    #[display("exclude")]
+    Exclude,
+}
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    #[display("applications")]
+    Applications,
+    #[display("patterns")]
+    Patterns,
+    #[display("mime_types")]
+    MimeTypes,
+}
+
+/// Where a rule lives in the config, ie: `exclude.passwords.applications = "KeePassXC"`
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+#[display("{list}.{group}.{field} = \"{pattern}\"")]
+pub struct Rule {
+    pub list: List,
+    pub group: String,
+    pub field: Field,
+    pub pattern: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// No rule matched
+    Store,
+    /// An include rule matched, overriding any exclude rule that may also have matched
+    Included(Rule),
+    Excluded(Rule),
+}
+
+impl Decision {
+    pub fn is_stored(&self) -> bool {
+        !matches!(self, Self::Excluded(_))
+    }
+}
+
+/// Compiled include and exclude rules of a clipboard section.
+///
+/// A clip matching any exclude rule is dropped unless it also matches an include rule, so include
+/// rules carve out exceptions rather than limit what gets stored. Application and MIME type rules
+/// are case insensitive. The default has no rules and stores every clip.
+#[derive(Default)]
+pub struct Filters {
+    include: Vec<(Rule, Regex)>,
+    exclude: Vec<(Rule, Regex)>,
+}
+
+impl Filters {
+    /// Fails on the first rule that isn't a valid regular expression
+    pub fn new(clipboard: &Clipboard) -> Result<Self> {
+        Ok(Self {
+            include: compile(List::Include, clipboard.include.as_ref())?,
+            exclude: compile(List::Exclude, clipboard.exclude.as_ref())?,
+        })
+    }
+
+    pub fn check(&self, entry: &ClipEntry) -> Decision {
+        if let Some(rule) = first_match(&self.include, entry) {
+            return Decision::Included(rule);
+        }
+
+        match first_match(&self.exclude, entry) {
+            Some(rule) => Decision::Excluded(rule),
+            None => Decision::Store,
+        }
+    }
+}
+
+fn compile(list: List, groups: Option<&HashMap<String, Clude>>) -> Result<Vec<(Rule, Regex)>> {
+    let mut rules = Vec::new();
+    // Sorted so the rule reported for a clip doesn't change between runs
+    let mut groups = groups.into_iter().flatten().collect::<Vec<_>>();
+    groups.sort_by_key(|(group, _)| *group);
+
+    for (group, clude) in groups {
+        for (field, patterns) in [
+            (Field::Applications, &clude.applications),
+            (Field::Patterns, &clude.patterns),
+            (Field::MimeTypes, &clude.mime_types),
+        ] {
+            for pattern in patterns.iter().flatten() {
+                let rule = Rule {
+                    list,
+                    group: group.clone(),
+                    field,
+                    pattern: pattern.clone(),
+                };
+                let regex = RegexBuilder::new(pattern)
+                    .case_insensitive(field != Field::Patterns)
+                    .build()
+                    .map_err(|e| anyhow!("Invalid rule {rule}: {e}"))?;
+
+                rules.push((rule, regex));
+            }
+        }
+    }
+
+    Ok(rules)
+}
+
+fn first_match(rules: &[(Rule, Regex)], entry: &ClipEntry) -> Option<Rule> {
+    let text = entry.text().ok();
+
+    rules
+        .iter()
+        .find(|(rule, regex)| match rule.field {
+            Field::Applications =>
+                entry.application.as_deref().is_some_and(|app| regex.is_match(app)),
+            Field::Patterns => text.as_deref().is_some_and(|text| regex.is_match(text)),
+            Field::MimeTypes => entry.mime_types().any(|mime_type| regex.is_match(mime_type)),
+        })
+        .map(|(rule, _)| rule.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::utils::config::Config;
+
+    fn filters() -> Filters {
+        let config: Config = toml::from_str(
+            r#"
+            [clipboard.default.exclude.passwords]
+            applications = ["keepassxc", "Bitwarden"]
+            mime_types = ["x-kde-passwordManagerHint"]
+
+            [clipboard.default.exclude.secrets]
+            patterns = ["^ghp_[A-Za-z0-9]{36}$"]
+
+            [clipboard.default.include.urls]
+            patterns = ["^https?://"]
+            "#,
+        )
+        .unwrap();
+
+        Filters::new(&config.clipboard()).unwrap()
+    }
+
+    fn clip(text: &str, application: Option<&str>) -> ClipEntry {
+        let mut entry = ClipEntry::new(text.as_bytes());
+        entry.application = application.map(str::to_string);
+        entry
+    }
+
+    #[test]
+    fn it_excludes_matching_clips() {
+        let filters = filters();
+        let token = format!("ghp_{}", "a".repeat(36));
+
+        assert_eq!(filters.check(&clip("hello", None)), Decision::Store);
+        assert_eq!(
+            filters.check(&clip("hello", Some("Firefox"))),
+            Decision::Store
+        );
+
+        let Decision::Excluded(rule) = filters.check(&clip("hunter2", Some("KeePassXC"))) else {
+            panic!("Expected the clip to be excluded");
+        };
+        assert_eq!(
+            rule.to_string(),
+            r#"exclude.passwords.applications = "keepassxc""#
+        );
+
+        let Decision::Excluded(rule) = filters.check(&clip(&token, None)) else {
+            panic!("Expected the clip to be excluded");
+        };
+        assert_eq!(rule.group, "secrets");
+    }
+
+    #[test]
+    fn it_prefers_include_rules() {
+        let decision = filters().check(&clip("https://example.com", Some("KeePassXC")));
+
+        assert!(decision.is_stored());
+        assert!(matches!(decision, Decision::Included(rule) if rule.group == "urls"));
+    }
+
+    #[test]
+    fn it_rejects_invalid_patterns() {
+        let clipboard = Clipboard {
+            exclude: Some(HashMap::from([(
+                "broken".to_string(),
+                Clude {
+                    patterns: Some(vec!["(".to_string()]),
+                    ..Default::default()
+                },
+            )])),
+            ..Default::default()
+        };
+
+        assert!(Filters::new(&clipboard).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Result};
+use tracing::warn;
+
+use super::{
+    config::{Clipboard, Config},
+    mime::{matches_type, size_limit},
+};
+use crate::database::ClipEntry;
+
+/// How large each representation of a clip may be, from `max_clip_size` and
+/// `max_clip_size_by_type` of a clipboard section
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SizeLimits {
+    pub max_clip_size: Option<usize>,
+    /// By MIME type pattern, exact types first so they win over `type/*`
+    pub max_clip_size_by_type: Vec<(String, usize)>,
+}
+
+/// A representation left out for being over its limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Oversized {
+    pub mime_type: String,
+    pub size: usize,
+    pub limit: usize,
+}
+
+impl SizeLimits {
+    pub fn new(clipboard: &Clipboard) -> Result<Self> {
+        let parse = |size: &str| {
+            parse_size::parse_size(size)
+                .map(|size| size as usize)
+                .map_err(|err| anyhow!("Invalid size `{size}`: {err}"))
+        };
+        let mut max_clip_size_by_type = clipboard
+            .max_clip_size_by_type
+            .iter()
+            .flatten()
+            .map(|(pattern, size)| Ok((pattern.clone(), parse(size)?)))
+            .collect::<Result<Vec<_>>>()?;
+        max_clip_size_by_type
+            .sort_by_key(|(pattern, _)| (pattern.ends_with("/*"), pattern.clone()));
+
+        Ok(Self {
+            max_clip_size: clipboard.max_clip_size.as_deref().map(parse).transpose()?,
+            max_clip_size_by_type,
+        })
+    }
+
+    /// Limits of the `[clipboard.default]` section of `config`, the built in ones while it's
+    /// invalid
+    pub fn current(config: &Config) -> Self {
+        Self::new(&config.clipboard()).unwrap_or_else(|e| {
+            warn!("{e}. Using the default size limits until the config is fixed");
+            Self::default()
+        })
+    }
+
+    /// Largest a `mime_type` representation may be, in bytes
+    pub fn limit(&self, mime_type: &str) -> usize {
+        self.max_clip_size_by_type
+            .iter()
+            .find(|(pattern, _)| matches_type(pattern, mime_type))
+            .map(|(_, limit)| *limit)
+            .or(self.max_clip_size)
+            .unwrap_or_else(|| size_limit(mime_type))
+    }
+
+    /// Takes the representations over their limit out of `entry`. Nothing is left of a clip that
+    /// only had oversized ones
+    pub fn enforce(&self, entry: &mut ClipEntry) -> Vec<Oversized> {
+        let oversized = entry
+            .payload
+            .iter()
+            .filter_map(|(mime_type, bytes)| {
+                let limit = self.limit(mime_type);
+                (bytes.len() > limit).then(|| Oversized {
+                    mime_type: mime_type.clone(),
+                    size: bytes.len(),
+                    limit,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for Oversized { mime_type, .. } in &oversized {
+            entry.payload.remove(mime_type);
+        }
+        oversized
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::database::{Payload, Selection};
+
+    #[test]
+    fn it_limits_representations() -> Result<()> {
+        let limits = SizeLimits::new(&Clipboard {
+            max_clip_size: Some("1 KiB".to_string()),
+            max_clip_size_by_type: Some(HashMap::from([
+                ("image/*".to_string(), "4 KiB".to_string()),
+                ("image/bmp".to_string(), "10B".to_string()),
+            ])),
+            ..Default::default()
+        })?;
+
+        assert_eq!(limits.limit("image/png"), 4096);
+        assert_eq!(limits.limit("image/bmp"), 10);
+        assert_eq!(limits.limit("text/html"), 1024);
+        assert_eq!(
+            SizeLimits::default().limit("image/png"),
+            size_limit("image/png")
+        );
+
+        let mut entry = ClipEntry::with_payload(
+            Payload::from([
+                ("image/png".to_string(), vec![0; 2048]),
+                ("image/bmp".to_string(), vec![0; 2048]),
+            ]),
+            Selection::Clipboard,
+        );
+        assert_eq!(
+            limits.enforce(&mut entry),
+            [Oversized {
+                mime_type: "image/bmp".to_string(),
+                size: 2048,
+                limit: 10
+            }]
+        );
+        assert_eq!(entry.mime_types().collect::<Vec<_>>(), ["image/png"]);
+
+        assert!(SizeLimits::new(&Clipboard {
+            max_clip_size: Some("huge".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! Tracing setup shared by `clippy` and `clippy_daemon`.
+//!
+//! Events go to `stderr` and, for the daemon, to a log file rotated daily. `-v` raises the level of
+//! clippy's own events while dependencies only report warnings, [`LOG_ENV`] replaces both.
+
+use std::{
+    fs::create_dir_all,
+    io::{stderr, IsTerminal},
+};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    fmt::{self, MakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+};
+
+use super::get_cache_path;
+
+/// Filter directives taking over from `-v`, ie: `clippy_daemon::database=trace`
+pub const LOG_ENV: &str = "CLIPPY_LOG";
+/// Older log files are deleted as new ones are started
+const LOG_FILES_KEPT: usize = 7;
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per event, with the spans it happened in
+    Json,
+}
+
+/// Where the daemon keeps its log files
+pub fn log_dir() -> Option<String> {
+    get_cache_path("clippy", "logs")
+}
+
+/// Installs the global subscriber. With `log_file`, events are also written to files named after
+/// it in [`log_dir`], for as long as the returned guard is kept
+pub fn init(
+    verbosity: u8,
+    format: LogFormat,
+    log_file: Option<&str>,
+) -> Result<Option<WorkerGuard>> {
+    let filter = match std::env::var(LOG_ENV) {
+        Ok(directives) => EnvFilter::try_new(&directives)
+            .map_err(|e| anyhow!("Invalid {LOG_ENV} `{directives}`: {e}"))?,
+        Err(_) => EnvFilter::new(directives(verbosity)),
+    };
+    let mut layers = vec![layer(format, stderr, stderr().is_terminal())];
+
+    let guard = match log_file {
+        Some(name) => {
+            let dir = log_dir().ok_or_else(|| anyhow!("Unable to determine log directory"))?;
+            create_dir_all(&dir)?;
+            let appender = RollingFileAppender::builder()
+                .rotation(Rotation::DAILY)
+                .filename_prefix(name)
+                .filename_suffix("log")
+                .max_log_files(LOG_FILES_KEPT)
+                .build(dir)?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            layers.push(layer(format, writer, false));
+            Some(guard)
+        },
+        None => None,
+    };
+
+    tracing_subscriber::registry().with(layers).with(filter).try_init()?;
+    Ok(guard)
+}
+
+/// Level of clippy's own events for how many times `-v` was passed
+fn directives(verbosity: u8) -> String {
+    let level = match verbosity {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+
+    format!("warn,clippy={level},clippy_daemon={level}")
+}
+
+fn layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().with_current_span(true).with_span_list(true).boxed(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_raises_clippy_levels() {
+        assert_eq!(directives(0), "warn,clippy=warn,clippy_daemon=warn");
+        assert_eq!(directives(2), "warn,clippy=debug,clippy_daemon=debug");
+        assert_eq!(directives(9), "warn,clippy=trace,clippy_daemon=trace");
+
+        for verbosity in 0..4 {
+            assert!(EnvFilter::try_new(directives(verbosity)).is_ok());
+        }
+    }
+}
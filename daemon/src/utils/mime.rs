@@ -0,0 +1,92 @@
+pub const TEXT_MIME_TYPE: &str = "text/plain;charset=utf-8";
+pub const BINARY_MIME_TYPE: &str = "application/octet-stream";
+
+/// Offered alongside a password by KDE's password managers, with `secret` as its content
+pub const PASSWORD_MANAGER_HINT: &str = "x-kde-passwordManagerHint";
+
+/// Text representations in order of preference when a clip offers more than one.
+pub const TEXT_MIME_TYPES: [&str; 5] =
+    [TEXT_MIME_TYPE, "text/plain", "UTF8_STRING", "STRING", "TEXT"];
+
+/// Targets that describe the selection itself rather than its contents.
+const META_TARGETS: [&str; 6] =
+    ["TARGETS", "MULTIPLE", "TIMESTAMP", "SAVE_TARGETS", "DELETE", "INCR"];
+
+const MAX_TEXT_SIZE: usize = 5e6 as usize;
+const MAX_IMAGE_SIZE: usize = 5e7 as usize;
+const MAX_OTHER_SIZE: usize = 1e7 as usize;
+
+pub fn is_text(mime_type: &str) -> bool {
+    TEXT_MIME_TYPES.contains(&mime_type) || mime_type.starts_with("text/")
+}
+
+pub fn is_image(mime_type: &str) -> bool {
+    mime_type.starts_with("image/")
+}
+
+pub fn is_meta_target(mime_type: &str) -> bool {
+    META_TARGETS.contains(&mime_type)
+}
+
+/// Whether `mime_type` is `pattern`, or of its type when `pattern` is `type/*`. The legacy X11
+/// text targets count as `text/*`
+pub fn matches_type(pattern: &str, mime_type: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some("text") => is_text(mime_type),
+        Some(kind) => mime_type.split('/').next() == Some(kind),
+        None => pattern == mime_type,
+    }
+}
+
+/// Largest payload in bytes captured for `mime_type` unless the config says otherwise, see
+/// [`SizeLimits`](super::limits::SizeLimits)
+pub fn size_limit(mime_type: &str) -> usize {
+    match mime_type {
+        m if is_text(m) => MAX_TEXT_SIZE,
+        m if is_image(m) => MAX_IMAGE_SIZE,
+        _ => MAX_OTHER_SIZE,
+    }
+}
+
+/// Best guess at the MIME type of raw bytes handed to us without one (stdin, v1 clips).
+pub fn guess_mime_type(payload: &[u8]) -> String {
+    if std::str::from_utf8(payload).is_ok() {
+        return TEXT_MIME_TYPE.to_string();
+    }
+
+    image::guess_format(payload)
+        .map(|format| format.to_mime_type())
+        .unwrap_or(BINARY_MIME_TYPE)
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn it_guesses_mime_types() {
+        assert_eq!(guess_mime_type(b"hello"), TEXT_MIME_TYPE);
+        assert_eq!(guess_mime_type(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(guess_mime_type(&[0xff, 0xfe, 0x00]), BINARY_MIME_TYPE);
+    }
+
+    #[test]
+    fn it_matches_type_patterns() {
+        assert!(matches_type("image/png", "image/png"));
+        assert!(!matches_type("image/png", "image/jpeg"));
+        assert!(matches_type("image/*", "image/jpeg"));
+        assert!(matches_type("text/*", "UTF8_STRING"));
+        assert!(!matches_type("image/*", "text/plain"));
+    }
+
+    #[test]
+    fn it_limits_by_type() {
+        assert_eq!(size_limit("UTF8_STRING"), MAX_TEXT_SIZE);
+        assert_eq!(size_limit("text/html"), MAX_TEXT_SIZE);
+        assert_eq!(size_limit("image/png"), MAX_IMAGE_SIZE);
+        assert_eq!(size_limit("application/x-kde-cutselection"), MAX_OTHER_SIZE);
+    }
+}
@@ -1,5 +1,9 @@
-pub mod async_helpers;
 pub mod config;
+pub mod filter;
+pub mod limits;
+pub mod logging;
+pub mod mime;
+pub mod pidfile;
 #[allow(clippy::module_inception)]
 pub mod utils;
 
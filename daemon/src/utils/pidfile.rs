@@ -0,0 +1,96 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, Write},
+};
+
+use anyhow::{anyhow, Result};
+
+use super::get_runtime_path;
+
+pub fn pid_path() -> Option<String> {
+    get_runtime_path("clippy", "clippy.pid")
+}
+
+/// Holds the lock that keeps a second daemon from starting, released once dropped or when the
+/// process dies. The file holds the pid of the daemon for `clippy daemon` to find
+pub struct PidFile {
+    _file: File,
+}
+
+impl PidFile {
+    /// Fails with the pid of the other daemon if one already holds the lock
+    pub fn acquire(path: &str) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                let pid = read_pid(&mut file).map_or("unknown".to_string(), |pid| pid.to_string());
+                return Err(anyhow!("clippy_daemon is already running with pid {pid}"));
+            },
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        file.flush()?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Pid of the daemon holding the lock at `path`, if one is running
+pub fn running_pid(path: &str) -> Result<Option<u32>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    match file.try_lock() {
+        // Left behind by a daemon that's gone, the lock went with it
+        Ok(()) => Ok(None),
+        // The pid is missing for a moment while a daemon starts, it counts as not running yet
+        Err(TryLockError::WouldBlock) => Ok(read_pid(&mut file)),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut pid = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut pid).ok()?;
+
+    pid.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use shortcut_assert_fs::TmpFs;
+
+    use super::*;
+
+    #[test]
+    fn it_allows_a_single_daemon() -> Result<()> {
+        let tf = TmpFs::new()?;
+        let path = tf.path("clippy.pid");
+
+        assert_eq!(running_pid(path.as_str())?, None);
+
+        let pid_file = PidFile::acquire(path.as_str())?;
+        assert_eq!(running_pid(path.as_str())?, Some(std::process::id()));
+        assert!(PidFile::acquire(path.as_str())
+            .is_err_and(|e| e.to_string().contains(&std::process::id().to_string())));
+
+        drop(pid_file);
+        assert_eq!(running_pid(path.as_str())?, None);
+        assert!(PidFile::acquire(path.as_str()).is_ok());
+        Ok(())
+    }
+}
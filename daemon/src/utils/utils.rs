@@ -3,7 +3,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use dirs::{cache_dir, config_dir};
+use dirs::{cache_dir, config_dir, runtime_dir};
 
 fn get_path(base_path: Option<PathBuf>, path: &str, name: &str) -> Option<String> {
     let parent = Path::join(base_path?.as_path(), Path::new(path));
@@ -19,6 +19,11 @@ pub fn get_cache_path(path: &str, name: &str) -> Option<String> {
     get_path(cache_dir(), path, name)
 }
 
+/// Sockets and other per session files. Falls back to the cache directory without `XDG_RUNTIME_DIR`
+pub fn get_runtime_path(path: &str, name: &str) -> Option<String> {
+    get_path(runtime_dir().or_else(cache_dir), path, name)
+}
+
 use rand::{distributions::Alphanumeric, Rng};
 
 pub fn random_str(length: usize) -> String {